@@ -1,6 +1,7 @@
 use core::cell::RefCell;
 
 use async_std::sync::Arc;
+use protocol::RoomName;
 use web_sys::Event;
 use web_sys::{HtmlButtonElement, HtmlInputElement};
 
@@ -9,31 +10,42 @@ use crate::{ClosureCell1, Mode, Receiver, Sender};
 #[derive(Debug)]
 pub struct App {
     server_address_input: HtmlInputElement,
+    room_name_input: HtmlInputElement,
     start_sender_button: HtmlButtonElement,
     start_receiver_button: HtmlButtonElement,
+    stop_button: HtmlButtonElement,
     start_sender_click_handler: ClosureCell1<Event>,
     start_receiver_click_handler: ClosureCell1<Event>,
+    stop_click_handler: ClosureCell1<Event>,
     mode: RefCell<Option<Mode>>,
 }
 
 impl App {
     pub fn new() -> Arc<Self> {
-        use crate::default_server_address;
         use crate::{body, ElementExt};
+        use crate::{default_room_name, default_server_address};
 
         let server_address_input: HtmlInputElement = body().add_child("input");
         server_address_input.set_value(&default_server_address());
+        let room_name_input: HtmlInputElement = body().add_child("input");
+        room_name_input.set_value(&default_room_name());
         let start_sender_button: HtmlButtonElement = body().add_child("button");
         start_sender_button.add_text("Start sender");
         let start_receiver_button: HtmlButtonElement = body().add_child("button");
         start_receiver_button.add_text("Start receiver");
+        let stop_button: HtmlButtonElement = body().add_child("button");
+        stop_button.add_text("Stop");
+        stop_button.set_disabled(true);
 
         let app = Arc::new(App {
             server_address_input,
+            room_name_input,
             start_sender_button,
             start_receiver_button,
+            stop_button,
             start_sender_click_handler: RefCell::new(None),
             start_receiver_click_handler: RefCell::new(None),
+            stop_click_handler: RefCell::new(None),
             mode: RefCell::new(None),
         });
 
@@ -61,24 +73,42 @@ impl App {
             HtmlElement::set_onclick,
             &self.start_receiver_button,
         );
+
+        init_weak_callback(
+            &self,
+            Self::on_stop_click,
+            &self.stop_click_handler,
+            HtmlElement::set_onclick,
+            &self.stop_button,
+        );
     }
 
     fn set_start_buttons_inactive(&self) {
         self.server_address_input.set_read_only(true);
+        self.room_name_input.set_read_only(true);
         self.start_sender_button.set_disabled(true);
         self.start_receiver_button.set_disabled(true);
     }
 
+    fn set_start_buttons_active(&self) {
+        self.server_address_input.set_read_only(false);
+        self.room_name_input.set_read_only(false);
+        self.start_sender_button.set_disabled(false);
+        self.start_receiver_button.set_disabled(false);
+    }
+
     fn on_start_sender_click(self: &Arc<Self>, _: Event) {
         use wasm_bindgen_futures::spawn_local;
 
         self.set_start_buttons_inactive();
         let addr = self.fix_and_get_server_address();
+        let room = self.get_room_name();
         let self_arc = Arc::clone(self);
         spawn_local(async move {
-            let sender = Sender::new(addr).await;
+            let sender = Sender::new(addr, room).await;
             let prev = self_arc.mode.replace(Some(Mode::Sender(sender)));
             assert!(prev.is_none());
+            self_arc.stop_button.set_disabled(false);
         });
     }
 
@@ -87,32 +117,54 @@ impl App {
 
         self.set_start_buttons_inactive();
         let addr = self.fix_and_get_server_address();
+        let room = self.get_room_name();
         let self_arc = Arc::clone(self);
         spawn_local(async move {
-            let receiver = Receiver::new(addr).await;
+            let receiver = Receiver::new(addr, room).await;
             let prev = self_arc.mode.replace(Some(Mode::Receiver(receiver)));
             assert!(prev.is_none());
+            self_arc.stop_button.set_disabled(false);
         });
     }
 
+    fn on_stop_click(self: &Arc<Self>, _: Event) {
+        // Dropping the sender or receiver says goodbye to the server and releases its resources.
+        let _: Option<Mode> = self.mode.replace(None);
+        self.stop_button.set_disabled(true);
+        self.set_start_buttons_active();
+    }
+
     fn fix_and_get_server_address(&self) -> String {
+        use web_sys::window;
+
         let addr = self.server_address_input.value();
         if addr.starts_with("ws://") || addr.starts_with("wss://") {
             addr
         } else {
-            let addr = format!("ws://{}", &addr);
+            // Browsers block `ws://` on pages served over HTTPS.
+            let protocol = window().and_then(|window| window.location().protocol().ok());
+            let is_https = protocol.as_deref() == Some("https:");
+            let scheme = if is_https { "wss" } else { "ws" };
+            let addr = format!("{}://{}", scheme, &addr);
             self.server_address_input.set_value(&addr);
             addr
         }
     }
+
+    fn get_room_name(&self) -> RoomName {
+        RoomName(self.room_name_input.value())
+    }
 }
 
 impl Drop for App {
     fn drop(&mut self) {
         self.start_sender_button.set_onclick(None);
         self.start_receiver_button.set_onclick(None);
+        self.stop_button.set_onclick(None);
         self.server_address_input.remove();
+        self.room_name_input.remove();
         self.start_sender_button.remove();
         self.start_receiver_button.remove();
+        self.stop_button.remove();
     }
 }
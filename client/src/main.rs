@@ -27,12 +27,12 @@ mod websocket_utils;
 use app::App;
 use html::{body, navigator, ElementExt};
 use mode::Mode;
-use params::default_server_address;
+use params::{default_room_name, default_server_address};
 use receiver::Receiver;
 use sender::Sender;
 use weak_callback::{init_weak_callback, ClosureCell1};
 use webrtc_utils::{on_icecandidate, on_remote_icecandidate, RtcConfigurationExt};
-use websocket_utils::{ParseWebSocketMessage, SendWebSocketMessage};
+use websocket_utils::{handshake, new_websocket, ParseWebSocketMessage, SendWebSocketMessage};
 
 fn main() {
     console_error_panic_hook::set_once();
@@ -1,5 +1,7 @@
+use protocol::Codec;
+
 pub fn default_server_address() -> String {
-    const FALLBACK_ADDRESS: &str = "ws://localhost:9010";
+    const FALLBACK_ADDRESS: &str = "ws://localhost:9010/ws";
 
     use js_sys::{JsString, Reflect};
     use wasm_bindgen::{JsCast, JsValue};
@@ -11,3 +13,19 @@ pub fn default_server_address() -> String {
         .map(|addr: JsString| addr.into())
         .unwrap_or(FALLBACK_ADDRESS.to_owned())
 }
+
+pub fn default_room_name() -> String {
+    protocol::DEFAULT_ROOM.to_owned()
+}
+
+pub fn preferred_codec() -> Codec {
+    use js_sys::{JsString, Reflect};
+    use wasm_bindgen::{JsCast, JsValue};
+    use web_sys::window;
+
+    window()
+        .and_then(|window| Reflect::get(&window, &JsValue::from_str("codec")).ok())
+        .and_then(|codec| codec.dyn_into().ok())
+        .and_then(|codec: JsString| Codec::from_subprotocol(&String::from(codec)))
+        .unwrap_or_default()
+}
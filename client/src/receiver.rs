@@ -1,11 +1,12 @@
 use core::cell::RefCell;
 
 use async_std::sync::Arc;
-use protocol::{IceCandidate, SessionDescription};
+use protocol::{ErrorCode, IceCandidate, RoomName, ServerMessage, SessionDescription};
 use wasm_bindgen::closure::Closure;
 use web_sys::{
-    Event, HtmlTextAreaElement, HtmlVideoElement, MediaStream, MessageEvent, RtcDataChannel,
-    RtcDataChannelEvent, RtcPeerConnection, RtcPeerConnectionIceEvent, RtcTrackEvent, WebSocket,
+    CloseEvent, Event, HtmlDivElement, HtmlTextAreaElement, HtmlVideoElement, MediaStream,
+    MessageEvent, RtcDataChannel, RtcDataChannelEvent, RtcPeerConnection,
+    RtcPeerConnectionIceEvent, RtcTrackEvent, WebSocket,
 };
 
 use crate::ClosureCell1;
@@ -14,11 +15,13 @@ use crate::ClosureCell1;
 pub struct Receiver {
     websocket: WebSocket,
     webrtc: RtcPeerConnection,
+    status: HtmlDivElement,
     videos: RefCell<Vec<HtmlVideoElement>>,
     text_areas: RefCell<Vec<HtmlTextAreaElement>>,
     data_channels: RefCell<Vec<RtcDataChannel>>,
     media_streams: RefCell<Vec<MediaStream>>,
     message_handler: ClosureCell1<MessageEvent>,
+    close_handler: ClosureCell1<CloseEvent>,
     icecandidate_handler: ClosureCell1<RtcPeerConnectionIceEvent>,
     negotiationneeded_handler: ClosureCell1<Event>,
     iceconnectionstatechange_handler: ClosureCell1<Event>,
@@ -30,18 +33,16 @@ pub struct Receiver {
 }
 
 impl Receiver {
-    pub async fn new(addr: String) -> Arc<Self> {
-        use crate::RtcConfigurationExt;
+    pub async fn new(addr: String, room: RoomName) -> Arc<Self> {
+        use crate::{body, new_websocket, ElementExt, RtcConfigurationExt};
         use js_sys::Promise;
         use wasm_bindgen::JsValue;
         use wasm_bindgen_futures::JsFuture;
-        use web_sys::{BinaryType, RtcConfiguration};
+        use web_sys::RtcConfiguration;
 
-        let conf = RtcConfiguration::new().with_google_stun_server();
-        let webrtc = RtcPeerConnection::new_with_configuration(&conf).unwrap();
+        let status: HtmlDivElement = body().add_child("div");
 
-        let websocket = WebSocket::new(addr.as_ref()).unwrap();
-        websocket.set_binary_type(BinaryType::Arraybuffer);
+        let websocket = new_websocket(addr.as_ref());
 
         let web_socket_opened = Promise::new(&mut |resolve, reject| {
             websocket.set_onopen(Some(&resolve));
@@ -49,14 +50,25 @@ impl Receiver {
         });
         let _: JsValue = JsFuture::from(web_socket_opened).await.unwrap();
 
+        // The ICE servers are received in the handshake, so it precedes the peer connection.
+        let handshake = crate::handshake(&websocket).await;
+        let ice_servers = match &handshake {
+            ServerMessage::Hello { ice_servers, .. } => ice_servers.as_slice(),
+            ServerMessage::Error { .. } => &[],
+        };
+        let conf = RtcConfiguration::new().with_ice_servers(ice_servers);
+        let webrtc = RtcPeerConnection::new_with_configuration(&conf).unwrap();
+
         let receiver = Arc::new(Self {
             websocket,
             webrtc,
+            status,
             videos: RefCell::new(Vec::new()),
             text_areas: RefCell::new(Vec::new()),
             data_channels: RefCell::new(Vec::new()),
             media_streams: RefCell::new(Vec::new()),
             message_handler: RefCell::new(None),
+            close_handler: RefCell::new(None),
             icecandidate_handler: RefCell::new(None),
             negotiationneeded_handler: RefCell::new(None),
             iceconnectionstatechange_handler: RefCell::new(None),
@@ -67,15 +79,18 @@ impl Receiver {
             datachannel_message_handlers: RefCell::new(Vec::new()),
         });
 
-        receiver.init().await;
+        receiver.init(handshake, room).await;
 
         receiver
     }
 
-    async fn init(self: &Arc<Self>) {
+    async fn init(self: &Arc<Self>, handshake: ServerMessage, room: RoomName) {
         use crate::init_weak_callback;
 
-        self.start_server_sender().await;
+        if !self.on_handshake(handshake) {
+            return;
+        }
+        self.start_server_sender(room).await;
 
         init_weak_callback(
             &self,
@@ -85,6 +100,14 @@ impl Receiver {
             &self.websocket,
         );
 
+        init_weak_callback(
+            &self,
+            Self::on_close,
+            &self.close_handler,
+            WebSocket::set_onclose,
+            &self.websocket,
+        );
+
         init_weak_callback(
             &self,
             Self::on_icecandidate,
@@ -142,11 +165,33 @@ impl Receiver {
         );
     }
 
-    async fn start_server_sender(self: &Arc<Self>) {
+    fn on_handshake(self: &Arc<Self>, message: ServerMessage) -> bool {
+        match message {
+            ServerMessage::Hello {
+                protocol_version,
+                capabilities,
+                ice_servers,
+            } => {
+                log::debug!(
+                    "server protocol version: {}, capabilities: {:?}, ice servers: {:?}",
+                    protocol_version,
+                    capabilities,
+                    ice_servers
+                );
+                true
+            }
+            ServerMessage::Error { code, reason } => {
+                self.on_error(code, reason);
+                false
+            }
+        }
+    }
+
+    async fn start_server_sender(self: &Arc<Self>, room: RoomName) {
         use crate::SendWebSocketMessage;
         use protocol::ClientMessage;
 
-        self.websocket.send(ClientMessage::StartSender);
+        self.websocket.send(ClientMessage::StartSender(room));
     }
 
     fn on_message(self: &Arc<Self>, ev: MessageEvent) {
@@ -168,6 +213,8 @@ impl Receiver {
                 let self_arc = Arc::clone(self);
                 spawn_local(async move { self_arc.on_remote_all_icecandidates_sent().await });
             }
+            ServerSenderMessage::Error { code, reason } => self.on_error(code, reason),
+            ServerSenderMessage::Bye => self.on_bye(),
         }
     }
 
@@ -215,6 +262,24 @@ impl Receiver {
             .send(ClientReceiverMessage::Answer(SessionDescription(sdp)));
     }
 
+    fn on_error(self: &Arc<Self>, code: ErrorCode, reason: String) {
+        log::error!("server error: {:?}: {}", code, reason);
+        self.status
+            .set_text_content(Some(&format!("Server error: {}", reason)));
+        self.webrtc.close();
+    }
+
+    fn on_bye(self: &Arc<Self>) {
+        log::info!("session closed by server");
+        self.status
+            .set_text_content(Some("Session closed by server"));
+        self.webrtc.close();
+    }
+
+    fn on_close(self: &Arc<Self>, ev: CloseEvent) {
+        log::info!("websocket closed by server: {}", ev.code());
+    }
+
     async fn on_remote_icecandidate(self: &Arc<Self>, ice_candidate: IceCandidate) {
         crate::on_remote_icecandidate(&self.webrtc, ice_candidate).await;
     }
@@ -301,15 +366,24 @@ impl Receiver {
             let media_stream: MediaStream = media_stream.dyn_into().unwrap();
             video.set_src_object(Some(&media_stream));
         }
+
+        self.videos.borrow_mut().push(video);
     }
 }
 
 impl Drop for Receiver {
     fn drop(&mut self) {
+        use crate::SendWebSocketMessage;
+        use protocol::ClientReceiverMessage;
         use wasm_bindgen::JsCast;
         use web_sys::MediaStreamTrack;
 
+        if self.websocket.ready_state() == WebSocket::OPEN {
+            self.websocket.send(ClientReceiverMessage::Bye);
+        }
+
         self.websocket.set_onmessage(None);
+        self.websocket.set_onclose(None);
         self.webrtc.set_onicecandidate(None);
         self.webrtc.set_onnegotiationneeded(None);
         self.webrtc.set_oniceconnectionstatechange(None);
@@ -339,5 +413,6 @@ impl Drop for Receiver {
         for text in self.text_areas.borrow().iter() {
             text.remove();
         }
+        self.status.remove();
     }
 }
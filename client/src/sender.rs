@@ -1,10 +1,10 @@
 use core::cell::RefCell;
 
 use async_std::sync::Arc;
-use protocol::{IceCandidate, SessionDescription};
+use protocol::{ErrorCode, IceCandidate, RoomName, ServerMessage, SessionDescription};
 use web_sys::{
-    Event, HtmlTextAreaElement, HtmlVideoElement, MediaStream, MessageEvent, RtcDataChannel,
-    RtcPeerConnection, RtcPeerConnectionIceEvent, WebSocket,
+    CloseEvent, Event, HtmlDivElement, HtmlTextAreaElement, HtmlVideoElement, MediaStream,
+    MessageEvent, RtcDataChannel, RtcPeerConnection, RtcPeerConnectionIceEvent, WebSocket,
 };
 
 use crate::ClosureCell1;
@@ -13,11 +13,13 @@ use crate::ClosureCell1;
 pub struct Sender {
     websocket: WebSocket,
     webrtc: RtcPeerConnection,
+    status: HtmlDivElement,
     data_channel: RtcDataChannel,
     media_stream: MediaStream,
     video: HtmlVideoElement,
     text_area: HtmlTextAreaElement,
     message_handler: ClosureCell1<MessageEvent>,
+    close_handler: ClosureCell1<CloseEvent>,
     icecandidate_handler: ClosureCell1<RtcPeerConnectionIceEvent>,
     negotiationneeded_handler: ClosureCell1<Event>,
     iceconnectionstatechange_handler: ClosureCell1<Event>,
@@ -27,14 +29,12 @@ pub struct Sender {
 }
 
 impl Sender {
-    pub async fn new(addr: String) -> Arc<Self> {
-        use crate::{body, navigator, ElementExt, RtcConfigurationExt};
+    pub async fn new(addr: String, room: RoomName) -> Arc<Self> {
+        use crate::{body, navigator, new_websocket, ElementExt, RtcConfigurationExt};
         use js_sys::Promise;
         use wasm_bindgen::{JsCast, JsValue};
         use wasm_bindgen_futures::JsFuture;
-        use web_sys::{
-            BinaryType, MediaStreamConstraints, MediaStreamTrack, RtcConfiguration, RtcRtpSender,
-        };
+        use web_sys::{MediaStreamConstraints, MediaStreamTrack, RtcConfiguration, RtcRtpSender};
 
         let mut constraints = MediaStreamConstraints::new();
         let _: &mut _ = constraints.video(&JsValue::TRUE);
@@ -58,10 +58,25 @@ impl Sender {
         let _: Option<_> = video.set_attribute("playsinline", "").ok();
 
         let text: HtmlTextAreaElement = body().add_child("textarea");
+        let status: HtmlDivElement = body().add_child("div");
 
         video.set_src_object(Some(&media_stream));
 
-        let conf = RtcConfiguration::new().with_google_stun_server();
+        let websocket = new_websocket(addr.as_ref());
+
+        let web_socket_opened = Promise::new(&mut |resolve, reject| {
+            websocket.set_onopen(Some(&resolve));
+            websocket.set_onerror(Some(&reject));
+        });
+        let _: JsValue = JsFuture::from(web_socket_opened).await.unwrap();
+
+        // The ICE servers are received in the handshake, so it precedes the peer connection.
+        let handshake = crate::handshake(&websocket).await;
+        let ice_servers = match &handshake {
+            ServerMessage::Hello { ice_servers, .. } => ice_servers.as_slice(),
+            ServerMessage::Error { .. } => &[],
+        };
+        let conf = RtcConfiguration::new().with_ice_servers(ice_servers);
         let webrtc = RtcPeerConnection::new_with_configuration(&conf).unwrap();
 
         let audio_tracks = media_stream.get_audio_tracks();
@@ -76,23 +91,16 @@ impl Sender {
         }
         let data_channel = webrtc.create_data_channel("data");
 
-        let websocket = WebSocket::new(addr.as_ref()).unwrap();
-        websocket.set_binary_type(BinaryType::Arraybuffer);
-
-        let web_socket_opened = Promise::new(&mut |resolve, reject| {
-            websocket.set_onopen(Some(&resolve));
-            websocket.set_onerror(Some(&reject));
-        });
-        let _: JsValue = JsFuture::from(web_socket_opened).await.unwrap();
-
         let sender = Arc::new(Self {
             websocket,
             webrtc,
+            status,
             media_stream,
             data_channel,
             video,
             text_area: text,
             message_handler: RefCell::new(None),
+            close_handler: RefCell::new(None),
             icecandidate_handler: RefCell::new(None),
             negotiationneeded_handler: RefCell::new(None),
             iceconnectionstatechange_handler: RefCell::new(None),
@@ -101,16 +109,19 @@ impl Sender {
             input_handler: RefCell::new(None),
         });
 
-        sender.init().await;
+        sender.init(handshake, room).await;
 
         sender
     }
 
-    async fn init(self: &Arc<Self>) {
+    async fn init(self: &Arc<Self>, handshake: ServerMessage, room: RoomName) {
         use crate::init_weak_callback;
         use web_sys::HtmlElement;
 
-        self.start_server_receiver().await;
+        if !self.on_handshake(handshake) {
+            return;
+        }
+        self.start_server_receiver(room).await;
 
         init_weak_callback(
             &self,
@@ -120,6 +131,14 @@ impl Sender {
             &self.websocket,
         );
 
+        init_weak_callback(
+            &self,
+            Self::on_close,
+            &self.close_handler,
+            WebSocket::set_onclose,
+            &self.websocket,
+        );
+
         init_weak_callback(
             &self,
             Self::on_icecandidate,
@@ -171,11 +190,33 @@ impl Sender {
         self.send_offer().await;
     }
 
-    async fn start_server_receiver(self: &Arc<Self>) {
+    fn on_handshake(self: &Arc<Self>, message: ServerMessage) -> bool {
+        match message {
+            ServerMessage::Hello {
+                protocol_version,
+                capabilities,
+                ice_servers,
+            } => {
+                log::debug!(
+                    "server protocol version: {}, capabilities: {:?}, ice servers: {:?}",
+                    protocol_version,
+                    capabilities,
+                    ice_servers
+                );
+                true
+            }
+            ServerMessage::Error { code, reason } => {
+                self.on_error(code, reason);
+                false
+            }
+        }
+    }
+
+    async fn start_server_receiver(self: &Arc<Self>, room: RoomName) {
         use crate::SendWebSocketMessage;
         use protocol::ClientMessage;
 
-        self.websocket.send(ClientMessage::StartReceiver);
+        self.websocket.send(ClientMessage::StartReceiver(room));
     }
 
     fn on_message(self: &Arc<Self>, ev: MessageEvent) {
@@ -197,6 +238,8 @@ impl Sender {
                 let self_arc = Arc::clone(self);
                 spawn_local(async move { self_arc.on_remote_all_icecandidates_sent().await });
             }
+            ServerReceiverMessage::Error { code, reason } => self.on_error(code, reason),
+            ServerReceiverMessage::Bye => self.on_bye(),
         }
     }
 
@@ -241,6 +284,24 @@ impl Sender {
             .unwrap();
     }
 
+    fn on_error(self: &Arc<Self>, code: ErrorCode, reason: String) {
+        log::error!("server error: {:?}: {}", code, reason);
+        self.status
+            .set_text_content(Some(&format!("Server error: {}", reason)));
+        self.webrtc.close();
+    }
+
+    fn on_bye(self: &Arc<Self>) {
+        log::info!("session closed by server");
+        self.status
+            .set_text_content(Some("Session closed by server"));
+        self.webrtc.close();
+    }
+
+    fn on_close(self: &Arc<Self>, ev: CloseEvent) {
+        log::info!("websocket closed by server: {}", ev.code());
+    }
+
     async fn on_remote_icecandidate(self: &Arc<Self>, ice_candidate: IceCandidate) {
         crate::on_remote_icecandidate(&self.webrtc, ice_candidate).await;
     }
@@ -298,10 +359,17 @@ impl Sender {
 
 impl Drop for Sender {
     fn drop(&mut self) {
+        use crate::SendWebSocketMessage;
+        use protocol::ClientSenderMessage;
         use wasm_bindgen::JsCast;
         use web_sys::MediaStreamTrack;
 
+        if self.websocket.ready_state() == WebSocket::OPEN {
+            self.websocket.send(ClientSenderMessage::Bye);
+        }
+
         self.websocket.set_onmessage(None);
+        self.websocket.set_onclose(None);
         self.webrtc.set_onicecandidate(None);
         self.webrtc.set_onnegotiationneeded(None);
         self.webrtc.set_oniceconnectionstatechange(None);
@@ -320,5 +388,6 @@ impl Drop for Sender {
 
         self.video.remove();
         self.text_area.remove();
+        self.status.remove();
     }
 }
@@ -1,23 +1,32 @@
-use protocol::IceCandidate;
+use protocol::{IceCandidate, IceServer};
 use serde::Serialize;
 use web_sys::{RtcConfiguration, RtcPeerConnection, RtcPeerConnectionIceEvent, WebSocket};
 
 pub trait RtcConfigurationExt {
-    fn with_google_stun_server(self) -> Self;
+    fn with_ice_servers(self, ice_servers: &[IceServer]) -> Self;
 }
 
 impl RtcConfigurationExt for RtcConfiguration {
-    fn with_google_stun_server(mut self) -> Self {
+    fn with_ice_servers(mut self, ice_servers: &[IceServer]) -> Self {
         use js_sys::Array;
         use wasm_bindgen::JsValue;
         use web_sys::RtcIceServer;
 
-        let ice_server_urls = vec![JsValue::from("stun:stun.l.google.com:19302")];
-        let ice_server_urls: Array = ice_server_urls.into_iter().collect();
-        let mut ice_server = RtcIceServer::new();
-        let _: &mut _ = ice_server.urls(&JsValue::from(ice_server_urls));
-
-        let ice_servers: Array = vec![ice_server].into_iter().collect();
+        let ice_servers: Array = ice_servers
+            .iter()
+            .map(|ice_server| {
+                let urls: Array = ice_server.urls.iter().map(JsValue::from).collect();
+                let mut rtc_ice_server = RtcIceServer::new();
+                let _: &mut _ = rtc_ice_server.urls(&JsValue::from(urls));
+                if let Some(username) = &ice_server.username {
+                    let _: &mut _ = rtc_ice_server.username(username);
+                }
+                if let Some(credential) = &ice_server.credential {
+                    let _: &mut _ = rtc_ice_server.credential(credential);
+                }
+                rtc_ice_server
+            })
+            .collect();
         let _: &mut _ = self.ice_servers(&JsValue::from(ice_servers));
 
         self
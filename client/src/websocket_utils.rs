@@ -1,4 +1,6 @@
-use serde::{Deserialize, Serialize};
+use protocol::{Codec, Frame, ServerMessage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use web_sys::{MessageEvent, WebSocket};
 
 pub trait ParseWebSocketMessage<T> {
@@ -9,19 +11,24 @@ pub trait SendWebSocketMessage<T> {
     fn send(&self, message: T);
 }
 
-#[allow(single_use_lifetimes)] // false positive
 impl<T> ParseWebSocketMessage<T> for MessageEvent
 where
-    T: for<'a> Deserialize<'a>,
+    T: DeserializeOwned,
 {
+    // The codec is chosen by the frame type so the server may answer in either of them.
     fn parse(&self) -> T {
-        use bincode::deserialize;
-        use js_sys::{ArrayBuffer, Uint8Array};
+        use js_sys::{ArrayBuffer, JsString, Uint8Array};
         use wasm_bindgen::JsCast;
 
-        let array_buffer: ArrayBuffer = self.data().dyn_into().unwrap();
-        let data = Uint8Array::new(&array_buffer).to_vec();
-        deserialize(&data).unwrap()
+        let data = self.data();
+        let frame = match data.dyn_into::<JsString>() {
+            Ok(text) => Frame::Text(text.into()),
+            Err(data) => {
+                let array_buffer: ArrayBuffer = data.dyn_into().unwrap();
+                Frame::Binary(Uint8Array::new(&array_buffer).to_vec())
+            }
+        };
+        Codec::from_frame(&frame).decode(&frame).unwrap()
     }
 }
 
@@ -30,9 +37,52 @@ where
     T: Serialize,
 {
     fn send(&self, message: T) {
-        use bincode::serialize;
-
-        let request: Vec<u8> = serialize(&message).unwrap();
-        self.send_with_u8_array(&request).unwrap();
+        let codec = Codec::from_subprotocol(&self.protocol()).unwrap_or_default();
+        match codec.encode(&message).unwrap() {
+            Frame::Binary(data) => self.send_with_u8_array(&data).unwrap(),
+            Frame::Text(text) => self.send_with_str(&text).unwrap(),
+        }
     }
 }
+
+// Requests the preferred codec as a subprotocol, the server falls back to bincode without it.
+pub fn new_websocket(addr: &str) -> WebSocket {
+    use crate::params::preferred_codec;
+    use web_sys::BinaryType;
+
+    let websocket = WebSocket::new_with_str(addr, preferred_codec().subprotocol()).unwrap();
+    websocket.set_binary_type(BinaryType::Arraybuffer);
+    websocket
+}
+
+// Waits for a single message, so it must be called before `onmessage` handler is set.
+pub async fn recv_websocket_message<T>(websocket: &WebSocket) -> T
+where
+    T: DeserializeOwned,
+{
+    use js_sys::Promise;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let message_received = Promise::new(&mut |resolve, _| {
+        websocket.set_onmessage(Some(&resolve));
+    });
+    let message: MessageEvent = JsFuture::from(message_received)
+        .await
+        .unwrap()
+        .dyn_into()
+        .unwrap();
+    websocket.set_onmessage(None);
+
+    message.parse()
+}
+
+pub async fn handshake(websocket: &WebSocket) -> ServerMessage {
+    use protocol::{ClientMessage, CAPABILITY_ROOMS, PROTOCOL_VERSION};
+
+    websocket.send(ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: vec![CAPABILITY_ROOMS.to_owned()],
+    });
+    recv_websocket_message(websocket).await
+}
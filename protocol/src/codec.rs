@@ -0,0 +1,77 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Bincode is compact and is used by default,
+// JSON allows to drive the signaling from plain JavaScript or command-line tools.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Codec {
+    Bincode,
+    Json,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Frame {
+    Binary(Vec<u8>),
+    Text(String),
+}
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// Not derived, `#[default]` on enum variants needs Rust 1.62.
+#[allow(clippy::derivable_impls)]
+impl Default for Codec {
+    fn default() -> Self {
+        Self::Bincode
+    }
+}
+
+impl Codec {
+    pub const ALL: &'static [Self] = &[Self::Bincode, Self::Json];
+
+    pub fn from_subprotocol(subprotocol: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|codec| codec.subprotocol() == subprotocol)
+    }
+
+    pub fn subprotocol(self) -> &'static str {
+        match self {
+            Self::Bincode => "bincode",
+            Self::Json => "json",
+        }
+    }
+
+    // Used when no subprotocol was negotiated.
+    pub fn from_frame(frame: &Frame) -> Self {
+        match frame {
+            Frame::Binary(_) => Self::Bincode,
+            Frame::Text(_) => Self::Json,
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, message: &T) -> Result<Frame, CodecError> {
+        match self {
+            Self::Bincode => Ok(Frame::Binary(bincode::serialize(message)?)),
+            Self::Json => Ok(Frame::Text(serde_json::to_string(message)?)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, frame: &Frame) -> Result<T, CodecError> {
+        let data = match frame {
+            Frame::Binary(data) => data.as_slice(),
+            Frame::Text(text) => text.as_bytes(),
+        };
+        match self {
+            Self::Bincode => Ok(bincode::deserialize(data)?),
+            Self::Json => Ok(serde_json::from_slice(data)?),
+        }
+    }
+}
@@ -10,11 +10,29 @@
     unused_results
 )]
 
+mod codec;
+
+pub use codec::{Codec, CodecError, Frame};
+
 use serde::{Deserialize, Serialize};
 
+// Must be incremented on any incompatible change of the messages below.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+pub const CAPABILITY_ROOMS: &str = "rooms";
+
+// The WebSocket is served on this path, the other paths serve HTTP.
+pub const WEBSOCKET_PATH: &str = "/ws";
+
+// Joined by the clients without `CAPABILITY_ROOMS`, whatever room they ask for.
+pub const DEFAULT_ROOM: &str = "default";
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct SessionDescription(pub String);
 
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RoomName(pub String);
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct IceCandidate {
     pub candidate: String,
@@ -23,10 +41,47 @@ pub struct IceCandidate {
     pub username_fragment: Option<String>,
 }
 
+// A STUN or TURN server, the credentials are only used by TURN servers.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    pub username: Option<String>,
+    pub credential: Option<String>,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ErrorCode {
+    InvalidMessage,
+    InvalidSessionDescription,
+    InvalidIceCandidate,
+    RoomFull,
+    IncompatibleProtocolVersion,
+    Internal,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum ClientMessage {
-    StartReceiver,
-    StartSender,
+    // Must stay the first variant so that any client version can be decoded and rejected.
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<String>,
+    },
+    StartReceiver(RoomName),
+    StartSender(RoomName),
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ServerMessage {
+    // The client uses the same ICE servers as the server.
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<String>,
+        ice_servers: Vec<IceServer>,
+    },
+    Error {
+        code: ErrorCode,
+        reason: String,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -34,6 +89,7 @@ pub enum ClientSenderMessage {
     Offer(SessionDescription),
     IceCandidate(IceCandidate),
     AllIceCandidatesSent,
+    Bye,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -41,6 +97,7 @@ pub enum ClientReceiverMessage {
     Answer(SessionDescription),
     IceCandidate(IceCandidate),
     AllIceCandidatesSent,
+    Bye,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -48,6 +105,8 @@ pub enum ServerReceiverMessage {
     Answer(SessionDescription),
     IceCandidate(IceCandidate),
     AllIceCandidatesSent,
+    Error { code: ErrorCode, reason: String },
+    Bye,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -55,4 +114,6 @@ pub enum ServerSenderMessage {
     Offer(SessionDescription),
     IceCandidate(IceCandidate),
     AllIceCandidatesSent,
+    Error { code: ErrorCode, reason: String },
+    Bye,
 }
@@ -0,0 +1,84 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use protocol::{
+    ClientMessage, Codec, ErrorCode, Frame, IceCandidate, RoomName, ServerSenderMessage,
+    SessionDescription,
+};
+
+fn messages() -> (Vec<ClientMessage>, Vec<ServerSenderMessage>) {
+    let client_messages = vec![
+        ClientMessage::Hello {
+            protocol_version: protocol::PROTOCOL_VERSION,
+            capabilities: vec![protocol::CAPABILITY_ROOMS.to_owned()],
+        },
+        ClientMessage::StartSender(RoomName("room".to_owned())),
+    ];
+    let server_messages = vec![
+        ServerSenderMessage::Offer(SessionDescription("v=0\r\n".to_owned())),
+        ServerSenderMessage::IceCandidate(IceCandidate {
+            candidate: "candidate:1 1 udp 2130706431 127.0.0.1 50000 typ host".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_mline_index: None,
+            username_fragment: None,
+        }),
+        ServerSenderMessage::Error {
+            code: ErrorCode::RoomFull,
+            reason: "room is full".to_owned(),
+        },
+        ServerSenderMessage::Bye,
+    ];
+    (client_messages, server_messages)
+}
+
+fn assert_round_trip(codec: Codec) {
+    let (client_messages, server_messages) = messages();
+    for message in client_messages {
+        let frame = codec.encode(&message).unwrap();
+        assert_eq!(Codec::from_frame(&frame), codec);
+        assert_eq!(codec.decode::<ClientMessage>(&frame).unwrap(), message);
+    }
+    for message in server_messages {
+        let frame = codec.encode(&message).unwrap();
+        assert_eq!(
+            codec.decode::<ServerSenderMessage>(&frame).unwrap(),
+            message
+        );
+    }
+}
+
+#[test]
+fn bincode_round_trip() {
+    assert_round_trip(Codec::Bincode);
+}
+
+#[test]
+fn json_round_trip() {
+    assert_round_trip(Codec::Json);
+}
+
+#[test]
+fn json_is_readable() {
+    let frame = Codec::Json
+        .encode(&ClientMessage::StartReceiver(RoomName("room".to_owned())))
+        .unwrap();
+    assert_eq!(frame, Frame::Text(r#"{"StartReceiver":"room"}"#.to_owned()));
+}
+
+#[test]
+fn subprotocols() {
+    assert_eq!(Codec::default(), Codec::Bincode);
+    for &codec in Codec::ALL {
+        assert_eq!(Codec::from_subprotocol(codec.subprotocol()), Some(codec));
+    }
+    assert_eq!(Codec::from_subprotocol("xml"), None);
+}
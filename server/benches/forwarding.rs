@@ -0,0 +1,152 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+// Per-packet cost of the fan-out of a received video packet to the subscribers of a room:
+// `ChannelSender::send` into the subscriber queues, `ChannelReceiver::recv` and the write
+// to the local track of every subscriber, reported as packets per second on a single core.
+// The tracks are not bound to a peer connection, so SRTP and the sockets are not included.
+// `shared_packet` is the current path, `marshaled` the previous one where the packet was
+// marshaled into a `Vec<u8>`, copied into every queue and parsed again for every subscriber.
+// Run with `cargo bench`.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rtp::packet::Packet;
+use server::{ChannelMessage, ChannelReceiver, ChannelSender};
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+
+const SUBSCRIBERS: [usize; 3] = [1, 4, 16];
+const SUBSCRIBER_QUEUE_SIZE: usize = 512;
+
+fn video_packet() -> Packet {
+    use rtp::header::{Extension, Header};
+
+    Packet {
+        header: Header {
+            version: 2,
+            extension: true,
+            payload_type: 96,
+            sequence_number: 1234,
+            timestamp: 90000,
+            ssrc: 0x1234_5678,
+            extension_profile: 0xBEDE,
+            extensions: vec![Extension {
+                id: 1,
+                payload: Bytes::from_static(&[0x12, 0x34, 0x56]),
+            }],
+            ..Default::default()
+        },
+        payload: Bytes::from(vec![0x55; 1100]),
+    }
+}
+
+// A room with a sender and `subscribers` receivers, each with its own video track.
+fn room(subscribers: usize) -> (ChannelSender, Vec<(ChannelReceiver, TrackLocalStaticRTP)>) {
+    use protocol::RoomName;
+    use server::Channels;
+    use webrtc::api::media_engine::MIME_TYPE_VP8;
+    use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+
+    let mut channels = Channels::new(SUBSCRIBER_QUEUE_SIZE, None);
+    let room = RoomName("room".to_owned());
+    let sender = channels.sender(&room).unwrap();
+    let receivers = (0..subscribers)
+        .map(|index| {
+            let track = TrackLocalStaticRTP::new(
+                RTCRtpCodecCapability {
+                    mime_type: MIME_TYPE_VP8.to_owned(),
+                    ..Default::default()
+                },
+                format!("video{}", index),
+                "webrtc-rs".to_owned(),
+            );
+            (channels.receiver(&room), track)
+        })
+        .collect();
+    (sender, receivers)
+}
+
+async fn forward(
+    sender: &ChannelSender,
+    receivers: &[(ChannelReceiver, TrackLocalStaticRTP)],
+    packet: Packet,
+) {
+    use webrtc::track::track_local::TrackLocalWriter;
+
+    sender.send(ChannelMessage::Video(Arc::new(packet)));
+    for (receiver, track) in receivers {
+        if let Some(ChannelMessage::Video(rtp)) = receiver.recv().await {
+            let _: usize = track.write_rtp(&rtp).await.unwrap();
+        }
+    }
+}
+
+// The marshaled packet goes through the same queues as a data message.
+async fn forward_marshaled(
+    sender: &ChannelSender,
+    receivers: &[(ChannelReceiver, TrackLocalStaticRTP)],
+    packet: Packet,
+) {
+    use webrtc::track::track_local::TrackLocalWriter;
+    use webrtc_util::marshal::{Marshal, Unmarshal};
+
+    let data: Vec<u8> = packet.marshal().unwrap().to_vec();
+    sender.send(ChannelMessage::Data(Bytes::from(data)));
+    for (receiver, track) in receivers {
+        if let Some(ChannelMessage::Data(data)) = receiver.recv().await {
+            let data = data.to_vec();
+            let rtp = Packet::unmarshal(&mut data.as_slice()).unwrap();
+            let _: usize = track.write_rtp(&rtp).await.unwrap();
+        }
+    }
+}
+
+fn forwarding(c: &mut Criterion) {
+    use criterion::{BatchSize, BenchmarkId, Throughput};
+    use tokio::runtime::Builder;
+
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let packet = video_packet();
+    let mut group = c.benchmark_group("forwarding");
+    let _: &mut _ = group.throughput(Throughput::Elements(1));
+    for &subscribers in &SUBSCRIBERS {
+        let (sender, receivers) = runtime.block_on(async { room(subscribers) });
+        let _: &mut _ = group.bench_with_input(
+            BenchmarkId::new("shared_packet", subscribers),
+            &subscribers,
+            |b, _| {
+                b.iter_batched(
+                    || packet.clone(),
+                    |packet| runtime.block_on(forward(&sender, &receivers, packet)),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        let _: &mut _ = group.bench_with_input(
+            BenchmarkId::new("marshaled", subscribers),
+            &subscribers,
+            |b, _| {
+                b.iter_batched(
+                    || packet.clone(),
+                    |packet| runtime.block_on(forward_marshaled(&sender, &receivers, packet)),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, forwarding);
+criterion_main!(benches);
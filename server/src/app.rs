@@ -1,4 +1,14 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
 use clap::{AppSettings, Clap};
+use protocol::IceServer;
+
+use server::{
+    Error, ForwardingSettings, NetworkSettings, StaticFiles, TlsConfig, TurnCredentials, TurnServer,
+};
+
+const DEFAULT_STUN_SERVER: &str = "stun:stun.l.google.com:19302";
 
 #[derive(Clap)]
 #[clap(
@@ -14,14 +24,262 @@ struct Options {
     /// Port number
     #[clap(short, long, default_value = "9010")]
     port: String,
+    /// PEM certificate chain to serve `wss://` and `https://`
+    #[clap(long, requires = "tls-key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    #[clap(long, requires = "tls-cert")]
+    tls_key: Option<PathBuf>,
+    /// Serve `wss://` and `https://` with a generated self-signed certificate for development
+    #[clap(long, conflicts_with = "tls-cert")]
+    tls_self_signed: bool,
+    /// Serve the built client from this directory, e.g. `client/dist`, with a generated `params.js`
+    #[clap(long)]
+    static_dir: Option<PathBuf>,
+    /// Forwarded packets kept per receiver and track to answer NACKs without the sender, 0 disables it [default: 512]
+    #[clap(long)]
+    nack_buffer_size: Option<usize>,
+    /// Media packets queued per receiver before video is dropped until the next keyframe [default: 512]
+    #[clap(long)]
+    subscriber_queue_size: Option<usize>,
+    /// Record incoming media to IVF (VP8) and Ogg (Opus) files
+    #[clap(long)]
+    record: bool,
+    /// Directory for recordings
+    #[clap(long, default_value = "recordings")]
+    record_dir: PathBuf,
+    /// Stream media files into the room as a virtual sender
+    #[clap(long)]
+    play_room: Option<String>,
+    /// VP8 IVF file to stream
+    #[clap(long, requires = "play-room")]
+    play_video: Option<PathBuf>,
+    /// Opus Ogg file to stream
+    #[clap(long, requires = "play-room")]
+    play_audio: Option<PathBuf>,
+    /// Restart streaming the files when they end
+    #[clap(long, requires = "play-room")]
+    play_loop: bool,
+    /// STUN or TURN server URL, can be repeated [default: stun:stun.l.google.com:19302]
+    #[clap(long = "ice-server")]
+    ice_servers: Vec<String>,
+    /// Username for the TURN servers given with --ice-server
+    #[clap(long, requires = "ice-credential")]
+    ice_username: Option<String>,
+    /// Credential for the TURN servers given with --ice-server
+    #[clap(long, requires = "ice-username")]
+    ice_credential: Option<String>,
+    /// JSON file with a list of ICE servers, e.g. [{"urls": ["turn:host"], "username": "user", "credential": "secret"}]
+    #[clap(long)]
+    ice_config: Option<PathBuf>,
+    /// TURN server URL with time-limited credentials minted for every session, can be repeated
+    #[clap(long = "turn-server", requires = "turn-secret")]
+    turn_servers: Vec<String>,
+    /// Secret shared with the TURN servers given with --turn-server, e.g. coturn static-auth-secret
+    #[clap(long)]
+    turn_secret: Option<String>,
+    /// Lifetime of the minted TURN credentials in seconds
+    #[clap(long, default_value = "86400")]
+    turn_ttl: u64,
+    /// Run an embedded STUN and TURN server on this UDP port and advertise it to the clients
+    #[clap(long, requires = "turn-public-ip")]
+    turn_port: Option<u16>,
+    /// IP address of the embedded TURN server reachable by the clients, also used for relays
+    #[clap(long, requires = "turn-port")]
+    turn_public_ip: Option<IpAddr>,
+    /// Let the embedded TURN server relay to loopback, link-local and private addresses
+    #[clap(long, requires = "turn-port")]
+    turn_allow_private_peers: bool,
+    /// Lowest UDP port for ICE, e.g. to open a fixed range on the firewall
+    #[clap(long, requires = "udp-port-max")]
+    udp_port_min: Option<u16>,
+    /// Highest UDP port for ICE
+    #[clap(long, requires = "udp-port-min")]
+    udp_port_max: Option<u16>,
+    /// Single UDP port shared by all peer connections, instead of a port per connection
+    #[clap(long, conflicts_with_all = &["udp-port-min", "udp-port-max"])]
+    udp_mux_port: Option<u16>,
+    /// Public IP of a 1:1 NAT, or `public/private` to map each private IP
+    #[clap(long = "nat-1to1-ip")]
+    nat_1to1_ips: Vec<String>,
+    /// Use the 1:1 NAT IPs in host candidates or add them as server reflexive candidates
+    #[clap(long = "nat-1to1-candidate-type", default_value = "host", possible_values = &["host", "srflx"])]
+    nat_1to1_candidate_type: String,
+    /// Gather ICE candidates only on this network interface, `*` at the end matches a prefix
+    #[clap(long = "ice-interface")]
+    ice_interfaces: Vec<String>,
+    /// Do not gather ICE candidates on this network interface, e.g. `docker*`
+    #[clap(long = "ice-exclude-interface")]
+    ice_excluded_interfaces: Vec<String>,
+    /// Gather ICE candidates only on the network interface with this IP
+    #[clap(long = "ice-ip")]
+    ice_ips: Vec<IpAddr>,
+    /// Gather ICE candidates only on this network type
+    #[clap(long = "ice-network-type", possible_values = &["udp4", "udp6"])]
+    ice_network_types: Vec<String>,
 }
 
-pub async fn app() {
-    use crate::Server;
+pub async fn app() -> Result<(), Error> {
+    use protocol::RoomName;
+    use server::{FileSender, Recorder, Server, ServerSettings};
 
     env_logger::init();
     let opts: Options = Options::parse();
     let addr = format!("{}:{}", opts.address, opts.port);
-    let server = Server::new(addr).await;
+    // Without a configured secret the embedded server gets a random one,
+    // it is only known to this process anyway.
+    let turn_secret = opts
+        .turn_secret
+        .clone()
+        .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+    let turn_server = match (opts.turn_port, opts.turn_public_ip) {
+        (Some(port), Some(public_ip)) => {
+            let secret = turn_secret.clone();
+            Some(TurnServer::new(port, public_ip, secret, opts.turn_allow_private_peers).await?)
+        }
+        _ => None,
+    };
+    let ice_servers = ice_servers(&opts, turn_server.as_ref())?;
+    let turn_credentials = turn_credentials(&opts, turn_secret, turn_server.as_ref());
+    let network_settings = network_settings(&opts);
+    let forwarding_settings = forwarding_settings(&opts);
+    let tls_config = tls_config(&opts)?;
+    let static_files = opts.static_dir.clone().map(StaticFiles::new).transpose()?;
+    let recorder = if opts.record {
+        Some(Recorder::new(opts.record_dir)?)
+    } else {
+        None
+    };
+    let settings = ServerSettings {
+        tls_config,
+        static_files,
+        ice_servers,
+        turn_credentials,
+        network_settings,
+        forwarding_settings,
+        recorder,
+    };
+    let server = Server::new(addr, settings).await?;
+    if let Some(room) = opts.play_room {
+        let file_sender = FileSender::new(
+            RoomName(room),
+            opts.play_video.as_deref(),
+            opts.play_audio.as_deref(),
+            opts.play_loop,
+        )?;
+        file_sender.spawn(server.channels());
+    }
     Server::run(server).await;
+    if let Some(turn_server) = turn_server {
+        turn_server.close().await;
+    }
+    Ok(())
+}
+
+// The servers from the command line are followed by the servers from the config file
+// and the embedded STUN server, the default STUN server is used only if none is given.
+fn ice_servers(opts: &Options, turn_server: Option<&TurnServer>) -> Result<Vec<IceServer>, Error> {
+    let mut ice_servers = Vec::new();
+    if !opts.ice_servers.is_empty() {
+        ice_servers.push(IceServer {
+            urls: opts.ice_servers.clone(),
+            username: opts.ice_username.clone(),
+            credential: opts.ice_credential.clone(),
+        });
+    }
+    if let Some(path) = &opts.ice_config {
+        let config = std::fs::read(path)?;
+        let config: Vec<IceServer> = serde_json::from_slice(&config)?;
+        ice_servers.extend(config);
+    }
+    if let Some(turn_server) = turn_server {
+        ice_servers.push(IceServer {
+            urls: vec![turn_server.stun_url()],
+            username: None,
+            credential: None,
+        });
+    }
+    if opts.ice_servers.is_empty() && opts.ice_config.is_none() && turn_server.is_none() {
+        ice_servers.push(IceServer {
+            urls: vec![DEFAULT_STUN_SERVER.to_owned()],
+            username: None,
+            credential: None,
+        });
+    }
+    Ok(ice_servers)
+}
+
+// The embedded TURN server accepts the same credentials as the external ones.
+fn turn_credentials(
+    opts: &Options,
+    secret: String,
+    turn_server: Option<&TurnServer>,
+) -> Option<TurnCredentials> {
+    use core::time::Duration;
+
+    let mut urls = opts.turn_servers.clone();
+    if let Some(turn_server) = turn_server {
+        urls.push(turn_server.turn_url());
+        urls.push(turn_server.turn_tcp_url());
+    }
+    if urls.is_empty() {
+        None
+    } else {
+        Some(TurnCredentials::new(
+            urls,
+            secret,
+            Duration::from_secs(opts.turn_ttl),
+        ))
+    }
+}
+
+fn tls_config(opts: &Options) -> Result<Option<TlsConfig>, Error> {
+    match (&opts.tls_cert, &opts.tls_key) {
+        (Some(cert_path), Some(key_path)) => {
+            Ok(Some(TlsConfig::from_pem_files(cert_path, key_path)?))
+        }
+        _ if opts.tls_self_signed => {
+            let mut subject_alt_names = vec!["localhost".to_owned()];
+            // The certificate is useless for a wildcard address like 0.0.0.0.
+            if !matches!(opts.address.parse::<IpAddr>(), Ok(ip) if ip.is_unspecified()) {
+                subject_alt_names.push(opts.address.clone());
+            }
+            Ok(Some(TlsConfig::self_signed(subject_alt_names)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn forwarding_settings(opts: &Options) -> ForwardingSettings {
+    let mut forwarding_settings = ForwardingSettings::default();
+    if let Some(nack_buffer_size) = opts.nack_buffer_size {
+        forwarding_settings.nack_buffer_size = nack_buffer_size;
+    }
+    if let Some(subscriber_queue_size) = opts.subscriber_queue_size {
+        forwarding_settings.subscriber_queue_size = subscriber_queue_size;
+    }
+    forwarding_settings
+}
+
+fn network_settings(opts: &Options) -> NetworkSettings {
+    use webrtc::ice_transport::ice_candidate_type::RTCIceCandidateType;
+    use webrtc_ice::network_type::NetworkType;
+
+    NetworkSettings {
+        udp_port_range: opts.udp_port_min.zip(opts.udp_port_max),
+        udp_mux_port: opts.udp_mux_port,
+        nat_1to1_ips: opts.nat_1to1_ips.clone(),
+        nat_1to1_candidate_type: RTCIceCandidateType::from(opts.nat_1to1_candidate_type.as_str()),
+        interfaces: opts.ice_interfaces.clone(),
+        excluded_interfaces: opts.ice_excluded_interfaces.clone(),
+        ips: opts.ice_ips.clone(),
+        network_types: opts
+            .ice_network_types
+            .iter()
+            .map(|network_type| match network_type.as_str() {
+                "udp6" => NetworkType::Udp6,
+                _ => NetworkType::Udp4,
+            })
+            .collect(),
+    }
 }
@@ -1,42 +1,120 @@
 use core::fmt;
-use core::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::sync::Notify;
 
-use crate::{ChannelMessage, ChannelReceiver, ChannelSender};
+use crate::{ChannelFeedback, ChannelReceiver, ChannelSender, Recorder, SubscriberQueue};
+
+// Feedback is dropped rather than queued without limit, e.g. while there is no sender.
+const FEEDBACK_QUEUE_SIZE: usize = 64;
 
 #[derive(Debug)]
 pub struct Channel {
     channel_id: ChannelId,
-    sender: UnboundedSender<ChannelMessage>,
-    receiver: UnboundedReceiver<ChannelMessage>,
-    has_receiver: Arc<AtomicBool>,
+    subscribers: Arc<Mutex<Vec<SubscriberQueue>>>,
+    subscriber_queue_size: usize,
+    // Subscribers ask the sender for a keyframe, pending requests are coalesced into one.
+    keyframe_requests: Arc<Notify>,
+    // Shared by the subscribers, the receiving end is passed on from sender to sender.
+    feedback_sender: Sender<ChannelFeedback>,
+    feedback_receiver: Arc<AsyncMutex<Receiver<ChannelFeedback>>>,
+    has_sender: bool,
+    recorder: Option<Arc<Recorder>>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct ChannelId(pub u32);
 
 impl Channel {
-    pub fn new(channel_id: ChannelId) -> Self {
-        use tokio::sync::mpsc::unbounded_channel;
-        let (sender, receiver) = unbounded_channel();
-        let has_receiver = Arc::new(AtomicBool::new(false));
+    pub fn new(
+        channel_id: ChannelId,
+        subscriber_queue_size: usize,
+        recorder: Option<Arc<Recorder>>,
+    ) -> Self {
+        use tokio::sync::mpsc::channel;
+
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let keyframe_requests = Arc::new(Notify::new());
+        let (feedback_sender, feedback_receiver) = channel(FEEDBACK_QUEUE_SIZE);
+        let feedback_receiver = Arc::new(AsyncMutex::new(feedback_receiver));
 
         Self {
             channel_id,
-            sender,
-            receiver,
-            has_receiver,
+            subscribers,
+            subscriber_queue_size: subscriber_queue_size.max(1),
+            keyframe_requests,
+            feedback_sender,
+            feedback_receiver,
+            has_sender: false,
+            recorder,
+        }
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        self.channel_id
+    }
+
+    // Only one sender is allowed, so it returns `None` if the channel already has one.
+    pub fn sender(&mut self) -> Option<ChannelSender> {
+        if self.has_sender {
+            None
+        } else {
+            self.has_sender = true;
+            Some(ChannelSender::new(
+                self.channel_id,
+                Arc::clone(&self.subscribers),
+                Arc::clone(&self.keyframe_requests),
+                Arc::clone(&self.feedback_receiver),
+                self.recorder.clone(),
+            ))
         }
     }
 
-    pub fn split(self) -> (ChannelSender, ChannelReceiver) {
-        (
-            ChannelSender::new(self.channel_id, self.sender, Arc::clone(&self.has_receiver)),
-            ChannelReceiver::new(self.channel_id, self.receiver, self.has_receiver),
+    pub fn remove_sender(&mut self) {
+        self.has_sender = false;
+    }
+
+    pub fn has_sender(&self) -> bool {
+        self.has_sender
+    }
+
+    // Every subscriber gets its own queues so that a slow one does not stall the others.
+    pub fn subscribe(&self) -> ChannelReceiver {
+        use crate::DropCounters;
+        use tokio::sync::mpsc::{channel, unbounded_channel};
+
+        let (media_sender, media_receiver) = channel(self.subscriber_queue_size);
+        let (data_sender, data_receiver) = unbounded_channel();
+        let dropped = Arc::new(DropCounters::default());
+        self.subscribers.lock().unwrap().push(SubscriberQueue::new(
+            self.channel_id,
+            media_sender,
+            data_sender,
+            Arc::clone(&self.keyframe_requests),
+            Arc::clone(&dropped),
+        ));
+        ChannelReceiver::new(
+            self.channel_id,
+            media_receiver,
+            data_receiver,
+            dropped,
+            Arc::clone(&self.keyframe_requests),
+            self.feedback_sender.clone(),
         )
     }
+
+    pub fn has_subscribers(&self) -> bool {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| !subscriber.is_closed());
+        !subscribers.is_empty()
+    }
+
+    // Drops all subscriber queues, so every subscriber receives the end of the stream.
+    pub fn close(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
 }
 
 impl fmt::Display for ChannelId {
@@ -0,0 +1,11 @@
+use rtcp::transport_feedbacks::transport_layer_nack::NackPair;
+
+// RTCP feedback of a subscriber for the channel sender, keyframe requests go separately.
+// The sequence numbers are the same on both sides, only the SSRCs are translated.
+#[derive(Clone, Debug)]
+pub enum ChannelFeedback {
+    VideoNack(Vec<NackPair>),
+    AudioNack(Vec<NackPair>),
+    // Receiver estimated maximum bitrate in bits per second.
+    Remb(u64),
+}
@@ -1,6 +1,58 @@
-#[derive(Debug)]
+use std::sync::Arc;
+
+use bytes::Bytes;
+use rtp::packet::Packet;
+
+// The packets are shared by all subscribers, cloning a message does not copy them.
+#[derive(Clone, Debug)]
 pub enum ChannelMessage {
-    Data(Vec<u8>),
-    Video(Vec<u8>),
-    Audio(Vec<u8>),
+    Data(Bytes),
+    Video(Arc<Packet>),
+    Audio(Arc<Packet>),
+}
+
+impl ChannelMessage {
+    // Is true for the first RTP packet of a VP8 keyframe.
+    pub fn is_keyframe(&self) -> bool {
+        match self {
+            ChannelMessage::Video(packet) => is_vp8_keyframe(&packet.payload),
+            ChannelMessage::Data(_) | ChannelMessage::Audio(_) => false,
+        }
+    }
+}
+
+// Skips the VP8 payload descriptor of RFC 7741 and checks the inverse key frame flag
+// of the VP8 payload header, which is only there at the start of a partition.
+fn is_vp8_keyframe(payload: &[u8]) -> bool {
+    let descriptor = match payload.first() {
+        Some(descriptor) => *descriptor,
+        None => return false,
+    };
+    let extended = descriptor & 0x80 != 0;
+    let start_of_partition = descriptor & 0x10 != 0;
+    let partition_index = descriptor & 0x07;
+    if !start_of_partition || partition_index != 0 {
+        return false;
+    }
+
+    let mut header_index = 1;
+    if extended {
+        let extension = match payload.get(1) {
+            Some(extension) => *extension,
+            None => return false,
+        };
+        header_index += 1;
+        if extension & 0x80 != 0 {
+            // The picture ID takes two bytes if its M bit is set.
+            let long_picture_id = matches!(payload.get(header_index), Some(id) if id & 0x80 != 0);
+            header_index += if long_picture_id { 2 } else { 1 };
+        }
+        if extension & 0x40 != 0 {
+            header_index += 1;
+        }
+        if extension & 0x30 != 0 {
+            header_index += 1;
+        }
+    }
+    matches!(payload.get(header_index), Some(header) if header & 0x01 == 0)
 }
@@ -1,30 +1,36 @@
-use core::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
-use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::sync::Mutex;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver};
+use tokio::sync::{Mutex, Notify};
 
-use crate::{ChannelId, ChannelMessage};
+use crate::{ChannelFeedback, ChannelId, ChannelMessage, DropCounters, DroppedPackets};
 
 #[derive(Debug)]
 pub struct ChannelReceiver {
     channel_id: ChannelId,
-    receiver: Mutex<UnboundedReceiver<ChannelMessage>>,
-    has_receiver: Arc<AtomicBool>,
+    receiver: Mutex<Queues>,
+    dropped: Arc<DropCounters>,
+    keyframe_requests: Arc<Notify>,
+    feedback: Sender<ChannelFeedback>,
 }
 
 impl ChannelReceiver {
     pub fn new(
         channel_id: ChannelId,
-        receiver: UnboundedReceiver<ChannelMessage>,
-        has_receiver: Arc<AtomicBool>,
+        media: Receiver<ChannelMessage>,
+        data: UnboundedReceiver<ChannelMessage>,
+        dropped: Arc<DropCounters>,
+        keyframe_requests: Arc<Notify>,
+        feedback: Sender<ChannelFeedback>,
     ) -> Self {
-        let receiver = Mutex::new(receiver);
+        let receiver = Mutex::new(Queues { media, data });
 
         Self {
             channel_id,
             receiver,
-            has_receiver,
+            dropped,
+            keyframe_requests,
+            feedback,
         }
     }
 
@@ -32,9 +38,43 @@ impl ChannelReceiver {
         self.channel_id
     }
 
+    // Asks the channel sender for a keyframe, e.g. when the subscriber has just started.
+    pub fn request_keyframe(&self) {
+        self.keyframe_requests.notify_one();
+    }
+
+    // Passes NACKs and bitrate estimates on to the channel sender.
+    // Feedback is dropped if the queue is full, it would be stale by then anyway.
+    pub fn send_feedback(&self, feedback: ChannelFeedback) {
+        let _: Result<(), _> = self.feedback.try_send(feedback);
+    }
+
+    // The media packets dropped so far because this subscriber did not keep up.
+    pub fn dropped_packets(&self) -> DroppedPackets {
+        self.dropped.get()
+    }
+
+    // Data is preferred, it is small and sent rarely.
     pub async fn recv(&self) -> Option<ChannelMessage> {
-        use core::sync::atomic::Ordering;
-        self.has_receiver.store(true, Ordering::Relaxed);
-        self.receiver.lock().await.recv().await
+        let mut queues = self.receiver.lock().await;
+        let Queues { media, data } = &mut *queues;
+        tokio::select! {
+            biased;
+            Some(message) = data.recv() => Some(message),
+            Some(message) = media.recv() => Some(message),
+            else => None,
+        }
+    }
+
+    pub async fn close(&self) {
+        let mut queues = self.receiver.lock().await;
+        queues.media.close();
+        queues.data.close();
     }
 }
+
+#[derive(Debug)]
+struct Queues {
+    media: Receiver<ChannelMessage>,
+    data: UnboundedReceiver<ChannelMessage>,
+}
@@ -1,27 +1,34 @@
-use core::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::sync::Notify;
 
-use crate::{ChannelId, ChannelMessage};
+use crate::{ChannelFeedback, ChannelId, ChannelMessage, Recorder, SubscriberQueue};
 
 #[derive(Clone, Debug)]
 pub struct ChannelSender {
     channel_id: ChannelId,
-    sender: UnboundedSender<ChannelMessage>,
-    has_receiver: Arc<AtomicBool>,
+    subscribers: Arc<Mutex<Vec<SubscriberQueue>>>,
+    keyframe_requests: Arc<Notify>,
+    feedback: Arc<AsyncMutex<Receiver<ChannelFeedback>>>,
+    recorder: Option<Arc<Recorder>>,
 }
 
 impl ChannelSender {
     pub fn new(
         channel_id: ChannelId,
-        sender: UnboundedSender<ChannelMessage>,
-        has_receiver: Arc<AtomicBool>,
+        subscribers: Arc<Mutex<Vec<SubscriberQueue>>>,
+        keyframe_requests: Arc<Notify>,
+        feedback: Arc<AsyncMutex<Receiver<ChannelFeedback>>>,
+        recorder: Option<Arc<Recorder>>,
     ) -> Self {
         Self {
             channel_id,
-            sender,
-            has_receiver,
+            subscribers,
+            keyframe_requests,
+            feedback,
+            recorder,
         }
     }
 
@@ -29,10 +36,26 @@ impl ChannelSender {
         self.channel_id
     }
 
+    // Is `None` unless recording is enabled.
+    pub fn recorder(&self) -> Option<&Arc<Recorder>> {
+        self.recorder.as_ref()
+    }
+
+    // Resolves once a subscriber has requested a keyframe since the previous call.
+    pub async fn keyframe_requested(&self) {
+        self.keyframe_requests.notified().await
+    }
+
+    // Resolves with the next feedback of any subscriber.
+    pub async fn recv_feedback(&self) -> Option<ChannelFeedback> {
+        self.feedback.lock().await.recv().await
+    }
+
+    // The subscribers which are gone are removed, the others keep their order.
     pub fn send(&self, message: ChannelMessage) {
-        use core::sync::atomic::Ordering;
-        if self.has_receiver.load(Ordering::Relaxed) {
-            self.sender.send(message).unwrap();
-        }
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain_mut(|subscriber| subscriber.send(message.clone()));
     }
 }
@@ -1,47 +1,77 @@
 use core::sync::atomic::AtomicU32;
-use std::collections::VecDeque;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-use crate::{Channel, ChannelId, ChannelReceiver, ChannelSender};
+use protocol::RoomName;
+
+use crate::{Channel, ChannelId, ChannelReceiver, ChannelSender, Error, Recorder};
 
 #[derive(Debug)]
 pub struct Channels {
-    senders: VecDeque<ChannelSender>,
-    receivers: VecDeque<ChannelReceiver>,
+    channels: HashMap<RoomName, Channel>,
     next_channel_id: AtomicU32,
+    subscriber_queue_size: usize,
+    recorder: Option<Arc<Recorder>>,
 }
 
 impl Channels {
-    pub fn new() -> Self {
-        let senders = VecDeque::new();
-        let receivers = VecDeque::new();
+    pub fn new(subscriber_queue_size: usize, recorder: Option<Recorder>) -> Self {
+        let channels = HashMap::new();
         let next_channel_id = AtomicU32::new(0);
+        let recorder = recorder.map(Arc::new);
 
         Self {
-            senders,
-            receivers,
+            channels,
             next_channel_id,
+            subscriber_queue_size,
+            recorder,
         }
     }
 
-    pub fn sender(&mut self) -> ChannelSender {
-        use core::sync::atomic::Ordering;
+    pub fn sender(&mut self, room: &RoomName) -> Result<ChannelSender, Error> {
+        self.channel(room)
+            .sender()
+            .ok_or_else(|| Error::RoomFull(room.clone()))
+    }
 
-        self.senders.pop_front().unwrap_or_else(|| {
-            let channel_id = ChannelId(self.next_channel_id.fetch_add(1, Ordering::Relaxed));
-            let (sender, receiver) = Channel::new(channel_id).split();
-            self.receivers.push_back(receiver);
-            sender
-        })
+    pub fn receiver(&mut self, room: &RoomName) -> ChannelReceiver {
+        self.channel(room).subscribe()
+    }
+
+    // Once the sender of a room is gone its receivers are disconnected.
+    pub fn remove_sender(&mut self, room: &RoomName) {
+        if let Some(channel) = self.channels.get_mut(room) {
+            channel.remove_sender();
+            channel.close();
+        }
+        self.remove_unused(room);
+    }
+
+    pub fn remove_receiver(&mut self, room: &RoomName) {
+        self.remove_unused(room);
     }
 
-    pub fn receiver(&mut self) -> ChannelReceiver {
+    fn channel(&mut self, room: &RoomName) -> &mut Channel {
         use core::sync::atomic::Ordering;
 
-        self.receivers.pop_front().unwrap_or_else(|| {
-            let channel_id = ChannelId(self.next_channel_id.fetch_add(1, Ordering::Relaxed));
-            let (sender, receiver) = Channel::new(channel_id).split();
-            self.senders.push_back(sender);
-            receiver
+        let next_channel_id = &self.next_channel_id;
+        let subscriber_queue_size = self.subscriber_queue_size;
+        let recorder = &self.recorder;
+        self.channels.entry(room.clone()).or_insert_with(|| {
+            let channel_id = ChannelId(next_channel_id.fetch_add(1, Ordering::Relaxed));
+            log::debug!("channel {}: created for room {:?}", channel_id, room.0);
+            Channel::new(channel_id, subscriber_queue_size, recorder.clone())
         })
     }
+
+    fn remove_unused(&mut self, room: &RoomName) {
+        let channel = match self.channels.get(room) {
+            Some(channel) => channel,
+            None => return,
+        };
+        if !channel.has_sender() && !channel.has_subscribers() {
+            log::debug!("channel {}: removed", channel.channel_id());
+            let _: Option<_> = self.channels.remove(room);
+        }
+    }
 }
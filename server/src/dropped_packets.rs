@@ -0,0 +1,32 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// Media packets dropped for a subscriber that did not keep up, data is never dropped.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DroppedPackets {
+    pub video: u64,
+    pub audio: u64,
+}
+
+// Shared by the queue of a subscriber and the subscriber itself.
+#[derive(Debug, Default)]
+pub struct DropCounters {
+    video: AtomicU64,
+    audio: AtomicU64,
+}
+
+impl DropCounters {
+    pub fn add_video(&self) {
+        let _: u64 = self.video.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_audio(&self) {
+        let _: u64 = self.audio.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> DroppedPackets {
+        DroppedPackets {
+            video: self.video.load(Ordering::Relaxed),
+            audio: self.audio.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+
+use protocol::{CodecError, ErrorCode, RoomName, PROTOCOL_VERSION};
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("http error: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("websocket error: {0}")]
+    WebSocket(#[source] Box<tungstenite::Error>),
+    #[error("websocket key expected")]
+    WebSocketKeyExpected,
+    #[error("message codec error: {0}")]
+    Codec(#[from] CodecError),
+    #[error("handshake expected")]
+    HandshakeExpected,
+    #[error("unexpected repeated handshake")]
+    UnexpectedHandshake,
+    #[error(
+        "incompatible protocol version {0}, server supports version {}",
+        PROTOCOL_VERSION
+    )]
+    ProtocolVersion(u32),
+    #[error("room {:?} already has a sender", .0 .0)]
+    RoomFull(RoomName),
+    #[error("webrtc error: {0}")]
+    WebRtc(#[source] webrtc::Error),
+    #[error("signaling error: {0}")]
+    Signaling(#[source] anyhow::Error),
+    #[error("invalid session description: {0}")]
+    SessionDescription(#[source] anyhow::Error),
+    #[error("invalid ice candidate: {0}")]
+    IceCandidate(#[source] anyhow::Error),
+    #[error("forwarding error: {0}")]
+    Forwarding(#[source] anyhow::Error),
+    #[error("recording error: {0}")]
+    Recording(#[source] anyhow::Error),
+    #[error("invalid media file: {0}")]
+    InvalidMediaFile(&'static str),
+    #[error("invalid ICE server config: {0}")]
+    IceConfig(#[from] serde_json::Error),
+    #[error("TURN server {0:?} requires a username and a credential")]
+    TurnCredentialsExpected(String),
+    #[error("embedded TURN server error: {0}")]
+    Turn(#[source] anyhow::Error),
+    #[error("invalid network settings: {0}")]
+    NetworkSettings(#[source] anyhow::Error),
+    #[error("TLS error: {0}")]
+    Tls(#[source] anyhow::Error),
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Codec(_)
+            | Self::WebSocketKeyExpected
+            | Self::HandshakeExpected
+            | Self::UnexpectedHandshake => ErrorCode::InvalidMessage,
+            Self::SessionDescription(_) => ErrorCode::InvalidSessionDescription,
+            Self::IceCandidate(_) => ErrorCode::InvalidIceCandidate,
+            Self::RoomFull(_) => ErrorCode::RoomFull,
+            Self::ProtocolVersion(_) => ErrorCode::IncompatibleProtocolVersion,
+            Self::Io(_)
+            | Self::Http(_)
+            | Self::WebSocket(_)
+            | Self::WebRtc(_)
+            | Self::Signaling(_)
+            | Self::Forwarding(_)
+            | Self::Recording(_)
+            | Self::InvalidMediaFile(_)
+            | Self::IceConfig(_)
+            | Self::TurnCredentialsExpected(_)
+            | Self::Turn(_)
+            | Self::NetworkSettings(_)
+            | Self::Tls(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(err: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(err))
+    }
+}
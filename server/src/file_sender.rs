@@ -0,0 +1,235 @@
+use core::time::Duration;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use protocol::RoomName;
+use rtp::packet::Packet;
+use rtp::packetizer::Payloader;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::{ChannelMessage, ChannelSender, Channels, Error, IvfReader, OggReader};
+
+const RTP_MTU: usize = 1200;
+const RTP_HEADER_SIZE: usize = 12;
+const VIDEO_PAYLOAD_TYPE: u8 = 96;
+const VIDEO_CLOCK_RATE: u32 = 90000;
+const AUDIO_PAYLOAD_TYPE: u8 = 111;
+const AUDIO_CLOCK_RATE: u32 = 48000;
+// Used for the last frame of a looped video, whose duration is not stored in IVF.
+const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(33);
+
+// A virtual sender which streams a VP8 IVF file and an Opus Ogg file into a room in real time,
+// its packets are forwarded to the receivers like the packets of a browser sender.
+#[derive(Debug)]
+pub struct FileSender {
+    room: RoomName,
+    video: Option<IvfReader>,
+    audio: Option<OggReader>,
+    repeat: bool,
+}
+
+impl FileSender {
+    pub fn new(
+        room: RoomName,
+        video_path: Option<&Path>,
+        audio_path: Option<&Path>,
+        repeat: bool,
+    ) -> Result<Self, Error> {
+        let video = match video_path {
+            Some(path) => Some(IvfReader::new(read_file(path)?)?),
+            None => None,
+        };
+        let audio = match audio_path {
+            Some(path) => Some(OggReader::new(read_file(path)?)?),
+            None => None,
+        };
+
+        Ok(Self {
+            room,
+            video,
+            audio,
+            repeat,
+        })
+    }
+
+    pub fn spawn(self, channels: Arc<Mutex<Channels>>) {
+        use tokio::spawn;
+        use tokio::task::JoinHandle;
+
+        let _join_handle: JoinHandle<()> = spawn(self.run(channels));
+    }
+
+    async fn run(self, channels: Arc<Mutex<Channels>>) {
+        use tokio::join;
+
+        let channel_sender = match channels.lock().await.sender(&self.room) {
+            Ok(channel_sender) => channel_sender,
+            Err(err) => {
+                log::error!("file sender: {}", err);
+                return;
+            }
+        };
+        let channel_id = channel_sender.channel_id();
+        log::info!(
+            "channel {}: file sender started in room {:?}",
+            channel_id,
+            self.room.0
+        );
+
+        // Both streams are paced from the same instant to keep them in sync.
+        let start = Instant::now();
+        let (video, audio) = join!(
+            self.stream_video(&channel_sender, start),
+            self.stream_audio(&channel_sender, start)
+        );
+        if let Err(err) = video {
+            log::error!("channel {}: file sender video: {}", channel_id, err);
+        }
+        if let Err(err) = audio {
+            log::error!("channel {}: file sender audio: {}", channel_id, err);
+        }
+
+        channels.lock().await.remove_sender(&self.room);
+        log::info!("channel {}: file sender finished", channel_id);
+    }
+
+    // The keyframe requests of the receivers are not answered, the frames of a file can only be
+    // decoded from its own keyframes. New receivers wait for the next keyframe of the file, so it
+    // should be encoded with a short keyframe interval.
+    async fn stream_video(
+        &self,
+        channel_sender: &ChannelSender,
+        start: Instant,
+    ) -> Result<(), Error> {
+        use rtp::codecs::vp8::Vp8Payloader;
+        use tokio::time::sleep_until;
+
+        let reader = match &self.video {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+        let mut rtp = RtpStream::new(VIDEO_PAYLOAD_TYPE, VIDEO_CLOCK_RATE);
+        let mut payloader = Vp8Payloader::default();
+        let mut loop_position = Duration::from_secs(0);
+        loop {
+            let mut reader = reader.clone();
+            let mut last_position = None;
+            let mut frame_duration = DEFAULT_FRAME_DURATION;
+            while let Some(frame) = reader.next_frame() {
+                if let Some(last_position) = last_position.filter(|&last| frame.position > last) {
+                    frame_duration = frame.position - last_position;
+                }
+                last_position = Some(frame.position);
+
+                let position = loop_position + frame.position;
+                sleep_until(start + position).await;
+                for packet in rtp.packetize(&mut payloader, &frame.data, position)? {
+                    channel_sender.send(ChannelMessage::Video(Arc::new(packet)));
+                }
+            }
+            match last_position {
+                Some(last_position) if self.repeat => {
+                    loop_position += last_position + frame_duration
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    async fn stream_audio(
+        &self,
+        channel_sender: &ChannelSender,
+        start: Instant,
+    ) -> Result<(), Error> {
+        use crate::opus_packet_samples;
+        use rtp::codecs::opus::OpusPayloader;
+        use tokio::time::sleep_until;
+
+        let reader = match &self.audio {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+        let mut rtp = RtpStream::new(AUDIO_PAYLOAD_TYPE, AUDIO_CLOCK_RATE);
+        let mut position = Duration::from_secs(0);
+        loop {
+            let mut reader = reader.clone();
+            let loop_position = position;
+            while let Some(packet) = reader.next_opus_packet()? {
+                sleep_until(start + position).await;
+                for packet in rtp.packetize(&mut OpusPayloader, &packet, position)? {
+                    channel_sender.send(ChannelMessage::Audio(Arc::new(packet)));
+                }
+                let samples = opus_packet_samples(&packet);
+                position += Duration::from_secs(u64::from(samples)) / AUDIO_CLOCK_RATE;
+            }
+            if !self.repeat || position == loop_position {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Produces RTP packets with timestamps derived from the stream position.
+#[derive(Debug)]
+struct RtpStream {
+    payload_type: u8,
+    ssrc: u32,
+    clock_rate: u32,
+    base_timestamp: u32,
+    sequence_number: u16,
+}
+
+impl RtpStream {
+    fn new(payload_type: u8, clock_rate: u32) -> Self {
+        Self {
+            payload_type,
+            ssrc: rand::random(),
+            clock_rate,
+            base_timestamp: rand::random(),
+            sequence_number: rand::random(),
+        }
+    }
+
+    fn packetize(
+        &mut self,
+        payloader: &mut dyn Payloader,
+        payload: &Bytes,
+        position: Duration,
+    ) -> Result<Vec<Packet>, Error> {
+        use rtp::header::Header;
+
+        // RTP timestamps wrap around, so the truncation is intended.
+        let timestamp = self.base_timestamp.wrapping_add(
+            (position.as_nanos() * u128::from(self.clock_rate) / 1_000_000_000) as u32,
+        );
+        let payloads = payloader
+            .payload(RTP_MTU - RTP_HEADER_SIZE, payload)
+            .map_err(|err| Error::Forwarding(err.into()))?;
+        let count = payloads.len();
+
+        let mut packets = Vec::with_capacity(count);
+        for (index, payload) in payloads.into_iter().enumerate() {
+            let packet = Packet {
+                header: Header {
+                    version: 2,
+                    marker: index + 1 == count,
+                    payload_type: self.payload_type,
+                    sequence_number: self.sequence_number,
+                    timestamp,
+                    ssrc: self.ssrc,
+                    ..Default::default()
+                },
+                payload,
+            };
+            self.sequence_number = self.sequence_number.wrapping_add(1);
+            packets.push(packet);
+        }
+        Ok(packets)
+    }
+}
+
+fn read_file(path: &Path) -> Result<Bytes, Error> {
+    Ok(Bytes::from(std::fs::read(path)?))
+}
@@ -0,0 +1,23 @@
+// Default of 512 packets covers about one second of 4 Mbit/s video.
+const DEFAULT_NACK_BUFFER_SIZE: usize = 512;
+const DEFAULT_SUBSCRIBER_QUEUE_SIZE: usize = 512;
+
+// Settings of the forwarding from the room sender to the receivers.
+#[derive(Clone, Copy, Debug)]
+pub struct ForwardingSettings {
+    // Recently forwarded packets kept per receiver and track to answer NACKs locally,
+    // rounded up to a power of two, 0 forwards all NACKs to the sender.
+    pub nack_buffer_size: usize,
+    // Media packets queued per receiver, once full video is dropped until the next keyframe
+    // and audio is dropped, data channel messages are never dropped. At least 1.
+    pub subscriber_queue_size: usize,
+}
+
+impl Default for ForwardingSettings {
+    fn default() -> Self {
+        Self {
+            nack_buffer_size: DEFAULT_NACK_BUFFER_SIZE,
+            subscriber_queue_size: DEFAULT_SUBSCRIBER_QUEUE_SIZE,
+        }
+    }
+}
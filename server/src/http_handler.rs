@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::{Body, HeaderMap, Request, Response, StatusCode};
+use protocol::{IceServer, RoomName, SessionDescription};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Mutex;
+
+use crate::{Channels, Error, StaticFiles, WebRtcApi, WhepSender, WhipReceiver};
+
+// Serves WebSocket upgrades, WHIP and WHEP resources and the client files on the same port.
+#[derive(Debug)]
+pub struct HttpHandler {
+    channels: Arc<Mutex<Channels>>,
+    webrtc_api: Arc<WebRtcApi>,
+    static_files: Option<StaticFiles>,
+    // Whether the connections are served over TLS.
+    secure: bool,
+    whip_receivers: Mutex<HashMap<String, Arc<WhipReceiver>>>,
+    whep_senders: Mutex<HashMap<String, Arc<WhepSender>>>,
+}
+
+impl HttpHandler {
+    pub fn new(
+        channels: Arc<Mutex<Channels>>,
+        webrtc_api: Arc<WebRtcApi>,
+        static_files: Option<StaticFiles>,
+        secure: bool,
+    ) -> Self {
+        let whip_receivers = Mutex::new(HashMap::new());
+        let whep_senders = Mutex::new(HashMap::new());
+
+        Self {
+            channels,
+            webrtc_api,
+            static_files,
+            secure,
+            whip_receivers,
+            whep_senders,
+        }
+    }
+
+    // The stream is either a plain TCP stream or a TLS stream on top of it.
+    pub async fn serve<Stream>(self: Arc<Self>, stream: Stream, addr: SocketAddr)
+    where
+        Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        use core::convert::Infallible;
+        use hyper::server::conn::Http;
+        use hyper::service::service_fn;
+
+        let service = service_fn(move |request| {
+            let handler = Arc::clone(&self);
+            async move { Ok::<_, Infallible>(handler.handle(request, addr).await) }
+        });
+        let result = Http::new()
+            .http1_only(true)
+            .serve_connection(stream, service)
+            .with_upgrades()
+            .await;
+        if let Err(err) = result {
+            log::debug!("http {}: {}", addr, err);
+        }
+    }
+
+    async fn handle(self: Arc<Self>, request: Request<Body>, addr: SocketAddr) -> Response<Body> {
+        use crate::Socket;
+        use hyper::Method;
+        use protocol::WEBSOCKET_PATH;
+
+        if is_websocket_upgrade(request.headers()) {
+            if request.uri().path() != WEBSOCKET_PATH {
+                return status_response(StatusCode::NOT_FOUND);
+            }
+            let channels = Arc::clone(&self.channels);
+            let webrtc_api = Arc::clone(&self.webrtc_api);
+            return Socket::upgrade(request, addr, channels, webrtc_api).unwrap_or_else(|err| {
+                log::error!("socket {}: {}", addr, err);
+                error_response(&err)
+            });
+        }
+
+        let method = request.method().clone();
+        let path = request.uri().path().to_owned();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let result = match (&method, segments.as_slice()) {
+            (&Method::POST, ["whip", room]) => match room_name(room) {
+                Some(room) => self.on_whip_offer(&path, room, request).await,
+                None => Ok(status_response(StatusCode::BAD_REQUEST)),
+            },
+            (&Method::DELETE, ["whip", room, resource_id]) => match room_name(room) {
+                Some(room) => Ok(self.on_whip_delete(&room, resource_id).await),
+                None => Ok(status_response(StatusCode::BAD_REQUEST)),
+            },
+            (&Method::POST, ["whep", room]) => match room_name(room) {
+                Some(room) => self.on_whep_offer(&path, room, request).await,
+                None => Ok(status_response(StatusCode::BAD_REQUEST)),
+            },
+            (&Method::DELETE, ["whep", room, resource_id]) => match room_name(room) {
+                Some(room) => Ok(self.on_whep_delete(&room, resource_id).await),
+                None => Ok(status_response(StatusCode::BAD_REQUEST)),
+            },
+            (&Method::GET, _) => self.on_static_file(&path, &request).await,
+            _ => Ok(status_response(StatusCode::NOT_FOUND)),
+        };
+
+        result.unwrap_or_else(|err| {
+            log::error!("http {}: {} {}: {}", addr, method, path, err);
+            error_response(&err)
+        })
+    }
+
+    async fn on_static_file(
+        &self,
+        path: &str,
+        request: &Request<Body>,
+    ) -> Result<Response<Body>, Error> {
+        use hyper::header::HOST;
+
+        let static_files = match &self.static_files {
+            Some(static_files) => static_files,
+            None => return Ok(status_response(StatusCode::NOT_FOUND)),
+        };
+        let host = request
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok());
+        let response = static_files.response(path, host, self.secure).await?;
+        Ok(response.unwrap_or_else(|| status_response(StatusCode::NOT_FOUND)))
+    }
+
+    async fn on_whip_offer(
+        self: &Arc<Self>,
+        path: &str,
+        room: RoomName,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, Error> {
+        use tokio::spawn;
+        use tokio::task::JoinHandle;
+
+        let offer = match recv_offer(request).await? {
+            Ok(offer) => offer,
+            Err(status) => return Ok(status_response(status)),
+        };
+
+        let (whip_receiver, answer) = WhipReceiver::new(
+            room,
+            offer,
+            Arc::clone(&self.channels),
+            Arc::clone(&self.webrtc_api),
+        )
+        .await?;
+        let whip_receiver = Arc::new(whip_receiver);
+
+        let resource_id = new_resource_id();
+        let location = format!("{}/{}", path.trim_end_matches('/'), resource_id);
+        log::info!(
+            "channel {}: whip receiver {} opened in room {:?}",
+            whip_receiver.channel_id(),
+            resource_id,
+            whip_receiver.room().0
+        );
+        let _: Option<_> = self
+            .whip_receivers
+            .lock()
+            .await
+            .insert(resource_id.clone(), Arc::clone(&whip_receiver));
+
+        // WHIP senders may disappear without deleting their resource.
+        let handler = Arc::clone(self);
+        let _join_handle: JoinHandle<()> = spawn(async move {
+            whip_receiver.disconnected().await;
+            let whip_receiver = handler.whip_receivers.lock().await.remove(&resource_id);
+            if let Some(whip_receiver) = whip_receiver {
+                whip_receiver.close().await;
+                log::info!(
+                    "channel {}: whip receiver {} disconnected",
+                    whip_receiver.channel_id(),
+                    resource_id
+                );
+            }
+        });
+
+        Ok(answer_response(
+            answer,
+            &location,
+            &self.webrtc_api.ice_servers(),
+        ))
+    }
+
+    async fn on_whip_delete(
+        self: &Arc<Self>,
+        room: &RoomName,
+        resource_id: &str,
+    ) -> Response<Body> {
+        let whip_receiver = {
+            let mut whip_receivers = self.whip_receivers.lock().await;
+            match whip_receivers.get(resource_id) {
+                Some(whip_receiver) if whip_receiver.room() == room => {
+                    whip_receivers.remove(resource_id)
+                }
+                _ => None,
+            }
+        };
+
+        match whip_receiver {
+            Some(whip_receiver) => {
+                whip_receiver.close().await;
+                log::info!(
+                    "channel {}: whip receiver {} deleted",
+                    whip_receiver.channel_id(),
+                    resource_id
+                );
+                status_response(StatusCode::OK)
+            }
+            None => status_response(StatusCode::NOT_FOUND),
+        }
+    }
+
+    async fn on_whep_offer(
+        self: &Arc<Self>,
+        path: &str,
+        room: RoomName,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, Error> {
+        use tokio::spawn;
+        use tokio::task::JoinHandle;
+
+        let offer = match recv_offer(request).await? {
+            Ok(offer) => offer,
+            Err(status) => return Ok(status_response(status)),
+        };
+
+        let (whep_sender, answer) = WhepSender::new(
+            room,
+            offer,
+            Arc::clone(&self.channels),
+            Arc::clone(&self.webrtc_api),
+        )
+        .await?;
+        let whep_sender = Arc::new(whep_sender);
+
+        let resource_id = new_resource_id();
+        let location = format!("{}/{}", path.trim_end_matches('/'), resource_id);
+        log::info!(
+            "channel {}: whep sender {} opened in room {:?}",
+            whep_sender.channel_id(),
+            resource_id,
+            whep_sender.room().0
+        );
+        let _: Option<_> = self
+            .whep_senders
+            .lock()
+            .await
+            .insert(resource_id.clone(), Arc::clone(&whep_sender));
+
+        // WHEP players may disappear without deleting their resource,
+        // the session is also closed once the room sender has gone.
+        let handler = Arc::clone(self);
+        let _join_handle: JoinHandle<()> = spawn(async move {
+            whep_sender.disconnected().await;
+            let whep_sender = handler.whep_senders.lock().await.remove(&resource_id);
+            if let Some(whep_sender) = whep_sender {
+                whep_sender.close().await;
+                log::info!(
+                    "channel {}: whep sender {} disconnected",
+                    whep_sender.channel_id(),
+                    resource_id
+                );
+            }
+        });
+
+        Ok(answer_response(
+            answer,
+            &location,
+            &self.webrtc_api.ice_servers(),
+        ))
+    }
+
+    async fn on_whep_delete(
+        self: &Arc<Self>,
+        room: &RoomName,
+        resource_id: &str,
+    ) -> Response<Body> {
+        let whep_sender = {
+            let mut whep_senders = self.whep_senders.lock().await;
+            match whep_senders.get(resource_id) {
+                Some(whep_sender) if whep_sender.room() == room => whep_senders.remove(resource_id),
+                _ => None,
+            }
+        };
+
+        match whep_sender {
+            Some(whep_sender) => {
+                whep_sender.close().await;
+                log::info!(
+                    "channel {}: whep sender {} deleted",
+                    whep_sender.channel_id(),
+                    resource_id
+                );
+                status_response(StatusCode::OK)
+            }
+            None => status_response(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+const SDP_MIME_TYPE: &str = "application/sdp";
+// Offers are a few kilobytes, the limit keeps a client from filling the memory.
+const MAX_OFFER_SIZE: usize = 64 * 1024;
+
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    use hyper::header::UPGRADE;
+
+    matches!(
+        headers.get(UPGRADE).and_then(|value| value.to_str().ok()),
+        Some(upgrade) if upgrade.eq_ignore_ascii_case("websocket")
+    )
+}
+
+fn is_sdp(headers: &HeaderMap) -> bool {
+    use hyper::header::CONTENT_TYPE;
+
+    matches!(
+        headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()),
+        Some(content_type) if content_type.starts_with(SDP_MIME_TYPE)
+    )
+}
+
+// Returns the status to respond with if the request body is not an SDP or is too large.
+async fn recv_offer(
+    request: Request<Body>,
+) -> Result<Result<SessionDescription, StatusCode>, Error> {
+    use hyper::body::HttpBody;
+    use hyper::header::CONTENT_LENGTH;
+
+    if !is_sdp(request.headers()) {
+        return Ok(Err(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    }
+    let content_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if matches!(content_length, Some(content_length) if content_length > MAX_OFFER_SIZE) {
+        return Ok(Err(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+
+    // Chunked bodies have no length, so the limit is checked while reading too.
+    let mut body = request.into_body();
+    let mut offer = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if offer.len() + chunk.len() > MAX_OFFER_SIZE {
+            return Ok(Err(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+        offer.extend_from_slice(&chunk);
+    }
+    let offer = String::from_utf8(offer).map_err(|err| Error::SessionDescription(err.into()))?;
+    Ok(Ok(SessionDescription(offer)))
+}
+
+fn new_resource_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+fn room_name(segment: &str) -> Option<RoomName> {
+    use percent_encoding::percent_decode_str;
+
+    let room = percent_decode_str(segment).decode_utf8().ok()?;
+    Some(RoomName(room.into_owned()))
+}
+
+// The ICE servers are advertised in `Link` headers as described in the WHIP specification.
+fn answer_response(
+    answer: SessionDescription,
+    location: &str,
+    ice_servers: &[IceServer],
+) -> Response<Body> {
+    use hyper::header::{HeaderValue, CONTENT_TYPE, LINK, LOCATION};
+
+    let mut response = Response::new(Body::from(answer.0));
+    *response.status_mut() = StatusCode::CREATED;
+    let headers = response.headers_mut();
+    let _: Option<_> = headers.insert(CONTENT_TYPE, HeaderValue::from_static(SDP_MIME_TYPE));
+    let _: Option<_> = headers.insert(
+        LOCATION,
+        HeaderValue::from_str(location).expect("request path is a valid header value"),
+    );
+    for ice_server in ice_servers {
+        for url in &ice_server.urls {
+            let mut link = format!("<{}>; rel=\"ice-server\"", url);
+            if let (Some(username), Some(credential)) =
+                (&ice_server.username, &ice_server.credential)
+            {
+                link += &format!(
+                    "; username=\"{}\"; credential=\"{}\"; credential-type=\"password\"",
+                    username, credential
+                );
+            }
+            if let Ok(link) = HeaderValue::from_str(&link) {
+                let _: bool = headers.append(LINK, link);
+            }
+        }
+    }
+    response
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn error_response(err: &Error) -> Response<Body> {
+    use protocol::ErrorCode;
+
+    let status = match err.code() {
+        ErrorCode::InvalidMessage
+        | ErrorCode::InvalidSessionDescription
+        | ErrorCode::InvalidIceCandidate
+        | ErrorCode::IncompatibleProtocolVersion => StatusCode::BAD_REQUEST,
+        ErrorCode::RoomFull => StatusCode::CONFLICT,
+        ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let mut response = Response::new(Body::from(err.to_string()));
+    *response.status_mut() = status;
+    response
+}
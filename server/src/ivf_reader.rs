@@ -0,0 +1,66 @@
+use core::time::Duration;
+
+use bytes::Bytes;
+
+use crate::Error;
+
+#[derive(Clone, Debug)]
+pub struct IvfFrame {
+    pub position: Duration,
+    pub data: Bytes,
+}
+
+// Reads VP8 frames from an IVF file loaded into memory.
+#[derive(Clone, Debug)]
+pub struct IvfReader {
+    data: Bytes,
+    offset: usize,
+    timebase_numerator: u32,
+    timebase_denominator: u32,
+}
+
+impl IvfReader {
+    pub fn new(data: Bytes) -> Result<Self, Error> {
+        if data.len() < 32 || &data[0..4] != b"DKIF" {
+            return Err(Error::InvalidMediaFile("IVF signature expected"));
+        }
+        if &data[8..12] != b"VP80" {
+            return Err(Error::InvalidMediaFile("only VP8 IVF files are supported"));
+        }
+        let header_size = usize::from(u16::from_le_bytes([data[6], data[7]]));
+        let timebase_denominator = u32::from_le_bytes([data[16], data[17], data[18], data[19]]);
+        let timebase_numerator = u32::from_le_bytes([data[20], data[21], data[22], data[23]]);
+        if header_size < 32 || timebase_denominator == 0 {
+            return Err(Error::InvalidMediaFile("invalid IVF header"));
+        }
+
+        Ok(Self {
+            data,
+            offset: header_size,
+            timebase_numerator,
+            timebase_denominator,
+        })
+    }
+
+    // Returns `None` at the end of the file, a truncated last frame is ignored.
+    pub fn next_frame(&mut self) -> Option<IvfFrame> {
+        let header = self.data.get(self.offset..self.offset + 12)?;
+        let size = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&header[4..12]);
+        let timestamp = u64::from_le_bytes(timestamp);
+
+        let start = self.offset + 12;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= self.data.len())?;
+        self.offset = end;
+
+        let nanos = u128::from(timestamp) * u128::from(self.timebase_numerator) * 1_000_000_000
+            / u128::from(self.timebase_denominator);
+        Some(IvfFrame {
+            position: Duration::from_nanos(nanos as u64),
+            data: self.data.slice(start..end),
+        })
+    }
+}
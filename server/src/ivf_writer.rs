@@ -0,0 +1,168 @@
+use rtp::packet::Packet;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::Error;
+
+const IVF_HEADER_SIZE: u16 = 32;
+// RTP clock rate of VP8, the timestamps are written as is.
+const IVF_TIMEBASE_DENOMINATOR: u32 = 90000;
+
+// Depacketizes a VP8 track into an IVF file. Frames before the first keyframe are skipped, and
+// after a lost packet every frame is skipped until the next keyframe, as they cannot be decoded.
+#[derive(Debug)]
+pub struct IvfWriter<W> {
+    writer: W,
+    frame: Vec<u8>,
+    frame_is_keyframe: bool,
+    // Is `false` from a lost packet until the start of the next frame.
+    frame_is_complete: bool,
+    waiting_for_keyframe: bool,
+    last_sequence_number: Option<u16>,
+    first_timestamp: Option<u32>,
+}
+
+impl<W: AsyncWrite + Unpin> IvfWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            frame: Vec::new(),
+            frame_is_keyframe: false,
+            frame_is_complete: false,
+            waiting_for_keyframe: true,
+            last_sequence_number: None,
+            first_timestamp: None,
+        }
+    }
+
+    pub async fn write_rtp(&mut self, rtp: &Packet) -> Result<(), Error> {
+        let sequence_number = rtp.header.sequence_number;
+        if let Some(last_sequence_number) = self.last_sequence_number {
+            let delta = sequence_number.wrapping_sub(last_sequence_number) as i16;
+            // Late packets belong to a frame which is already written or dropped.
+            if delta <= 0 {
+                return Ok(());
+            }
+            if delta > 1 {
+                self.drop_frame();
+            }
+        }
+        self.last_sequence_number = Some(sequence_number);
+        // Padding only packets.
+        if rtp.payload.is_empty() {
+            return Ok(());
+        }
+
+        // Not depacketized by `Vp8Packet`, it panics on some truncated descriptors.
+        let payload = match vp8_descriptor_size(&rtp.payload) {
+            Some(size) if size < rtp.payload.len() => &rtp.payload[size..],
+            _ => {
+                self.drop_frame();
+                return Ok(());
+            }
+        };
+        // The start of a partition with the partition index 0 starts a frame.
+        if rtp.payload[0] & 0x17 == 0x10 {
+            self.frame.clear();
+            self.frame_is_complete = true;
+            // The lowest bit of the VP8 frame tag is zero for keyframes.
+            self.frame_is_keyframe = payload[0] & 0x01 == 0;
+        }
+        if !self.frame_is_complete {
+            return Ok(());
+        }
+        self.frame.extend_from_slice(payload);
+
+        if rtp.header.marker {
+            self.write_frame(rtp.header.timestamp).await?;
+            self.frame.clear();
+            self.frame_is_complete = false;
+        }
+        Ok(())
+    }
+
+    pub async fn finish(&mut self) -> Result<(), Error> {
+        Ok(self.writer.flush().await?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn drop_frame(&mut self) {
+        self.frame.clear();
+        self.frame_is_complete = false;
+        self.waiting_for_keyframe = true;
+    }
+
+    async fn write_frame(&mut self, timestamp: u32) -> Result<(), Error> {
+        if self.waiting_for_keyframe {
+            if !self.frame_is_keyframe {
+                return Ok(());
+            }
+            self.waiting_for_keyframe = false;
+        }
+        let first_timestamp = match self.first_timestamp {
+            Some(first_timestamp) => first_timestamp,
+            None => {
+                self.write_header().await?;
+                *self.first_timestamp.insert(timestamp)
+            }
+        };
+
+        let timestamp = u64::from(timestamp.wrapping_sub(first_timestamp));
+        let mut frame_header = [0; 12];
+        frame_header[0..4].copy_from_slice(&(self.frame.len() as u32).to_le_bytes());
+        frame_header[4..12].copy_from_slice(&timestamp.to_le_bytes());
+        self.writer.write_all(&frame_header).await?;
+        self.writer.write_all(&self.frame).await?;
+        Ok(())
+    }
+
+    // The header is written with the dimensions of the first keyframe.
+    async fn write_header(&mut self) -> Result<(), Error> {
+        let (width, height) = match self.frame.get(6..10) {
+            Some(size) => (
+                u16::from_le_bytes([size[0], size[1]]) & 0x3fff,
+                u16::from_le_bytes([size[2], size[3]]) & 0x3fff,
+            ),
+            None => (0, 0),
+        };
+
+        let mut header = Vec::with_capacity(usize::from(IVF_HEADER_SIZE));
+        header.extend_from_slice(b"DKIF");
+        header.extend_from_slice(&0_u16.to_le_bytes());
+        header.extend_from_slice(&IVF_HEADER_SIZE.to_le_bytes());
+        header.extend_from_slice(b"VP80");
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&IVF_TIMEBASE_DENOMINATOR.to_le_bytes());
+        header.extend_from_slice(&1_u32.to_le_bytes());
+        // The number of frames is unknown while recording, players do not rely on it.
+        header.extend_from_slice(&0_u32.to_le_bytes());
+        header.extend_from_slice(&0_u32.to_le_bytes());
+        Ok(self.writer.write_all(&header).await?)
+    }
+}
+
+// Returns the size of the VP8 payload descriptor, see RFC 7741, section 4.2.
+fn vp8_descriptor_size(payload: &[u8]) -> Option<usize> {
+    let mut size = 1;
+    if payload.first()? & 0x80 == 0 {
+        return Some(size);
+    }
+    let extension = *payload.get(size)?;
+    size += 1;
+    // PictureID, its M bit selects the 15 bit form.
+    if extension & 0x80 != 0 {
+        size += if payload.get(size)? & 0x80 == 0 { 1 } else { 2 };
+    }
+    // TL0PICIDX.
+    if extension & 0x40 != 0 {
+        size += 1;
+    }
+    // TID/Y/KEYIDX.
+    if extension & 0x30 != 0 {
+        size += 1;
+    }
+    Some(size)
+}
@@ -0,0 +1,98 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+mod channel;
+mod channel_feedback;
+mod channel_message;
+mod channel_receiver;
+mod channel_sender;
+mod channels;
+mod dropped_packets;
+mod error;
+mod file_sender;
+mod forwarding_settings;
+mod http_handler;
+mod ivf_reader;
+mod ivf_writer;
+mod network_settings;
+mod ogg_reader;
+mod ogg_writer;
+mod recorder;
+mod rtp_history;
+mod server;
+mod server_settings;
+mod socket;
+mod socket_receiver;
+mod socket_sender;
+mod static_files;
+mod subscriber_queue;
+mod tls_config;
+mod track_recorder;
+mod turn_credentials;
+mod turn_server;
+mod turn_tcp_conn;
+mod weak_callback;
+mod webrtc_api;
+mod webrtc_data_receiver;
+mod webrtc_media_receiver;
+mod webrtc_receiver;
+mod webrtc_sender;
+mod webrtc_utils;
+mod websocket_receiver;
+mod websocket_sender;
+mod whep_sender;
+mod whip_receiver;
+
+use channel::{Channel, ChannelId};
+use channel_feedback::ChannelFeedback;
+pub use channel_message::ChannelMessage;
+pub use channel_receiver::ChannelReceiver;
+pub use channel_sender::ChannelSender;
+pub use channels::Channels;
+use dropped_packets::DropCounters;
+pub use dropped_packets::DroppedPackets;
+pub use error::Error;
+pub use file_sender::FileSender;
+pub use forwarding_settings::ForwardingSettings;
+use http_handler::HttpHandler;
+pub use ivf_reader::{IvfFrame, IvfReader};
+pub use ivf_writer::IvfWriter;
+pub use network_settings::NetworkSettings;
+pub use ogg_reader::{opus_packet_samples, OggReader};
+pub use ogg_writer::OggWriter;
+pub use recorder::Recorder;
+use rtp_history::RtpHistory;
+pub use server::Server;
+pub use server_settings::ServerSettings;
+use socket::Socket;
+use socket_receiver::SocketReceiver;
+use socket_sender::SocketSender;
+pub use static_files::StaticFiles;
+use subscriber_queue::SubscriberQueue;
+pub use tls_config::TlsConfig;
+use track_recorder::TrackRecorder;
+pub use turn_credentials::{turn_credential, TurnCredentials};
+pub use turn_server::TurnServer;
+use turn_tcp_conn::TurnTcpConn;
+use weak_callback::WeakAsyncCallback;
+use webrtc_api::WebRtcApi;
+use webrtc_data_receiver::WebRtcDataReceiver;
+use webrtc_media_receiver::WebRtcMediaReceiver;
+use webrtc_receiver::WebRtcReceiver;
+use webrtc_sender::WebRtcSender;
+use webrtc_utils::{
+    add_remote_icecandidate, is_keyframe_request, rtcp_feedback, send_local_icecandidate,
+};
+use websocket_receiver::WebSocketReceiver;
+use websocket_sender::WebSocketSender;
+use whep_sender::WhepSender;
+use whip_receiver::WhipReceiver;
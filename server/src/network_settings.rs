@@ -0,0 +1,154 @@
+use std::net::IpAddr;
+
+use webrtc::api::setting_engine::SettingEngine;
+use webrtc::ice_transport::ice_candidate_type::RTCIceCandidateType;
+use webrtc_ice::network_type::NetworkType;
+use webrtc_ice::udp_network::UDPNetwork;
+
+use crate::Error;
+
+// Network settings of the server side peer connections,
+// e.g. for firewall rules, a 1:1 NAT or hosts with docker and VPN interfaces.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkSettings {
+    // Inclusive range of the ICE UDP ports, any ephemeral port is used if None.
+    pub udp_port_range: Option<(u16, u16)>,
+    // All peer connections share this UDP port if set, with a single host candidate.
+    // IPv4 is used unless `network_types` is only UDP over IPv6.
+    pub udp_mux_port: Option<u16>,
+    // Public IPs of a 1:1 NAT, either `public` or `public/private` for multiple private IPs.
+    pub nat_1to1_ips: Vec<String>,
+    // Host replaces the private IPs in the host candidates,
+    // srflx adds server reflexive candidates with the public IPs.
+    pub nat_1to1_candidate_type: RTCIceCandidateType,
+    // Only these interfaces are used if not empty, a trailing `*` matches a name prefix.
+    pub interfaces: Vec<String>,
+    pub excluded_interfaces: Vec<String>,
+    // Only the interfaces carrying one of these IPs are used if not empty.
+    pub ips: Vec<IpAddr>,
+    // UDP over IPv4 and IPv6 are used if empty.
+    pub network_types: Vec<NetworkType>,
+}
+
+impl NetworkSettings {
+    pub async fn setting_engine(&self) -> Result<SettingEngine, Error> {
+        let mut setting_engine = SettingEngine::default();
+
+        if let Some((port_min, port_max)) = self.udp_port_range {
+            use webrtc_ice::udp_network::EphemeralUDP;
+
+            let ephemeral_udp = EphemeralUDP::new(port_min, port_max)
+                .map_err(|err| Error::NetworkSettings(err.into()))?;
+            setting_engine.set_udp_network(UDPNetwork::Ephemeral(ephemeral_udp));
+        }
+
+        if let Some(port) = self.udp_mux_port {
+            setting_engine.set_udp_network(self.udp_mux(port).await?);
+        }
+
+        if !self.nat_1to1_ips.is_empty() {
+            self.validate_nat_1to1_ips()?;
+            setting_engine
+                .set_nat_1to1_ips(self.nat_1to1_ips.clone(), self.nat_1to1_candidate_type);
+        }
+
+        if !self.interfaces.is_empty()
+            || !self.excluded_interfaces.is_empty()
+            || !self.ips.is_empty()
+        {
+            let interfaces = self.ip_interfaces().await?;
+            let included = self.interfaces.clone();
+            let excluded = self.excluded_interfaces.clone();
+            setting_engine.set_interface_filter(Box::new(move |name| {
+                (included.is_empty() || matches_any(&included, name))
+                    && !matches_any(&excluded, name)
+                    && (interfaces.is_empty()
+                        || interfaces.iter().any(|interface| interface == name))
+            }));
+        }
+
+        if !self.network_types.is_empty() || self.udp_mux_port.is_some() {
+            setting_engine.set_network_types(self.network_types());
+        }
+
+        Ok(setting_engine)
+    }
+
+    // The mux gathers its candidate from an arbitrary local IP of the allowed families,
+    // so it is restricted to a single family.
+    fn network_types(&self) -> Vec<NetworkType> {
+        match self.udp_mux_port {
+            Some(_) if self.network_types == [NetworkType::Udp6] => vec![NetworkType::Udp6],
+            Some(_) => vec![NetworkType::Udp4],
+            None => self.network_types.clone(),
+        }
+    }
+
+    async fn udp_mux(&self, port: u16) -> Result<UDPNetwork, Error> {
+        use anyhow::anyhow;
+        use std::net::{Ipv4Addr, Ipv6Addr};
+        use tokio::net::UdpSocket;
+        use webrtc_ice::udp_mux::{UDPMuxDefault, UDPMuxParams};
+
+        if self.udp_port_range.is_some() {
+            return Err(Error::NetworkSettings(anyhow!(
+                "the UDP mux port and the UDP port range are exclusive"
+            )));
+        }
+        let ip = if self.network_types() == [NetworkType::Udp6] {
+            IpAddr::from(Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::from(Ipv4Addr::UNSPECIFIED)
+        };
+        let socket = UdpSocket::bind((ip, port))
+            .await
+            .map_err(|err| Error::NetworkSettings(err.into()))?;
+        Ok(UDPNetwork::Muxed(UDPMuxDefault::new(UDPMuxParams::new(
+            socket,
+        ))))
+    }
+
+    // The ICE agent filters by interface name only,
+    // so the IPs are resolved to the interfaces carrying them once at startup.
+    async fn ip_interfaces(&self) -> Result<Vec<String>, Error> {
+        use anyhow::anyhow;
+        use webrtc_util::vnet::net::Net;
+
+        let all_interfaces = Net::new(None).get_interfaces().await;
+        let mut interfaces = Vec::new();
+        for ip in &self.ips {
+            let interface = all_interfaces
+                .iter()
+                .find(|interface| interface.addrs().iter().any(|addr| addr.addr() == *ip))
+                .ok_or_else(|| {
+                    Error::NetworkSettings(anyhow!("no network interface with IP {}", ip))
+                })?;
+            interfaces.push(interface.name().to_owned());
+        }
+        Ok(interfaces)
+    }
+
+    fn validate_nat_1to1_ips(&self) -> Result<(), Error> {
+        use anyhow::anyhow;
+
+        for nat_1to1_ip in &self.nat_1to1_ips {
+            let ips: Vec<&str> = nat_1to1_ip.split('/').collect();
+            if ips.len() > 2 || ips.iter().any(|ip| ip.parse::<IpAddr>().is_err()) {
+                return Err(Error::NetworkSettings(anyhow!(
+                    "invalid 1:1 NAT IP {:?}",
+                    nat_1to1_ip
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+}
@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::Error;
+
+const OGG_CONTINUED_PACKET: u8 = 0x01;
+
+// Reads Opus packets from an Ogg file loaded into memory, the header packets are skipped.
+#[derive(Clone, Debug)]
+pub struct OggReader {
+    data: Bytes,
+    offset: usize,
+    segments: VecDeque<u8>,
+    packet: BytesMut,
+}
+
+impl OggReader {
+    pub fn new(data: Bytes) -> Result<Self, Error> {
+        let mut reader = Self {
+            data,
+            offset: 0,
+            segments: VecDeque::new(),
+            packet: BytesMut::new(),
+        };
+        match reader.next_packet()? {
+            Some(packet) if packet.starts_with(b"OpusHead") => Ok(reader),
+            _ => Err(Error::InvalidMediaFile(
+                "Ogg file with Opus stream expected",
+            )),
+        }
+    }
+
+    // Returns `None` at the end of the file.
+    pub fn next_opus_packet(&mut self) -> Result<Option<Bytes>, Error> {
+        while let Some(packet) = self.next_packet()? {
+            if !packet.is_empty() && !packet.starts_with(b"OpusTags") {
+                return Ok(Some(packet));
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_packet(&mut self) -> Result<Option<Bytes>, Error> {
+        loop {
+            while let Some(lacing_value) = self.segments.pop_front() {
+                let segment_end = self.offset + usize::from(lacing_value);
+                let segment = self
+                    .data
+                    .get(self.offset..segment_end)
+                    .ok_or(Error::InvalidMediaFile("truncated Ogg page"))?;
+                self.packet.extend_from_slice(segment);
+                self.offset = segment_end;
+                // A lacing value less than 255 terminates the packet.
+                if lacing_value < 255 {
+                    return Ok(Some(self.packet.split().freeze()));
+                }
+            }
+            if !self.next_page()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    // Reads the page header, the cursor is left at the start of the page segments.
+    fn next_page(&mut self) -> Result<bool, Error> {
+        let header = match self.data.get(self.offset..self.offset + 27) {
+            Some(header) => header,
+            None => return Ok(false),
+        };
+        if &header[0..4] != b"OggS" {
+            return Err(Error::InvalidMediaFile("Ogg page signature expected"));
+        }
+        if header[5] & OGG_CONTINUED_PACKET == 0 {
+            self.packet.clear();
+        }
+        let segment_count = usize::from(header[26]);
+        let segments_start = self.offset + 27;
+        let segments_end = segments_start + segment_count;
+        self.segments = self
+            .data
+            .get(segments_start..segments_end)
+            .ok_or(Error::InvalidMediaFile("truncated Ogg page"))?
+            .iter()
+            .copied()
+            .collect();
+        self.offset = segments_end;
+        Ok(true)
+    }
+}
+
+// Returns the number of 48 kHz samples in the packet, see RFC 6716, section 3.1.
+pub fn opus_packet_samples(packet: &[u8]) -> u32 {
+    let toc = match packet.first() {
+        Some(&toc) => toc,
+        None => return 0,
+    };
+    let config = toc >> 3;
+    let frame_samples = match config {
+        0..=11 => [480, 960, 1920, 2880][usize::from(config % 4)],
+        12..=15 => [480, 960][usize::from(config % 2)],
+        _ => [120, 240, 480, 960][usize::from(config % 4)],
+    };
+    let frame_count = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map_or(0, |&count| u32::from(count & 0x3f)),
+    };
+    frame_samples * frame_count
+}
@@ -0,0 +1,126 @@
+use rtp::packet::Packet;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::Error;
+
+const OGG_BEGINNING_OF_STREAM: u8 = 0x02;
+const OGG_END_OF_STREAM: u8 = 0x04;
+const OPUS_CHANNEL_COUNT: u8 = 2;
+const OPUS_PRE_SKIP: u16 = 3840;
+const OPUS_SAMPLE_RATE: u32 = 48000;
+
+// Depacketizes an Opus track into an Ogg file, every packet is written as a separate page.
+#[derive(Debug)]
+pub struct OggWriter<W> {
+    writer: W,
+    serial: u32,
+    page_index: u32,
+    first_timestamp: Option<u32>,
+    granule_position: u64,
+}
+
+impl<W: AsyncWrite + Unpin> OggWriter<W> {
+    pub async fn new(writer: W, serial: u32) -> Result<Self, Error> {
+        let mut writer = Self {
+            writer,
+            serial,
+            page_index: 0,
+            first_timestamp: None,
+            granule_position: 0,
+        };
+        writer.write_headers().await?;
+        Ok(writer)
+    }
+
+    pub async fn write_rtp(&mut self, rtp: &Packet) -> Result<(), Error> {
+        use crate::opus_packet_samples;
+        use rtp::codecs::opus::OpusPacket;
+        use rtp::packetizer::Depacketizer;
+
+        if rtp.payload.is_empty() {
+            return Ok(());
+        }
+        let payload = OpusPacket
+            .depacketize(&rtp.payload)
+            .map_err(|err| Error::Recording(err.into()))?;
+
+        // The Opus RTP clock rate is equal to the granule position rate, the granule position of a
+        // page is the position of the last sample of its packet.
+        let first_timestamp = *self.first_timestamp.get_or_insert(rtp.header.timestamp);
+        self.granule_position = u64::from(rtp.header.timestamp.wrapping_sub(first_timestamp))
+            + u64::from(opus_packet_samples(&payload));
+        self.write_page(&payload, 0, self.granule_position).await
+    }
+
+    pub async fn finish(&mut self) -> Result<(), Error> {
+        self.write_page(&[], OGG_END_OF_STREAM, self.granule_position)
+            .await?;
+        Ok(self.writer.flush().await?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    async fn write_headers(&mut self) -> Result<(), Error> {
+        let mut opus_head = Vec::with_capacity(19);
+        opus_head.extend_from_slice(b"OpusHead");
+        opus_head.push(1);
+        opus_head.push(OPUS_CHANNEL_COUNT);
+        opus_head.extend_from_slice(&OPUS_PRE_SKIP.to_le_bytes());
+        opus_head.extend_from_slice(&OPUS_SAMPLE_RATE.to_le_bytes());
+        opus_head.extend_from_slice(&0_u16.to_le_bytes());
+        opus_head.push(0);
+        self.write_page(&opus_head, OGG_BEGINNING_OF_STREAM, 0)
+            .await?;
+
+        let vendor = env!("CARGO_PKG_NAME").as_bytes();
+        let mut opus_tags = Vec::with_capacity(16 + vendor.len());
+        opus_tags.extend_from_slice(b"OpusTags");
+        opus_tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        opus_tags.extend_from_slice(vendor);
+        opus_tags.extend_from_slice(&0_u32.to_le_bytes());
+        self.write_page(&opus_tags, 0, 0).await
+    }
+
+    async fn write_page(
+        &mut self,
+        payload: &[u8],
+        header_type: u8,
+        granule: u64,
+    ) -> Result<(), Error> {
+        let segment_count = payload.len() / 255 + 1;
+        let mut page = Vec::with_capacity(27 + segment_count + payload.len());
+        page.extend_from_slice(b"OggS");
+        page.push(0);
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.page_index.to_le_bytes());
+        page.extend_from_slice(&0_u32.to_le_bytes());
+        page.push(segment_count as u8);
+        page.extend((0..segment_count - 1).map(|_| 255));
+        page.push((payload.len() % 255) as u8);
+        page.extend_from_slice(payload);
+
+        let checksum = ogg_crc32(&page);
+        page[22..26].copy_from_slice(&checksum.to_le_bytes());
+
+        self.writer.write_all(&page).await?;
+        self.page_index += 1;
+        Ok(())
+    }
+}
+
+// CRC-32 with the 0x04c11db7 polynomial, no reflection and a zero initial value.
+fn ogg_crc32(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (u32::from(byte) << 24), |crc, _| {
+            if crc & 0x8000_0000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x04c1_1db7
+            }
+        })
+    })
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use tokio::fs::File;
+use tokio::io::BufWriter;
+
+use crate::{ChannelId, Error, TrackRecorder};
+
+// Records the incoming media of every channel into the given directory.
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&dir)?;
+        log::info!("recording to directory: {}", dir.display());
+
+        Ok(Self { dir })
+    }
+
+    // Returns `None` for codecs other than VP8 and Opus.
+    pub async fn track_recorder(
+        &self,
+        channel_id: ChannelId,
+        ssrc: u32,
+        mime_type: &str,
+    ) -> Result<Option<TrackRecorder>, Error> {
+        use crate::{IvfWriter, OggWriter};
+        use webrtc::api::media_engine::{MIME_TYPE_OPUS, MIME_TYPE_VP8};
+
+        if mime_type.eq_ignore_ascii_case(MIME_TYPE_VP8) {
+            let file = self.create_file(channel_id, ssrc, "ivf").await?;
+            Ok(Some(TrackRecorder::Video(IvfWriter::new(file))))
+        } else if mime_type.eq_ignore_ascii_case(MIME_TYPE_OPUS) {
+            let file = self.create_file(channel_id, ssrc, "ogg").await?;
+            Ok(Some(TrackRecorder::Audio(
+                OggWriter::new(file, ssrc).await?,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn create_file(
+        &self,
+        channel_id: ChannelId,
+        ssrc: u32,
+        extension: &str,
+    ) -> Result<BufWriter<File>, Error> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = self.dir.join(format!(
+            "channel-{}-{}-{}.{}",
+            channel_id, timestamp, ssrc, extension
+        ));
+        let file = File::create(&path).await?;
+        log::info!("channel {}: recording to {}", channel_id, path.display());
+        Ok(BufWriter::new(file))
+    }
+}
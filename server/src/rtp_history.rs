@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use rtp::packet::Packet;
+
+// Ring buffer of the recently forwarded packets of a track, indexed by sequence number.
+#[derive(Debug)]
+pub struct RtpHistory {
+    packets: Vec<Option<Arc<Packet>>>,
+}
+
+impl RtpHistory {
+    // The size is a power of two, so the slots stay in order when the sequence number wraps.
+    pub fn new(size: usize) -> Self {
+        let size = match size {
+            0 => 0,
+            size => size.next_power_of_two().min(usize::from(u16::MAX) + 1),
+        };
+        Self {
+            packets: vec![None; size],
+        }
+    }
+
+    pub fn push(&mut self, packet: Arc<Packet>) {
+        if let Some(index) = self.index(packet.header.sequence_number) {
+            self.packets[index] = Some(packet);
+        }
+    }
+
+    // Is `None` if the packet has been overwritten by a newer one or was never forwarded.
+    pub fn get(&self, sequence_number: u16) -> Option<Arc<Packet>> {
+        self.packets[self.index(sequence_number)?]
+            .as_ref()
+            .filter(|packet| packet.header.sequence_number == sequence_number)
+            .cloned()
+    }
+
+    fn index(&self, sequence_number: u16) -> Option<usize> {
+        match self.packets.len() {
+            0 => None,
+            len => Some(usize::from(sequence_number) % len),
+        }
+    }
+}
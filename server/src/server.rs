@@ -1,46 +1,92 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 
-use crate::{Channels, WebRtcApi};
+use crate::{Channels, Error, HttpHandler, ServerSettings, TlsConfig, WebRtcApi};
 
 #[derive(Debug)]
 pub struct Server {
-    webrtc_api: Arc<WebRtcApi>,
-    channel: Arc<Mutex<Channels>>,
+    channels: Arc<Mutex<Channels>>,
+    http_handler: Arc<HttpHandler>,
     listener: TcpListener,
+    tls_config: Option<TlsConfig>,
 }
 
 impl Server {
-    pub async fn new<Address: AsRef<str>>(addr: Address) -> Self {
-        let webrtc_api = Arc::new(WebRtcApi::new());
-        let channel = Arc::new(Mutex::new(Channels::new()));
-        let listener = TcpListener::bind(addr.as_ref()).await.unwrap();
+    pub async fn new<Address: AsRef<str>>(
+        addr: Address,
+        settings: ServerSettings,
+    ) -> Result<Self, Error> {
+        let ServerSettings {
+            tls_config,
+            static_files,
+            ice_servers,
+            turn_credentials,
+            network_settings,
+            forwarding_settings,
+            recorder,
+        } = settings;
+        let webrtc_api = Arc::new(
+            WebRtcApi::new(
+                ice_servers,
+                turn_credentials,
+                &network_settings,
+                forwarding_settings,
+            )
+            .await?,
+        );
+        let channels = Arc::new(Mutex::new(Channels::new(
+            forwarding_settings.subscriber_queue_size,
+            recorder,
+        )));
+        let http_handler = Arc::new(HttpHandler::new(
+            Arc::clone(&channels),
+            webrtc_api,
+            static_files,
+            tls_config.is_some(),
+        ));
+        let listener = TcpListener::bind(addr.as_ref()).await?;
 
-        log::info!("started on address: {}", addr.as_ref());
+        let scheme = if tls_config.is_some() { "wss" } else { "ws" };
+        log::info!("started on address: {}://{}", scheme, addr.as_ref());
 
-        Self {
-            webrtc_api,
-            channel,
+        Ok(Self {
+            channels,
+            http_handler,
             listener,
-        }
+            tls_config,
+        })
+    }
+
+    // Returns the bound address, e.g. to find out the port when binding to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn channels(&self) -> Arc<Mutex<Channels>> {
+        Arc::clone(&self.channels)
     }
 
     pub async fn run(self) {
-        use crate::Socket;
         use tokio::spawn;
         use tokio::task::JoinHandle;
 
         while let Ok((stream, addr)) = self.listener.accept().await {
-            let channel = Arc::clone(&self.channel);
-            let webrtc_api = Arc::clone(&self.webrtc_api);
-            let _: JoinHandle<()> = spawn(async move {
-                Socket::new(stream, addr, channel, webrtc_api)
-                    .await
-                    .run()
-                    .await;
-            });
+            let http_handler = Arc::clone(&self.http_handler);
+            let _join_handle: JoinHandle<()> = match &self.tls_config {
+                Some(tls_config) => {
+                    let acceptor = tls_config.acceptor();
+                    spawn(async move {
+                        match acceptor.accept(stream).await {
+                            Ok(stream) => http_handler.serve(stream, addr).await,
+                            Err(err) => log::debug!("tls {}: {}", addr, err),
+                        }
+                    })
+                }
+                None => spawn(http_handler.serve(stream, addr)),
+            };
         }
     }
 }
@@ -0,0 +1,21 @@
+use protocol::IceServer;
+
+use crate::{
+    ForwardingSettings, NetworkSettings, Recorder, StaticFiles, TlsConfig, TurnCredentials,
+};
+
+// Everything the server is configured with besides its address,
+// the default is a plain `ws://` server without ICE servers, static files and recording.
+#[derive(Debug, Default)]
+pub struct ServerSettings {
+    pub tls_config: Option<TlsConfig>,
+    // Served next to the WebSocket, e.g. the built client.
+    pub static_files: Option<StaticFiles>,
+    // Shared with the clients in the handshake.
+    pub ice_servers: Vec<IceServer>,
+    // Mints the credentials of the TURN servers among the ICE servers per session.
+    pub turn_credentials: Option<TurnCredentials>,
+    pub network_settings: NetworkSettings,
+    pub forwarding_settings: ForwardingSettings,
+    pub recorder: Option<Recorder>,
+}
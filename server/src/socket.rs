@@ -1,37 +1,103 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use futures::stream::SplitSink;
-use protocol::ClientMessage;
-use tokio::net::TcpStream;
+use hyper::upgrade::Upgraded;
+use hyper::{Body, HeaderMap, Request, Response};
+use protocol::{ClientMessage, Codec, RoomName, ServerMessage};
 use tokio::sync::Mutex;
-use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::WebSocketStream;
 
-use crate::{Channels, WebRtcApi, WebSocketReceiver};
+use crate::{Channels, Error, WebRtcApi, WebSocketReceiver, WebSocketSender};
+
+const CAPABILITIES: &[&str] = &[protocol::CAPABILITY_ROOMS];
 
 #[derive(Debug)]
 pub struct Socket {
-    websocket_sender: SplitSink<WebSocketStream<TcpStream>, Message>,
+    websocket_sender: WebSocketSender<ServerMessage>,
     websocket_receiver: WebSocketReceiver<ClientMessage>,
     addr: SocketAddr,
     channels: Arc<Mutex<Channels>>,
     webrtc_api: Arc<WebRtcApi>,
+    capabilities: Vec<String>,
+}
+
+// The role chosen by the client after the handshake.
+#[derive(Debug)]
+enum Start {
+    Receiver(RoomName),
+    Sender(RoomName),
 }
 
 impl Socket {
-    pub async fn new(
-        stream: TcpStream,
+    // Answers a WebSocket upgrade request, the socket is run once the connection is upgraded.
+    pub fn upgrade(
+        request: Request<Body>,
+        addr: SocketAddr,
+        channels: Arc<Mutex<Channels>>,
+        webrtc_api: Arc<WebRtcApi>,
+    ) -> Result<Response<Body>, Error> {
+        use hyper::header::{
+            HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
+            SEC_WEBSOCKET_PROTOCOL, UPGRADE,
+        };
+        use hyper::StatusCode;
+        use tokio::spawn;
+        use tokio::task::JoinHandle;
+        use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+
+        let key = request
+            .headers()
+            .get(SEC_WEBSOCKET_KEY)
+            .ok_or(Error::WebSocketKeyExpected)?;
+        let accept_key = derive_accept_key(key.as_bytes());
+        let codec = negotiate_subprotocol(request.headers());
+
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        let headers = response.headers_mut();
+        let _: Option<_> = headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        let _: Option<_> = headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        let _: Option<_> = headers.insert(
+            SEC_WEBSOCKET_ACCEPT,
+            HeaderValue::from_str(&accept_key).expect("accept key is a valid header value"),
+        );
+        if let Some(codec) = codec {
+            let _: Option<_> = headers.insert(
+                SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static(codec.subprotocol()),
+            );
+        }
+
+        let _join_handle: JoinHandle<()> = spawn(async move {
+            use tokio_tungstenite::tungstenite::protocol::Role;
+
+            match hyper::upgrade::on(request).await {
+                Ok(upgraded) => {
+                    let websocket =
+                        WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                    Self::new(websocket, codec, addr, channels, webrtc_api)
+                        .run()
+                        .await;
+                }
+                Err(err) => log::error!("socket {}: upgrade failed: {}", addr, err),
+            }
+        });
+
+        Ok(response)
+    }
+
+    fn new(
+        websocket: WebSocketStream<Upgraded>,
+        codec: Option<Codec>,
         addr: SocketAddr,
         channels: Arc<Mutex<Channels>>,
         webrtc_api: Arc<WebRtcApi>,
     ) -> Self {
         use futures::StreamExt;
-        use tokio_tungstenite::accept_async;
 
-        let websocket = accept_async(stream).await.unwrap();
         let (websocket_sender, websocket_receiver) = websocket.split();
-        let websocket_receiver = WebSocketReceiver::new(websocket_receiver);
+        let websocket_sender = WebSocketSender::new(websocket_sender, codec.unwrap_or_default());
+        let websocket_receiver = WebSocketReceiver::new(websocket_receiver, codec);
 
         Self {
             channels,
@@ -39,43 +105,163 @@ impl Socket {
             websocket_receiver,
             addr,
             webrtc_api,
+            capabilities: Vec::new(),
         }
     }
 
     pub async fn run(mut self) {
-        use crate::{SocketReceiver, SocketSender};
-
         let addr = self.addr;
         log::info!("socket {}: opened", addr);
 
-        match self.websocket_receiver.recv().await {
-            Some(ClientMessage::StartReceiver) => {
+        let result = match self.handshake().await {
+            Ok(true) => self.recv_start().await,
+            Ok(false) => Ok(None),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(Some(start)) => self.start(start).await,
+            Ok(None) => {}
+            Err(err) => {
+                log::error!("socket {}: {}", addr, err);
+                let message = ServerMessage::Error {
+                    code: err.code(),
+                    reason: err.to_string(),
+                };
+                // The socket is being closed anyway, so a failure here is not worth reporting.
+                let _: Result<(), Error> = self.websocket_sender.send(message).await;
+                self.websocket_sender.close().await;
+                self.websocket_receiver.wait_closed().await;
+            }
+        }
+
+        log::info!("socket {}: closed", addr);
+    }
+
+    // Returns `false` if the socket was closed before the handshake.
+    async fn handshake(&mut self) -> Result<bool, Error> {
+        use protocol::PROTOCOL_VERSION;
+
+        let message = self.websocket_receiver.recv().await;
+        // The codec may have been chosen by the first frame.
+        if let Some(codec) = self.websocket_receiver.codec() {
+            self.websocket_sender.set_codec(codec);
+        }
+
+        let (protocol_version, capabilities) = match message? {
+            Some(ClientMessage::Hello {
+                protocol_version,
+                capabilities,
+            }) => (protocol_version, capabilities),
+            Some(_) => return Err(Error::HandshakeExpected),
+            None => return Ok(false),
+        };
+
+        if protocol_version != PROTOCOL_VERSION {
+            return Err(Error::ProtocolVersion(protocol_version));
+        }
+
+        self.capabilities = capabilities
+            .into_iter()
+            .filter(|capability| CAPABILITIES.contains(&capability.as_str()))
+            .collect();
+        log::debug!(
+            "socket {}: negotiated capabilities: {:?}",
+            self.addr,
+            self.capabilities
+        );
+
+        self.websocket_sender
+            .send(ServerMessage::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: CAPABILITIES
+                    .iter()
+                    .map(|&capability| capability.to_owned())
+                    .collect(),
+                ice_servers: self.webrtc_api.ice_servers(),
+            })
+            .await?;
+
+        Ok(true)
+    }
+
+    // Returns `None` if the socket was closed before the start message.
+    async fn recv_start(&mut self) -> Result<Option<Start>, Error> {
+        match self.websocket_receiver.recv().await? {
+            Some(ClientMessage::StartReceiver(room)) => Ok(Some(Start::Receiver(room))),
+            Some(ClientMessage::StartSender(room)) => Ok(Some(Start::Sender(room))),
+            Some(ClientMessage::Hello { .. }) => Err(Error::UnexpectedHandshake),
+            None => Ok(None),
+        }
+    }
+
+    // The sender and receiver sockets report their errors to the client themselves.
+    async fn start(self, start: Start) {
+        use crate::{SocketReceiver, SocketSender};
+
+        match start {
+            Start::Receiver(room) => {
+                let room = self.room(room);
+                let codec = self.websocket_receiver.codec().unwrap_or_default();
                 SocketReceiver::new(
-                    self.websocket_sender,
+                    self.websocket_sender.into_sink(),
                     self.websocket_receiver.into_stream(),
+                    codec,
                     self.addr,
+                    room,
                     self.channels,
                     self.webrtc_api,
                 )
-                .await
                 .run()
                 .await
             }
-            Some(ClientMessage::StartSender) => {
+            Start::Sender(room) => {
+                let room = self.room(room);
+                let codec = self.websocket_receiver.codec().unwrap_or_default();
                 SocketSender::new(
-                    self.websocket_sender,
+                    self.websocket_sender.into_sink(),
                     self.websocket_receiver.into_stream(),
+                    codec,
                     self.addr,
+                    room,
                     self.channels,
                     self.webrtc_api,
                 )
-                .await
                 .run()
                 .await
             }
-            None => {}
         }
+    }
 
-        log::info!("socket {}: closed", addr);
+    // Clients without rooms support share the default room.
+    fn room(&self, room: RoomName) -> RoomName {
+        use protocol::{CAPABILITY_ROOMS, DEFAULT_ROOM};
+
+        if self.has_capability(CAPABILITY_ROOMS) {
+            room
+        } else {
+            log::debug!(
+                "socket {}: no rooms support, room {:?} replaced by {:?}",
+                self.addr,
+                room.0,
+                DEFAULT_ROOM
+            );
+            RoomName(DEFAULT_ROOM.to_owned())
+        }
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|item| item == capability)
     }
 }
+
+// Picks the first of the subprotocols requested by the client that is supported by the server.
+fn negotiate_subprotocol(headers: &HeaderMap) -> Option<Codec> {
+    use hyper::header::SEC_WEBSOCKET_PROTOCOL;
+
+    headers
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|subprotocol| Codec::from_subprotocol(subprotocol.trim()))
+}
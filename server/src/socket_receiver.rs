@@ -2,65 +2,122 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use futures::stream::{SplitSink, SplitStream};
-use protocol::ClientSenderMessage;
-use tokio::net::TcpStream;
+use hyper::upgrade::Upgraded;
+use protocol::{ClientSenderMessage, Codec, RoomName, ServerReceiverMessage};
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::WebSocketStream;
 
-use crate::{Channels, WebRtcApi, WebRtcReceiver, WebSocketReceiver};
+use crate::{
+    ChannelId, ChannelSender, Channels, Error, WebRtcApi, WebRtcReceiver, WebSocketReceiver,
+    WebSocketSender,
+};
 
 #[derive(Debug)]
 pub struct SocketReceiver {
     addr: SocketAddr,
+    room: RoomName,
+    channels: Arc<Mutex<Channels>>,
+    webrtc_api: Arc<WebRtcApi>,
+    websocket_sender: Arc<Mutex<WebSocketSender<ServerReceiverMessage>>>,
     websocket_receiver: WebSocketReceiver<ClientSenderMessage>,
-    webrtc_receiver: Arc<WebRtcReceiver>,
+    channel_id: Option<ChannelId>,
 }
 
 impl SocketReceiver {
-    pub async fn new(
-        websocket_sender: SplitSink<WebSocketStream<TcpStream>, Message>,
-        websocket_receiver: SplitStream<WebSocketStream<TcpStream>>,
+    pub fn new(
+        websocket_sender: SplitSink<WebSocketStream<Upgraded>, Message>,
+        websocket_receiver: SplitStream<WebSocketStream<Upgraded>>,
+        codec: Codec,
         addr: SocketAddr,
+        room: RoomName,
         channels: Arc<Mutex<Channels>>,
         webrtc_api: Arc<WebRtcApi>,
     ) -> Self {
-        use crate::WebSocketSender;
-
-        let channel_sender = channels.lock().await.sender();
-        let websocket_sender = WebSocketSender::new(websocket_sender);
-        let websocket_receiver = WebSocketReceiver::new(websocket_receiver);
-        let webrtc_receiver =
-            WebRtcReceiver::new(webrtc_api, channel_sender, websocket_sender).await;
+        let websocket_sender = Arc::new(Mutex::new(WebSocketSender::new(websocket_sender, codec)));
+        let websocket_receiver = WebSocketReceiver::new(websocket_receiver, Some(codec));
 
         Self {
             addr,
+            room,
+            channels,
+            webrtc_api,
+            websocket_sender,
             websocket_receiver,
-            webrtc_receiver,
+            channel_id: None,
         }
     }
 
     pub async fn run(mut self) {
         let addr = self.addr;
-        log::info!("receiver socket {}: opened", addr);
+        log::info!("receiver socket {}: opened in room {:?}", addr, self.room.0);
+
+        if let Err(err) = self.start().await {
+            match self.channel_id {
+                Some(channel_id) => {
+                    log::error!("channel {}: receiver socket {}: {}", channel_id, addr, err)
+                }
+                None => log::error!("receiver socket {}: {}", addr, err),
+            }
+            self.send_error(&err).await;
+        }
+
+        self.websocket_sender.lock().await.close().await;
+        self.websocket_receiver.wait_closed().await;
+
+        log::info!("receiver socket {}: closed", addr);
+    }
 
-        while let Some(message) = self.websocket_receiver.recv().await {
-            log::debug!("receiver socket {}: message: {:?}", addr, message);
+    async fn start(&mut self) -> Result<(), Error> {
+        let channel_sender = self.channels.lock().await.sender(&self.room)?;
+        self.channel_id = Some(channel_sender.channel_id());
+
+        let result = self.connect(channel_sender).await;
+        self.channels.lock().await.remove_sender(&self.room);
+        result
+    }
+
+    async fn connect(&mut self, channel_sender: ChannelSender) -> Result<(), Error> {
+        let webrtc_receiver = WebRtcReceiver::new(
+            Arc::clone(&self.webrtc_api),
+            channel_sender,
+            Some(Arc::clone(&self.websocket_sender)),
+        )
+        .await?;
+
+        let result = self.handle_messages(&webrtc_receiver).await;
+        webrtc_receiver.close().await;
+        result
+    }
+
+    async fn handle_messages(
+        &mut self,
+        webrtc_receiver: &Arc<WebRtcReceiver>,
+    ) -> Result<(), Error> {
+        while let Some(message) = self.websocket_receiver.recv().await? {
+            log::debug!("receiver socket {}: message: {:?}", self.addr, message);
             match message {
                 ClientSenderMessage::Offer(offer) => {
-                    self.webrtc_receiver.on_offer(offer).await;
+                    webrtc_receiver.on_offer(offer).await?;
                 }
                 ClientSenderMessage::IceCandidate(candidate) => {
-                    self.webrtc_receiver.on_remote_icecandidate(candidate).await;
+                    webrtc_receiver.on_remote_icecandidate(candidate).await?;
                 }
                 ClientSenderMessage::AllIceCandidatesSent => {
-                    self.webrtc_receiver
-                        .on_all_remote_icecandidates_sent()
-                        .await;
+                    webrtc_receiver.on_all_remote_icecandidates_sent().await;
                 }
+                ClientSenderMessage::Bye => break,
             }
         }
+        Ok(())
+    }
 
-        log::info!("receiver socket {}: closed", addr);
+    async fn send_error(&self, err: &Error) {
+        let message = ServerReceiverMessage::Error {
+            code: err.code(),
+            reason: err.to_string(),
+        };
+        // The session is being closed anyway, so a failure here is not worth reporting.
+        let _: Result<(), Error> = self.websocket_sender.lock().await.send(message).await;
     }
 }
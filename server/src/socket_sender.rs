@@ -2,62 +2,119 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use futures::stream::{SplitSink, SplitStream};
-use protocol::ClientReceiverMessage;
-use tokio::net::TcpStream;
+use hyper::upgrade::Upgraded;
+use protocol::{ClientReceiverMessage, Codec, RoomName, ServerSenderMessage};
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::WebSocketStream;
 
-use crate::{Channels, WebRtcApi, WebRtcSender, WebSocketReceiver};
+use crate::{
+    ChannelId, ChannelReceiver, Channels, Error, WebRtcApi, WebRtcSender, WebSocketReceiver,
+    WebSocketSender,
+};
 
 #[derive(Debug)]
 pub struct SocketSender {
     addr: SocketAddr,
+    room: RoomName,
+    channels: Arc<Mutex<Channels>>,
+    webrtc_api: Arc<WebRtcApi>,
+    websocket_sender: Arc<Mutex<WebSocketSender<ServerSenderMessage>>>,
     websocket_receiver: WebSocketReceiver<ClientReceiverMessage>,
-    webrtc_sender: Arc<WebRtcSender>,
+    channel_id: Option<ChannelId>,
 }
 
 impl SocketSender {
-    pub async fn new(
-        websocket_sender: SplitSink<WebSocketStream<TcpStream>, Message>,
-        websocket_receiver: SplitStream<WebSocketStream<TcpStream>>,
+    pub fn new(
+        websocket_sender: SplitSink<WebSocketStream<Upgraded>, Message>,
+        websocket_receiver: SplitStream<WebSocketStream<Upgraded>>,
+        codec: Codec,
         addr: SocketAddr,
+        room: RoomName,
         channels: Arc<Mutex<Channels>>,
         webrtc_api: Arc<WebRtcApi>,
     ) -> Self {
-        use crate::WebSocketSender;
-
-        let channel_receiver = channels.lock().await.receiver();
-        let websocket_sender = WebSocketSender::new(websocket_sender);
-        let websocket_receiver = WebSocketReceiver::new(websocket_receiver);
-        let webrtc_sender = WebRtcSender::new(webrtc_api, channel_receiver, websocket_sender).await;
+        let websocket_sender = Arc::new(Mutex::new(WebSocketSender::new(websocket_sender, codec)));
+        let websocket_receiver = WebSocketReceiver::new(websocket_receiver, Some(codec));
 
         Self {
             addr,
+            room,
+            channels,
+            webrtc_api,
+            websocket_sender,
             websocket_receiver,
-            webrtc_sender,
+            channel_id: None,
         }
     }
 
     pub async fn run(mut self) {
         let addr = self.addr;
-        log::info!("sender socket {}: opened", addr);
+        log::info!("sender socket {}: opened in room {:?}", addr, self.room.0);
+
+        if let Err(err) = self.start().await {
+            match self.channel_id {
+                Some(channel_id) => {
+                    log::error!("channel {}: sender socket {}: {}", channel_id, addr, err)
+                }
+                None => log::error!("sender socket {}: {}", addr, err),
+            }
+            self.send_error(&err).await;
+        }
+
+        self.websocket_sender.lock().await.close().await;
+        self.websocket_receiver.wait_closed().await;
+
+        log::info!("sender socket {}: closed", addr);
+    }
 
-        while let Some(message) = self.websocket_receiver.recv().await {
-            log::debug!("sender socket {}: message: {:?}", addr, message);
+    async fn start(&mut self) -> Result<(), Error> {
+        let channel_receiver = self.channels.lock().await.receiver(&self.room);
+        self.channel_id = Some(channel_receiver.channel_id());
+
+        let result = self.connect(channel_receiver).await;
+        self.channels.lock().await.remove_receiver(&self.room);
+        result
+    }
+
+    async fn connect(&mut self, channel_receiver: ChannelReceiver) -> Result<(), Error> {
+        let webrtc_sender = WebRtcSender::new(
+            Arc::clone(&self.webrtc_api),
+            channel_receiver,
+            Some(Arc::clone(&self.websocket_sender)),
+        )
+        .await?;
+
+        let result = self.handle_messages(&webrtc_sender).await;
+        webrtc_sender.close().await;
+        result
+    }
+
+    async fn handle_messages(&mut self, webrtc_sender: &Arc<WebRtcSender>) -> Result<(), Error> {
+        while let Some(message) = self.websocket_receiver.recv().await? {
+            log::debug!("sender socket {}: message: {:?}", self.addr, message);
             match message {
                 ClientReceiverMessage::Answer(offer) => {
-                    self.webrtc_sender.on_answer(offer).await;
+                    webrtc_sender.on_answer(offer).await?;
                 }
                 ClientReceiverMessage::IceCandidate(candidate) => {
-                    self.webrtc_sender.on_remote_icecandidate(candidate).await;
+                    webrtc_sender.on_remote_icecandidate(candidate).await?;
                 }
                 ClientReceiverMessage::AllIceCandidatesSent => {
-                    self.webrtc_sender.on_all_remote_icecandidates_sent().await;
+                    webrtc_sender.on_all_remote_icecandidates_sent().await;
                 }
+                ClientReceiverMessage::Bye => break,
             }
         }
+        Ok(())
+    }
 
-        log::info!("sender socket {}: closed", addr);
+    async fn send_error(&self, err: &Error) {
+        let message = ServerSenderMessage::Error {
+            code: err.code(),
+            reason: err.to_string(),
+        };
+        // The session is being closed anyway, so a failure here is not worth reporting.
+        let _: Result<(), Error> = self.websocket_sender.lock().await.send(message).await;
     }
 }
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use hyper::{Body, Response, StatusCode};
+
+use crate::Error;
+
+const CONFIG_PATH: &str = "/params.js";
+
+// Serves the built client, e.g. `client/dist`, on the same port as the signaling,
+// so a deployment is a single process.
+#[derive(Clone, Debug)]
+pub struct StaticFiles {
+    dir: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new(dir: PathBuf) -> Result<Self, Error> {
+        use std::fs::metadata;
+
+        // Fail at startup rather than with a 404 on every request.
+        let _: std::fs::Metadata = metadata(dir.join("index.html"))?;
+        Ok(Self { dir })
+    }
+
+    // Returns `None` if there is no such file.
+    pub async fn response(
+        &self,
+        path: &str,
+        host: Option<&str>,
+        secure: bool,
+    ) -> Result<Option<Response<Body>>, Error> {
+        use hyper::header::{HeaderValue, CONTENT_TYPE};
+        use percent_encoding::percent_decode_str;
+        use std::io::ErrorKind;
+        use tokio::fs::{metadata, read};
+
+        if path == CONFIG_PATH {
+            return Ok(Some(config_response(host, secure)));
+        }
+        // No file name is invalid UTF-8 or contains NUL.
+        let decoded_path = match percent_decode_str(path).decode_utf8() {
+            Ok(decoded_path) if !decoded_path.contains('\0') => decoded_path,
+            _ => return Ok(Some(bad_request_response())),
+        };
+        let file_path = match self.file_path(&decoded_path) {
+            Some(file_path) => file_path,
+            None => return Ok(None),
+        };
+        // The relative links of a directory index only resolve with the trailing slash.
+        // The leading slashes are collapsed, `//host` would redirect to another host.
+        match metadata(&file_path).await {
+            Ok(metadata) if metadata.is_dir() => {
+                let location = format!("/{}/", path.trim_start_matches('/'));
+                return Ok(Some(redirect_response(&location)));
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let content = match read(&file_path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut response = Response::new(Body::from(content));
+        let _: Option<_> = response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(content_type(&file_path)),
+        );
+        Ok(Some(response))
+    }
+
+    // Rejects the paths escaping the directory, `path` is percent-decoded.
+    fn file_path(&self, path: &str) -> Option<PathBuf> {
+        let mut file_path = self.dir.clone();
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            if segment == "." || segment == ".." || segment.contains('\\') {
+                return None;
+            }
+            file_path.push(segment);
+        }
+        if path.ends_with('/') {
+            file_path.push("index.html");
+        }
+        Some(file_path)
+    }
+}
+
+// Points the client to the WebSocket on the same host and port it was loaded from.
+fn config_response(host: Option<&str>, secure: bool) -> Response<Body> {
+    use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+    use protocol::WEBSOCKET_PATH;
+
+    let host = match host {
+        Some(host) => host,
+        None => return bad_request_response(),
+    };
+    let scheme = if secure { "wss" } else { "ws" };
+    let server_address = format!("{}://{}{}", scheme, host, WEBSOCKET_PATH);
+    // The host is a header value, so it is visible ASCII and debug formatting is valid JavaScript.
+    let config = format!("window.server_address = {:?};\n", server_address);
+
+    let mut response = Response::new(Body::from(config));
+    let headers = response.headers_mut();
+    let _: Option<_> = headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/javascript; charset=utf-8"),
+    );
+    let _: Option<_> = headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+fn bad_request_response() -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::BAD_REQUEST;
+    response
+}
+
+fn redirect_response(location: &str) -> Response<Body> {
+    use hyper::header::{HeaderValue, LOCATION};
+
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::MOVED_PERMANENTLY;
+    // The location is the request path, which hyper has already checked to be valid.
+    if let Ok(location) = HeaderValue::from_str(location) {
+        let _: Option<_> = response.headers_mut().insert(LOCATION, location);
+    }
+    response
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
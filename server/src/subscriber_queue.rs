@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{Sender, UnboundedSender};
+use tokio::sync::Notify;
+
+use crate::{ChannelId, ChannelMessage, DropCounters};
+
+// The sending end of the queues of a subscriber, a slow subscriber loses media but not data.
+// Media is bounded so a stalled subscriber can't grow the memory of the server,
+// data is rare and must be delivered.
+#[derive(Debug)]
+pub struct SubscriberQueue {
+    channel_id: ChannelId,
+    media: Sender<ChannelMessage>,
+    data: UnboundedSender<ChannelMessage>,
+    keyframe_requests: Arc<Notify>,
+    dropped: Arc<DropCounters>,
+    // Video can only be decoded again from a keyframe once a packet is lost.
+    waiting_for_keyframe: bool,
+}
+
+impl SubscriberQueue {
+    pub fn new(
+        channel_id: ChannelId,
+        media: Sender<ChannelMessage>,
+        data: UnboundedSender<ChannelMessage>,
+        keyframe_requests: Arc<Notify>,
+        dropped: Arc<DropCounters>,
+    ) -> Self {
+        Self {
+            channel_id,
+            media,
+            data,
+            keyframe_requests,
+            dropped,
+            waiting_for_keyframe: false,
+        }
+    }
+
+    // Returns false once the subscriber is gone.
+    pub fn send(&mut self, message: ChannelMessage) -> bool {
+        use tokio::sync::mpsc::error::TrySendError;
+
+        let is_video = match &message {
+            ChannelMessage::Data(_) => return self.data.send(message).is_ok(),
+            ChannelMessage::Video(_) => true,
+            ChannelMessage::Audio(_) => false,
+        };
+        if is_video && self.waiting_for_keyframe {
+            if !message.is_keyframe() {
+                self.dropped.add_video();
+                return !self.media.is_closed();
+            }
+            self.waiting_for_keyframe = false;
+        }
+        match self.media.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) if is_video => {
+                if !self.waiting_for_keyframe {
+                    log::warn!(
+                        "channel {}: subscriber queue full, dropping video until the next keyframe",
+                        self.channel_id
+                    );
+                    self.waiting_for_keyframe = true;
+                    self.keyframe_requests.notify_one();
+                }
+                self.dropped.add_video();
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                self.dropped.add_audio();
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.media.is_closed()
+    }
+}
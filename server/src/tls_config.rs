@@ -0,0 +1,79 @@
+use core::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::{Certificate, NoClientAuth, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::Error;
+
+// TLS termination of the signaling listener, so the clients connect with `wss://` and `https://`.
+#[derive(Clone)]
+pub struct TlsConfig {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsConfig {
+    // Reads a PEM certificate chain and a PEM PKCS#8 or RSA private key,
+    // e.g. `fullchain.pem` and `privkey.pem` from Let's Encrypt.
+    pub fn from_pem_files(cert_path: &Path, key_path: &Path) -> Result<Self, Error> {
+        use anyhow::anyhow;
+        use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+        use std::fs::read;
+
+        let cert_chain = certs(&mut read(cert_path)?.as_slice())
+            .map_err(|()| Error::Tls(anyhow!("invalid certificate file {:?}", cert_path)))?;
+        if cert_chain.is_empty() {
+            return Err(Error::Tls(anyhow!("no certificates in {:?}", cert_path)));
+        }
+
+        let key = read(key_path)?;
+        let mut keys = pkcs8_private_keys(&mut key.as_slice())
+            .map_err(|()| Error::Tls(anyhow!("invalid private key file {:?}", key_path)))?;
+        if keys.is_empty() {
+            keys = rsa_private_keys(&mut key.as_slice())
+                .map_err(|()| Error::Tls(anyhow!("invalid private key file {:?}", key_path)))?;
+        }
+        let key = keys
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Tls(anyhow!("no private key in {:?}", key_path)))?;
+
+        Self::new(cert_chain, key)
+    }
+
+    // Generates a self-signed certificate for development,
+    // browsers accept it after confirming the security warning once.
+    pub fn self_signed(subject_alt_names: Vec<String>) -> Result<Self, Error> {
+        use rcgen::generate_simple_self_signed;
+
+        let cert =
+            generate_simple_self_signed(subject_alt_names).map_err(|err| Error::Tls(err.into()))?;
+        let cert_der = cert.serialize_der().map_err(|err| Error::Tls(err.into()))?;
+        let key_der = cert.serialize_private_key_der();
+
+        Self::new(vec![Certificate(cert_der)], PrivateKey(key_der))
+    }
+
+    fn new(cert_chain: Vec<Certificate>, key: PrivateKey) -> Result<Self, Error> {
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config
+            .set_single_cert(cert_chain, key)
+            .map_err(|err| Error::Tls(err.into()))?;
+        config.set_protocols(&[b"http/1.1".to_vec()]);
+
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(Arc::clone(&self.config))
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig").finish_non_exhaustive()
+    }
+}
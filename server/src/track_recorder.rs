@@ -0,0 +1,27 @@
+use rtp::packet::Packet;
+use tokio::fs::File;
+use tokio::io::BufWriter;
+
+use crate::{Error, IvfWriter, OggWriter};
+
+#[derive(Debug)]
+pub enum TrackRecorder {
+    Video(IvfWriter<BufWriter<File>>),
+    Audio(OggWriter<BufWriter<File>>),
+}
+
+impl TrackRecorder {
+    pub async fn write_rtp(&mut self, rtp: &Packet) -> Result<(), Error> {
+        match self {
+            Self::Video(writer) => writer.write_rtp(rtp).await,
+            Self::Audio(writer) => writer.write_rtp(rtp).await,
+        }
+    }
+
+    pub async fn finish(&mut self) -> Result<(), Error> {
+        match self {
+            Self::Video(writer) => writer.finish().await,
+            Self::Audio(writer) => writer.finish().await,
+        }
+    }
+}
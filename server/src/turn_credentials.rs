@@ -0,0 +1,59 @@
+use core::fmt;
+use core::time::Duration;
+
+use protocol::IceServer;
+
+// Mints time-limited credentials for TURN servers sharing a secret with this server,
+// e.g. coturn with `use-auth-secret` and `static-auth-secret`.
+// The username is `expiry-timestamp:user` and the credential is its base64 HMAC-SHA1,
+// so the long-lived secret itself never reaches the clients.
+#[derive(Clone)]
+pub struct TurnCredentials {
+    urls: Vec<String>,
+    secret: String,
+    ttl: Duration,
+}
+
+impl TurnCredentials {
+    pub fn new(urls: Vec<String>, secret: String, ttl: Duration) -> Self {
+        Self { urls, secret, ttl }
+    }
+
+    // Every call mints credentials for a new random user, so sessions do not share them.
+    pub fn ice_server(&self) -> IceServer {
+        use std::time::SystemTime;
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let expiry = (now + self.ttl).as_secs();
+        let username = format!("{}:{:016x}", expiry, rand::random::<u64>());
+        let credential = self.credential(&username);
+
+        IceServer {
+            urls: self.urls.clone(),
+            username: Some(username),
+            credential: Some(credential),
+        }
+    }
+
+    pub fn credential(&self, username: &str) -> String {
+        turn_credential(&self.secret, username)
+    }
+}
+
+pub fn turn_credential(secret: &str, username: &str) -> String {
+    use ring::hmac;
+
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret.as_bytes());
+    base64::encode(hmac::sign(&key, username.as_bytes()))
+}
+
+impl fmt::Debug for TurnCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TurnCredentials")
+            .field("urls", &self.urls)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,265 @@
+use core::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+use webrtc_util::Conn;
+
+use crate::Error;
+
+const REALM: &str = "webrtc-server";
+
+// An embedded STUN and TURN server listening on a single UDP port and the same TCP port,
+// it accepts the time-limited credentials minted by `TurnCredentials` with the same secret.
+// TCP is the fallback for clients in networks that block UDP, as webrtc-ice has no ICE-TCP.
+pub struct TurnServer {
+    server: turn::server::Server,
+    public_addr: SocketAddr,
+}
+
+impl TurnServer {
+    // Relays are allocated on ephemeral UDP ports of `public_ip`. They only reach public peers
+    // unless `allow_private_peers` is set, e.g. for a media server on a private network,
+    // otherwise any client with credentials could reach the services of the host and its network.
+    pub async fn new(
+        port: u16,
+        public_ip: IpAddr,
+        secret: String,
+        allow_private_peers: bool,
+    ) -> Result<Self, Error> {
+        use core::time::Duration;
+        use std::sync::Arc;
+        use tokio::net::TcpListener;
+        use turn::server::config::{ConnConfig, ServerConfig};
+
+        use crate::TurnTcpConn;
+
+        let conn = Arc::new(UdpSocket::bind((unspecified_ip(public_ip), port)).await?);
+        let public_addr = SocketAddr::new(public_ip, conn.local_addr()?.port());
+        let listener = TcpListener::bind((unspecified_ip(public_ip), public_addr.port())).await?;
+        let tcp_conn = Arc::new(TurnTcpConn::new(listener)?);
+        let relay_addr_generator = || {
+            Box::new(RelayAddressGenerator {
+                public_ip,
+                allow_private_peers,
+            })
+        };
+        let server = turn::server::Server::new(ServerConfig {
+            conn_configs: vec![
+                ConnConfig {
+                    conn,
+                    relay_addr_generator: relay_addr_generator(),
+                },
+                ConnConfig {
+                    conn: tcp_conn,
+                    relay_addr_generator: relay_addr_generator(),
+                },
+            ],
+            realm: REALM.to_owned(),
+            auth_handler: Arc::new(AuthHandler { secret }),
+            channel_bind_timeout: Duration::from_secs(0),
+        })
+        .await
+        .map_err(|err| Error::Turn(err.into()))?;
+
+        log::info!("turn server started on address: {}", public_addr);
+
+        Ok(Self {
+            server,
+            public_addr,
+        })
+    }
+
+    pub fn public_addr(&self) -> SocketAddr {
+        self.public_addr
+    }
+
+    pub fn stun_url(&self) -> String {
+        format!("stun:{}", self.public_addr)
+    }
+
+    pub fn turn_url(&self) -> String {
+        format!("turn:{}?transport=udp", self.public_addr)
+    }
+
+    pub fn turn_tcp_url(&self) -> String {
+        format!("turn:{}?transport=tcp", self.public_addr)
+    }
+
+    pub async fn close(&self) {
+        if let Err(err) = self.server.close().await {
+            log::warn!("turn server close failed: {}", err);
+        }
+    }
+}
+
+impl fmt::Debug for TurnServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TurnServer")
+            .field("public_addr", &self.public_addr)
+            .finish_non_exhaustive()
+    }
+}
+
+struct AuthHandler {
+    secret: String,
+}
+
+impl turn::auth::AuthHandler for AuthHandler {
+    // The username is `expiry-timestamp:user`, expired usernames are rejected.
+    fn auth_handle(
+        &self,
+        username: &str,
+        realm: &str,
+        src_addr: SocketAddr,
+    ) -> Result<Vec<u8>, turn::Error> {
+        use std::time::SystemTime;
+        use turn::auth::generate_auth_key;
+
+        let expiry = username
+            .split(':')
+            .next()
+            .and_then(|expiry| expiry.parse::<u64>().ok())
+            .ok_or_else(|| {
+                turn::Error::Other(format!(
+                    "invalid TURN username {:?} from {}",
+                    username, src_addr
+                ))
+            })?;
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        if expiry < now.as_secs() {
+            return Err(turn::Error::Other(format!(
+                "expired TURN username {:?} from {}",
+                username, src_addr
+            )));
+        }
+
+        let password = crate::turn_credential(&self.secret, username);
+        Ok(generate_auth_key(username, realm, &password))
+    }
+}
+
+// Binds the relays to the address family of the public IP, whatever the client asks for.
+struct RelayAddressGenerator {
+    public_ip: IpAddr,
+    allow_private_peers: bool,
+}
+
+#[async_trait]
+impl turn::relay::RelayAddressGenerator for RelayAddressGenerator {
+    fn validate(&self) -> Result<(), turn::Error> {
+        Ok(())
+    }
+
+    async fn allocate_conn(
+        &self,
+        _: bool,
+        requested_port: u16,
+    ) -> Result<(std::sync::Arc<dyn Conn + Send + Sync>, SocketAddr), turn::Error> {
+        use std::sync::Arc;
+
+        let socket = UdpSocket::bind((unspecified_ip(self.public_ip), requested_port)).await?;
+        let mut relay_addr = socket.local_addr()?;
+        relay_addr.set_ip(self.public_ip);
+        let conn = RelayConn {
+            socket,
+            allow_private_peers: self.allow_private_peers,
+        };
+        Ok((Arc::new(conn), relay_addr))
+    }
+}
+
+// The relay socket of an allocation, the traffic to and from denied peers is dropped.
+struct RelayConn {
+    socket: UdpSocket,
+    allow_private_peers: bool,
+}
+
+impl RelayConn {
+    fn is_allowed(&self, peer: SocketAddr) -> bool {
+        self.allow_private_peers || is_public_ip(peer.ip())
+    }
+}
+
+#[async_trait]
+impl Conn for RelayConn {
+    // The relays are never connected, they serve all the peers with a permission.
+    async fn connect(&self, _: SocketAddr) -> Result<(), webrtc_util::Error> {
+        Err(webrtc_util::Error::Other(
+            "turn relay: connect is not supported".to_owned(),
+        ))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> Result<usize, webrtc_util::Error> {
+        Ok(self.recv_from(buf).await?.0)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), webrtc_util::Error> {
+        loop {
+            let (len, peer) = self.socket.recv_from(buf).await?;
+            if self.is_allowed(peer) {
+                return Ok((len, peer));
+            }
+            log::debug!("turn relay: dropped packet from denied peer {}", peer);
+        }
+    }
+
+    async fn send(&self, _: &[u8]) -> Result<usize, webrtc_util::Error> {
+        Err(webrtc_util::Error::Other(
+            "turn relay: send without a peer is not supported".to_owned(),
+        ))
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize, webrtc_util::Error> {
+        if !self.is_allowed(target) {
+            return Err(webrtc_util::Error::Other(format!(
+                "turn relay: denied peer {}",
+                target
+            )));
+        }
+        Ok(self.socket.send_to(buf, target).await?)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, webrtc_util::Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    async fn close(&self) -> Result<(), webrtc_util::Error> {
+        Ok(())
+    }
+}
+
+fn unspecified_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
+
+// Is false for loopback, link-local, private and unique local addresses, also IPv4-mapped.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_private()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            if segments[..5] == [0; 5] && segments[5] == 0xffff {
+                let [a, b] = segments[6].to_be_bytes();
+                let [c, d] = segments[7].to_be_bytes();
+                return is_public_ip(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+            }
+            let link_local = segments[0] & 0xffc0 == 0xfe80;
+            let unique_local = segments[0] & 0xfe00 == 0xfc00;
+            !(ip.is_loopback() || ip.is_unspecified() || link_local || unique_local)
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::io::AsyncRead;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch, Mutex};
+use webrtc_util::Conn;
+
+type Writers = Arc<Mutex<HashMap<SocketAddr, Arc<Mutex<OwnedWriteHalf>>>>>;
+
+// The TURN clients connected over TCP for UDP-blocked networks (RFC 5766, section 2.1).
+// The STUN and ChannelData messages are cut out of the streams and handed to the TURN server
+// like datagrams from the address of the connection, the answers are written back to it.
+// The relays stay UDP. An allocation outlives its connection until it expires unrefreshed.
+pub struct TurnTcpConn {
+    local_addr: SocketAddr,
+    messages: Mutex<mpsc::Receiver<(Vec<u8>, SocketAddr)>>,
+    writers: Writers,
+    closed: watch::Sender<bool>,
+}
+
+impl TurnTcpConn {
+    pub fn new(listener: TcpListener) -> io::Result<Self> {
+        use tokio::spawn;
+        use tokio::task::JoinHandle;
+
+        let local_addr = listener.local_addr()?;
+        let (message_sender, messages) = mpsc::channel(64);
+        let writers = Writers::default();
+        let (closed, closed_receiver) = watch::channel(false);
+        let _join_handle: JoinHandle<()> = spawn(accept(
+            listener,
+            message_sender,
+            Arc::clone(&writers),
+            closed_receiver,
+        ));
+
+        Ok(Self {
+            local_addr,
+            messages: Mutex::new(messages),
+            writers,
+            closed,
+        })
+    }
+}
+
+impl fmt::Debug for TurnTcpConn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TurnTcpConn")
+            .field("local_addr", &self.local_addr)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Conn for TurnTcpConn {
+    // Every client has a connection of its own, there is no single remote address.
+    async fn connect(&self, _: SocketAddr) -> Result<(), webrtc_util::Error> {
+        Err(webrtc_util::Error::Other(
+            "turn tcp: connect is not supported".to_owned(),
+        ))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> Result<usize, webrtc_util::Error> {
+        Ok(self.recv_from(buf).await?.0)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), webrtc_util::Error> {
+        let mut messages = self.messages.lock().await;
+        loop {
+            let (message, addr) = messages
+                .recv()
+                .await
+                .ok_or(webrtc_util::Error::ErrClosedListener)?;
+            match buf.get_mut(..message.len()) {
+                Some(buf) => {
+                    buf.copy_from_slice(&message);
+                    return Ok((message.len(), addr));
+                }
+                None => log::debug!(
+                    "turn tcp: dropped message of {} bytes from {}",
+                    message.len(),
+                    addr
+                ),
+            }
+        }
+    }
+
+    async fn send(&self, _: &[u8]) -> Result<usize, webrtc_util::Error> {
+        Err(webrtc_util::Error::Other(
+            "turn tcp: send without a client is not supported".to_owned(),
+        ))
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize, webrtc_util::Error> {
+        use tokio::io::AsyncWriteExt;
+
+        let writer = self.writers.lock().await.get(&target).cloned();
+        let writer = writer.ok_or_else(|| {
+            webrtc_util::Error::Other(format!("turn tcp: no connection from {}", target))
+        })?;
+        writer.lock().await.write_all(buf).await?;
+        Ok(buf.len())
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, webrtc_util::Error> {
+        Ok(self.local_addr)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    async fn close(&self) -> Result<(), webrtc_util::Error> {
+        let _: Result<(), _> = self.closed.send(true);
+        self.writers.lock().await.clear();
+        Ok(())
+    }
+}
+
+async fn accept(
+    listener: TcpListener,
+    message_sender: mpsc::Sender<(Vec<u8>, SocketAddr)>,
+    writers: Writers,
+    mut closed: watch::Receiver<bool>,
+) {
+    use tokio::spawn;
+    use tokio::task::JoinHandle;
+
+    loop {
+        let (stream, addr) = tokio::select! {
+            result = listener.accept() => match result {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    log::warn!("turn tcp: accept failed: {}", err);
+                    continue;
+                }
+            },
+            _ = closed.changed() => break,
+        };
+        let _: io::Result<()> = stream.set_nodelay(true);
+        let (reader, writer) = stream.into_split();
+        let _: Option<_> = writers
+            .lock()
+            .await
+            .insert(addr, Arc::new(Mutex::new(writer)));
+        let _join_handle: JoinHandle<()> = spawn(read(
+            reader,
+            addr,
+            message_sender.clone(),
+            Arc::clone(&writers),
+            closed.clone(),
+        ));
+    }
+}
+
+async fn read(
+    mut reader: impl AsyncRead + Unpin,
+    addr: SocketAddr,
+    message_sender: mpsc::Sender<(Vec<u8>, SocketAddr)>,
+    writers: Writers,
+    mut closed: watch::Receiver<bool>,
+) {
+    loop {
+        let message = tokio::select! {
+            result = read_message(&mut reader) => match result {
+                Ok(message) => message,
+                Err(err) => {
+                    log::debug!("turn tcp: connection from {} closed: {}", addr, err);
+                    break;
+                }
+            },
+            _ = closed.changed() => break,
+        };
+        if message_sender.send((message, addr)).await.is_err() {
+            break;
+        }
+    }
+    let _: Option<_> = writers.lock().await.remove(&addr);
+}
+
+// A STUN message has a 20 byte header followed by the length in its bytes 2 and 3,
+// a ChannelData message starts with the bits 01, has a 4 byte header
+// and is padded to a multiple of 4 bytes over TCP.
+async fn read_message(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut header = [0; 4];
+    let _: usize = reader.read_exact(&mut header).await?;
+    let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    let size = match header[0] >> 6 {
+        0b00 => 20 + length,
+        0b01 => 4 + ((length + 3) & !3),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "neither STUN nor ChannelData",
+            ))
+        }
+    };
+    let mut message = header.to_vec();
+    message.resize(size, 0);
+    let _: usize = reader.read_exact(&mut message[4..]).await?;
+    Ok(message)
+}
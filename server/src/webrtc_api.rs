@@ -1,53 +1,130 @@
 use core::fmt;
 
 use interceptor::registry::Registry;
-use webrtc::api::interceptor_registry::register_default_interceptors;
+use protocol::IceServer;
 use webrtc::api::media_engine::MediaEngine;
 use webrtc::api::{APIBuilder, API};
-use webrtc::peer::configuration::RTCConfiguration;
-use webrtc::peer::ice::ice_server::RTCIceServer;
-use webrtc::peer::peer_connection::RTCPeerConnection;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::RTCPeerConnection;
+
+use crate::{Error, ForwardingSettings, NetworkSettings, TurnCredentials};
 
 pub struct WebRtcApi {
     api: API,
+    ice_servers: Vec<IceServer>,
+    turn_credentials: Option<TurnCredentials>,
+    forwarding_settings: ForwardingSettings,
 }
 
 impl WebRtcApi {
-    pub fn new() -> Self {
+    pub async fn new(
+        ice_servers: Vec<IceServer>,
+        turn_credentials: Option<TurnCredentials>,
+        network_settings: &NetworkSettings,
+        forwarding_settings: ForwardingSettings,
+    ) -> Result<Self, Error> {
+        // Every peer connection would reject TURN servers without credentials, so fail early.
+        for ice_server in &ice_servers {
+            let has_credentials = ice_server.username.is_some() && ice_server.credential.is_some();
+            for url in &ice_server.urls {
+                if url.starts_with("turn") && !has_credentials {
+                    return Err(Error::TurnCredentialsExpected(url.clone()));
+                }
+            }
+        }
+
         let mut media_engine = MediaEngine::default();
-        media_engine.register_default_codecs().unwrap();
+        media_engine
+            .register_default_codecs()
+            .map_err(Error::WebRtc)?;
 
-        let mut registry = Registry::new();
-        registry = register_default_interceptors(registry, &mut media_engine).unwrap();
+        let registry = interceptor_registry(&mut media_engine)?;
 
         let api = APIBuilder::new()
             .with_media_engine(media_engine)
             .with_interceptor_registry(registry)
+            .with_setting_engine(network_settings.setting_engine().await?)
             .build();
 
-        Self { api }
+        Ok(Self {
+            api,
+            ice_servers,
+            turn_credentials,
+            forwarding_settings,
+        })
     }
 
-    pub fn default_config() -> RTCConfiguration {
-        RTCConfiguration {
-            ice_servers: vec![RTCIceServer {
-                urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+    pub fn forwarding_settings(&self) -> ForwardingSettings {
+        self.forwarding_settings
+    }
+
+    // The same list is sent to the clients in the handshake,
+    // TURN credentials are minted anew on every call.
+    pub fn ice_servers(&self) -> Vec<IceServer> {
+        let mut ice_servers = self.ice_servers.clone();
+        ice_servers.extend(
+            self.turn_credentials
+                .iter()
+                .map(TurnCredentials::ice_server),
+        );
+        ice_servers
+    }
+
+    pub fn config(&self) -> RTCConfiguration {
+        let ice_servers = self
+            .ice_servers()
+            .into_iter()
+            .map(|ice_server| RTCIceServer {
+                urls: ice_server.urls,
+                username: ice_server.username.unwrap_or_default(),
+                credential: ice_server.credential.unwrap_or_default(),
                 ..Default::default()
-            }],
+            })
+            .collect();
+        RTCConfiguration {
+            ice_servers,
             ..Default::default()
         }
     }
 
-    pub async fn new_peer_connection(&self) -> RTCPeerConnection {
+    pub async fn new_peer_connection(&self) -> Result<RTCPeerConnection, Error> {
         self.api
-            .new_peer_connection(Self::default_config())
+            .new_peer_connection(self.config())
             .await
-            .unwrap()
+            .map_err(Error::WebRtc)
+    }
+}
+
+// The default interceptors without the NACK responder, the server answers NACKs
+// from the `RtpHistory` of each receiver or forwards them to the sender.
+fn interceptor_registry(media_engine: &mut MediaEngine) -> Result<Registry, Error> {
+    use interceptor::nack::generator::Generator;
+    use webrtc::api::interceptor_registry::{configure_rtcp_reports, configure_twcc_receiver_only};
+    use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+    use webrtc::rtp_transceiver::RTCPFeedback;
+
+    for parameter in ["", "pli"] {
+        media_engine.register_feedback(
+            RTCPFeedback {
+                typ: "nack".to_owned(),
+                parameter: parameter.to_owned(),
+            },
+            RTPCodecType::Video,
+        );
     }
+    let mut registry = Registry::new();
+    registry.add(Box::new(Generator::builder()));
+    registry = configure_rtcp_reports(registry);
+    configure_twcc_receiver_only(registry, media_engine).map_err(Error::WebRtc)
 }
 
 impl fmt::Debug for WebRtcApi {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("WebRtc").finish_non_exhaustive()
+        f.debug_struct("WebRtc")
+            .field("ice_servers", &self.ice_servers)
+            .field("turn_credentials", &self.turn_credentials)
+            .field("forwarding_settings", &self.forwarding_settings)
+            .finish_non_exhaustive()
     }
 }
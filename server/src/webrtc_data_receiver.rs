@@ -1,8 +1,8 @@
 use core::fmt;
 use std::sync::Arc;
 
-use webrtc::data::data_channel::data_channel_message::DataChannelMessage;
-use webrtc::data::data_channel::RTCDataChannel;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
 
 use crate::ChannelSender;
 
@@ -12,26 +12,22 @@ pub struct WebRtcDataReceiver {
 }
 
 impl WebRtcDataReceiver {
-    pub async fn new(
-        channel_sender: ChannelSender,
-        data_channel: Arc<RTCDataChannel>,
-    ) -> Arc<Self> {
+    pub fn new(channel_sender: ChannelSender, data_channel: Arc<RTCDataChannel>) -> Arc<Self> {
         let receiver = Arc::new(Self {
             channel_sender,
             data_channel,
         });
 
-        receiver.init().await;
+        receiver.init();
 
         receiver
     }
 
-    async fn init(self: &Arc<Self>) {
+    fn init(self: &Arc<Self>) {
         use crate::WeakAsyncCallback;
 
         self.data_channel
-            .on_message(Box::with_weak_async_callback(self, Self::on_message))
-            .await;
+            .on_message(Box::with_weak_async_callback(self, Self::on_message));
     }
 
     pub async fn on_message(self: Arc<Self>, msg: DataChannelMessage) {
@@ -45,8 +41,7 @@ impl WebRtcDataReceiver {
             string
         );
 
-        self.channel_sender
-            .send(ChannelMessage::Data(msg.data.to_vec()));
+        self.channel_sender.send(ChannelMessage::Data(msg.data));
     }
 }
 
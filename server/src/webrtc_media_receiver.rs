@@ -1,10 +1,15 @@
 use core::fmt;
 use std::sync::Arc;
 
-use webrtc::media::rtp::rtp_receiver::RTCRtpReceiver;
-use webrtc::media::track::track_remote::TrackRemote;
+use rtp::packet::Packet;
+use tokio::sync::mpsc;
 
-use crate::ChannelSender;
+use webrtc::track::track_remote::TrackRemote;
+
+use crate::{ChannelId, ChannelSender, Error, TrackRecorder};
+
+// Packets queued for the recording of a track, once full they are dropped from the recording.
+const RECORDING_QUEUE_SIZE: usize = 512;
 
 pub struct WebRtcMediaReceiver {
     channel_sender: ChannelSender,
@@ -12,11 +17,7 @@ pub struct WebRtcMediaReceiver {
 }
 
 impl WebRtcMediaReceiver {
-    pub async fn new(
-        channel_sender: ChannelSender,
-        track: Arc<TrackRemote>,
-        _: Option<Arc<RTCRtpReceiver>>,
-    ) -> Arc<Self> {
+    pub async fn new(channel_sender: ChannelSender, track: Arc<TrackRemote>) -> Arc<Self> {
         let receiver = Arc::new(Self {
             channel_sender,
             track,
@@ -27,6 +28,26 @@ impl WebRtcMediaReceiver {
         receiver
     }
 
+    // Is `None` for audio tracks.
+    pub fn video_ssrc(&self) -> Option<u32> {
+        use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+
+        match self.track.kind() {
+            RTPCodecType::Video => Some(self.track.ssrc()),
+            RTPCodecType::Audio | RTPCodecType::Unspecified => None,
+        }
+    }
+
+    // Is `None` for video tracks.
+    pub fn audio_ssrc(&self) -> Option<u32> {
+        use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+
+        match self.track.kind() {
+            RTPCodecType::Audio => Some(self.track.ssrc()),
+            RTPCodecType::Video | RTPCodecType::Unspecified => None,
+        }
+    }
+
     fn init(self: &Arc<Self>) {
         self.spawn_thread()
     }
@@ -35,33 +56,104 @@ impl WebRtcMediaReceiver {
         use tokio::spawn;
         use tokio::task::JoinHandle;
 
-        let self_arc = Arc::clone(&self);
-        let _: JoinHandle<()> = spawn(async move { self_arc.thread().await });
+        let self_arc = Arc::clone(self);
+        let _join_handle: JoinHandle<()> = spawn(async move { self_arc.thread().await });
     }
 
+    // The recording is written by a task of its own, so that a slow disk does not delay
+    // the forwarding.
     async fn thread(self: &Arc<Self>) {
-        use crate::ChannelMessage;
-        use webrtc::media::rtp::rtp_codec::RTPCodecType;
-        use webrtc_util::marshal::Marshal;
-        use webrtc_util::marshal::MarshalSize;
+        use mpsc::error::TrySendError;
+
+        let mut recording = self.track_recorder().await.map(|recorder| {
+            use tokio::spawn;
+            use tokio::task::JoinHandle;
 
+            let (sender, receiver) = mpsc::channel(RECORDING_QUEUE_SIZE);
+            let channel_id = self.channel_sender.channel_id();
+            let _join_handle: JoinHandle<()> = spawn(record(channel_id, recorder, receiver));
+            sender
+        });
         while let Ok((rtp, _)) = self.track.read_rtp().await {
-            let len = rtp.marshal_size();
-            let mut buf = vec![0; len];
-            assert_eq!(rtp.marshal_to(&mut buf).unwrap(), len);
-            match self.track.kind() {
-                RTPCodecType::Video => self
-                    .channel_sender
-                    .send(ChannelMessage::Video(buf.to_vec())),
-                RTPCodecType::Audio => self
-                    .channel_sender
-                    .send(ChannelMessage::Audio(buf.to_vec())),
-                RTPCodecType::Unspecified => {
-                    panic!("Track data with unspecified codec type received")
+            let rtp = Arc::new(rtp);
+            if let Err(err) = self.forward(Arc::clone(&rtp)) {
+                log::error!(
+                    "channel {}: receiver: {}",
+                    self.channel_sender.channel_id(),
+                    err
+                );
+                break;
+            }
+            if let Some(sender) = &recording {
+                match sender.try_send(rtp) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => log::debug!(
+                        "channel {}: recording dropped a packet",
+                        self.channel_sender.channel_id()
+                    ),
+                    Err(TrySendError::Closed(_)) => recording = None,
                 }
             }
         }
     }
+
+    async fn track_recorder(&self) -> Option<TrackRecorder> {
+        let recorder = self.channel_sender.recorder()?;
+        let channel_id = self.channel_sender.channel_id();
+        let mime_type = self.track.codec().await.capability.mime_type;
+        match recorder
+            .track_recorder(channel_id, self.track.ssrc(), &mime_type)
+            .await
+        {
+            Ok(Some(track_recorder)) => Some(track_recorder),
+            Ok(None) => {
+                log::warn!(
+                    "channel {}: recording of {} is not supported",
+                    channel_id,
+                    mime_type
+                );
+                None
+            }
+            Err(err) => {
+                log::error!("channel {}: recording failed: {}", channel_id, err);
+                None
+            }
+        }
+    }
+
+    fn forward(self: &Arc<Self>, rtp: Arc<Packet>) -> Result<(), Error> {
+        use crate::ChannelMessage;
+        use anyhow::anyhow;
+        use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+
+        match self.track.kind() {
+            RTPCodecType::Video => self.channel_sender.send(ChannelMessage::Video(rtp)),
+            RTPCodecType::Audio => self.channel_sender.send(ChannelMessage::Audio(rtp)),
+            RTPCodecType::Unspecified => {
+                return Err(Error::Forwarding(anyhow!(
+                    "track data with unspecified codec type received"
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+// Finishes the recording once the track has ended, stops it on the first error.
+async fn record(
+    channel_id: ChannelId,
+    mut recorder: TrackRecorder,
+    mut packets: mpsc::Receiver<Arc<Packet>>,
+) {
+    while let Some(rtp) = packets.recv().await {
+        if let Err(err) = recorder.write_rtp(&rtp).await {
+            log::error!("channel {}: recording stopped: {}", channel_id, err);
+            return;
+        }
+    }
+    if let Err(err) = recorder.finish().await {
+        log::error!("channel {}: recording finish failed: {}", channel_id, err);
+    }
 }
 
 impl fmt::Debug for WebRtcMediaReceiver {
@@ -1,40 +1,55 @@
 use core::fmt;
+use core::time::Duration;
 use std::sync::Arc;
 
 use protocol::{IceCandidate, ServerReceiverMessage, SessionDescription};
-use tokio::sync::{Mutex, RwLock};
-use webrtc::data::data_channel::RTCDataChannel;
-use webrtc::media::rtp::rtp_receiver::RTCRtpReceiver;
-use webrtc::media::track::track_remote::TrackRemote;
-use webrtc::peer::ice::ice_candidate::RTCIceCandidate;
-use webrtc::peer::peer_connection::RTCPeerConnection;
-use webrtc::peer::peer_connection_state::RTCPeerConnectionState;
+use tokio::sync::{Mutex, Notify, RwLock};
+use tokio::task::JoinHandle;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidate;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_receiver::RTCRtpReceiver;
+use webrtc::track::track_remote::TrackRemote;
 
 use crate::{
-    ChannelId, ChannelSender, WebRtcApi, WebRtcDataReceiver, WebRtcMediaReceiver, WebSocketSender,
+    ChannelFeedback, ChannelId, ChannelSender, Error, WebRtcApi, WebRtcDataReceiver,
+    WebRtcMediaReceiver, WebSocketSender,
 };
 
+// Keyframes are expensive, so the requests of many receivers joining at once are coalesced.
+const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+// The lowest bitrate estimate of all receivers within the interval is passed on,
+// so the sender adapts to the slowest receiver.
+const BITRATE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct WebRtcReceiver {
     api: Arc<WebRtcApi>,
     channel_sender: ChannelSender,
     peer_connection: RTCPeerConnection,
-    websocket_sender: Mutex<WebSocketSender<ServerReceiverMessage>>,
+    // Is `None` for WHIP sessions, their local candidates are sent within the answer.
+    websocket_sender: Option<Arc<Mutex<WebSocketSender<ServerReceiverMessage>>>>,
     data_receivers: RwLock<Vec<Arc<WebRtcDataReceiver>>>,
     media_receivers: RwLock<Vec<Arc<WebRtcMediaReceiver>>>,
     delayed_icecandidates: Mutex<Vec<IceCandidate>>,
+    keyframe_thread: Mutex<Option<JoinHandle<()>>>,
+    feedback_thread: Mutex<Option<JoinHandle<()>>>,
+    disconnected: Notify,
 }
 
 impl WebRtcReceiver {
     pub async fn new(
         api: Arc<WebRtcApi>,
         channel_sender: ChannelSender,
-        websocket_sender: WebSocketSender<ServerReceiverMessage>,
-    ) -> Arc<Self> {
-        let peer_connection = api.new_peer_connection().await;
-        let websocket_sender = Mutex::new(websocket_sender);
+        websocket_sender: Option<Arc<Mutex<WebSocketSender<ServerReceiverMessage>>>>,
+    ) -> Result<Arc<Self>, Error> {
+        let peer_connection = api.new_peer_connection().await?;
         let data_receivers = RwLock::new(Vec::new());
         let media_receivers = RwLock::new(Vec::new());
         let delayed_icecandidates = Mutex::new(Vec::new());
+        let keyframe_thread = Mutex::new(None);
+        let feedback_thread = Mutex::new(None);
+        let disconnected = Notify::new();
 
         let receiver = Arc::new(Self {
             api,
@@ -44,95 +59,125 @@ impl WebRtcReceiver {
             data_receivers,
             media_receivers,
             delayed_icecandidates,
+            keyframe_thread,
+            feedback_thread,
+            disconnected,
         });
 
         receiver.init().await;
 
-        receiver
+        Ok(receiver)
     }
 
     async fn init(self: &Arc<Self>) {
-        self.init_handlers().await;
+        self.init_handlers();
+        self.spawn_keyframe_thread().await;
+        self.spawn_feedback_thread().await;
     }
 
-    async fn init_handlers(self: &Arc<Self>) {
+    fn init_handlers(self: &Arc<Self>) {
         use crate::WeakAsyncCallback;
 
         self.peer_connection
             .on_peer_connection_state_change(Box::with_weak_async_callback(
                 self,
                 Self::on_peer_connection_state_change,
-            ))
-            .await;
+            ));
 
         self.peer_connection
             .on_ice_candidate(Box::with_weak_async_callback(
                 self,
                 Self::on_local_icecandidate,
-            ))
-            .await;
+            ));
 
         self.peer_connection
-            .on_data_channel(Box::with_weak_async_callback(self, Self::on_data_channel))
-            .await;
+            .on_data_channel(Box::with_weak_async_callback(self, Self::on_data_channel));
 
         self.peer_connection
-            .on_track(Box::with_weak_async_callback(self, Self::on_track))
-            .await;
+            .on_track(Box::with_weak_async_callback(self, Self::on_track));
     }
 
-    pub async fn on_offer(self: &Arc<Self>, sdp: SessionDescription) {
+    pub async fn on_offer(self: &Arc<Self>, sdp: SessionDescription) -> Result<(), Error> {
         use core::mem::take;
-        use webrtc::peer::sdp::sdp_type::RTCSdpType;
-        use webrtc::peer::sdp::session_description::{
-            RTCSessionDescription, RTCSessionDescriptionSerde,
-        };
 
-        let mut offer = RTCSessionDescription::default();
-        offer.serde = RTCSessionDescriptionSerde {
-            sdp_type: RTCSdpType::Offer,
-            sdp: sdp.0,
-        };
+        self.set_offer(sdp).await?;
+        let answer = self.set_answer().await?;
 
-        self.peer_connection
-            .set_remote_description(offer)
-            .await
-            .unwrap();
-
-        self.send_answer().await;
+        if let Some(websocket_sender) = &self.websocket_sender {
+            websocket_sender
+                .lock()
+                .await
+                .send(ServerReceiverMessage::Answer(answer))
+                .await?;
+        }
 
         let mut icecandidates = self.delayed_icecandidates.lock().await;
         let icecandidates: Vec<_> = take(&mut icecandidates);
         for candidate in icecandidates {
-            self.on_remote_icecandidate(candidate).await;
+            self.on_remote_icecandidate(candidate).await?;
         }
+        Ok(())
     }
 
-    async fn send_answer(self: &Arc<Self>) {
-        let answer = self.peer_connection.create_answer(None).await.unwrap();
+    // WHIP does not trickle candidates, so the answer is returned once the gathering is complete.
+    pub async fn on_whip_offer(
+        self: &Arc<Self>,
+        sdp: SessionDescription,
+    ) -> Result<SessionDescription, Error> {
+        use anyhow::anyhow;
+
+        self.set_offer(sdp).await?;
+
+        let mut gathering_complete = self.peer_connection.gathering_complete_promise().await;
+        let _: SessionDescription = self.set_answer().await?;
+        let _: Option<()> = gathering_complete.recv().await;
+
+        let answer = self
+            .peer_connection
+            .local_description()
+            .await
+            .ok_or_else(|| Error::Signaling(anyhow!("local description is missing")))?;
+        Ok(SessionDescription(answer.sdp))
+    }
+
+    async fn set_offer(self: &Arc<Self>, sdp: SessionDescription) -> Result<(), Error> {
+        use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+        let offer = RTCSessionDescription::offer(sdp.0)
+            .map_err(|err| Error::SessionDescription(err.into()))?;
 
-        let answer_sdp = answer.serde.sdp.clone();
         self.peer_connection
-            .set_local_description(answer)
+            .set_remote_description(offer)
             .await
-            .unwrap();
+            .map_err(|err| Error::SessionDescription(err.into()))
+    }
+
+    async fn set_answer(self: &Arc<Self>) -> Result<SessionDescription, Error> {
+        let answer = self
+            .peer_connection
+            .create_answer(None)
+            .await
+            .map_err(|err| Error::Signaling(err.into()))?;
 
-        self.websocket_sender
-            .lock()
+        let answer_sdp = answer.sdp.clone();
+        self.peer_connection
+            .set_local_description(answer)
             .await
-            .send(ServerReceiverMessage::Answer(SessionDescription(
-                answer_sdp,
-            )))
-            .await;
+            .map_err(|err| Error::Signaling(err.into()))?;
+
+        Ok(SessionDescription(answer_sdp))
     }
 
-    pub async fn on_remote_icecandidate(self: &Arc<Self>, ice_candidate: IceCandidate) {
+    pub async fn on_remote_icecandidate(
+        self: &Arc<Self>,
+        ice_candidate: IceCandidate,
+    ) -> Result<(), Error> {
         crate::add_remote_icecandidate(
             &self.peer_connection,
             ice_candidate,
             &self.delayed_icecandidates,
         )
-        .await;
+        .await
     }
 
     pub async fn on_all_remote_icecandidates_sent(self: &Arc<Self>) {}
@@ -143,36 +188,209 @@ impl WebRtcReceiver {
             self.channel_id(),
             state
         );
+        if state == RTCPeerConnectionState::Failed || state == RTCPeerConnectionState::Closed {
+            self.disconnected.notify_one();
+        }
     }
 
     async fn on_local_icecandidate(self: Arc<Self>, ice_candidate: Option<RTCIceCandidate>) {
-        crate::send_local_icecandidate(
-            &self.websocket_sender,
+        let websocket_sender = match &self.websocket_sender {
+            Some(websocket_sender) => websocket_sender,
+            None => return,
+        };
+        let result = crate::send_local_icecandidate(
+            websocket_sender,
             ice_candidate,
             ServerReceiverMessage::IceCandidate,
             ServerReceiverMessage::AllIceCandidatesSent,
         )
         .await;
+        if let Err(err) = result {
+            log::error!("channel {}: receiver: {}", self.channel_id(), err);
+        }
+    }
+
+    async fn spawn_keyframe_thread(self: &Arc<Self>) {
+        use tokio::spawn;
+
+        let self_arc = Arc::clone(self);
+        let thread = spawn(async move { self_arc.keyframe_thread().await });
+        let prev = self.keyframe_thread.lock().await.replace(thread);
+        assert!(prev.is_none());
+    }
+
+    async fn keyframe_thread(self: &Arc<Self>) {
+        use tokio::time::sleep;
+
+        loop {
+            self.channel_sender.keyframe_requested().await;
+            // The video track is known only after its first packet arrives.
+            while !self.send_keyframe_request().await {
+                sleep(KEYFRAME_REQUEST_INTERVAL).await;
+            }
+            // The requests arriving meanwhile are served by a single PLI afterwards.
+            sleep(KEYFRAME_REQUEST_INTERVAL).await;
+        }
+    }
+
+    // Returns false if there is no video track yet.
+    async fn send_keyframe_request(&self) -> bool {
+        use rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+
+        let mut sent = false;
+        for media_receiver in self.media_receivers.read().await.iter() {
+            if let Some(media_ssrc) = media_receiver.video_ssrc() {
+                log::debug!(
+                    "channel {}: requesting keyframe for ssrc {}",
+                    self.channel_id(),
+                    media_ssrc
+                );
+                let pli = PictureLossIndication {
+                    sender_ssrc: 0,
+                    media_ssrc,
+                };
+                if let Err(err) = self.peer_connection.write_rtcp(&[Box::new(pli)]).await {
+                    log::warn!(
+                        "channel {}: keyframe request failed: {}",
+                        self.channel_id(),
+                        err
+                    );
+                }
+                sent = true;
+            }
+        }
+        sent
+    }
+
+    async fn spawn_feedback_thread(self: &Arc<Self>) {
+        use tokio::spawn;
+
+        let self_arc = Arc::clone(self);
+        let thread = spawn(async move { self_arc.feedback_thread().await });
+        let prev = self.feedback_thread.lock().await.replace(thread);
+        assert!(prev.is_none());
+    }
+
+    async fn feedback_thread(self: &Arc<Self>) {
+        use tokio::time::interval;
+
+        let mut ticker = interval(BITRATE_REPORT_INTERVAL);
+        let mut min_bitrate: Option<u64> = None;
+        loop {
+            tokio::select! {
+                feedback = self.channel_sender.recv_feedback() => match feedback {
+                    Some(ChannelFeedback::Remb(bitrate)) => {
+                        min_bitrate = Some(min_bitrate.map_or(bitrate, |min| min.min(bitrate)));
+                    }
+                    Some(feedback) => self.send_nack(feedback).await,
+                    None => break,
+                },
+                _ = ticker.tick() => {
+                    if let Some(bitrate) = min_bitrate.take() {
+                        self.send_remb(bitrate).await;
+                    }
+                }
+            }
+        }
+    }
+
+    // The downstream sequence numbers are the upstream ones, only the SSRC is replaced.
+    async fn send_nack(&self, feedback: ChannelFeedback) {
+        use rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+
+        let media_receivers = self.media_receivers.read().await;
+        let (media_ssrc, nacks) = match feedback {
+            ChannelFeedback::VideoNack(nacks) => (
+                media_receivers
+                    .iter()
+                    .find_map(|receiver| receiver.video_ssrc()),
+                nacks,
+            ),
+            ChannelFeedback::AudioNack(nacks) => (
+                media_receivers
+                    .iter()
+                    .find_map(|receiver| receiver.audio_ssrc()),
+                nacks,
+            ),
+            ChannelFeedback::Remb(_) => return,
+        };
+        let media_ssrc = match media_ssrc {
+            Some(media_ssrc) => media_ssrc,
+            None => return,
+        };
+        let nack = TransportLayerNack {
+            sender_ssrc: 0,
+            media_ssrc,
+            nacks,
+        };
+        if let Err(err) = self.peer_connection.write_rtcp(&[Box::new(nack)]).await {
+            log::warn!("channel {}: NACK failed: {}", self.channel_id(), err);
+        }
+    }
+
+    async fn send_remb(&self, bitrate: u64) {
+        use rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+
+        let ssrcs: Vec<u32> = self
+            .media_receivers
+            .read()
+            .await
+            .iter()
+            .filter_map(|receiver| receiver.video_ssrc().or_else(|| receiver.audio_ssrc()))
+            .collect();
+        if ssrcs.is_empty() {
+            return;
+        }
+        let remb = ReceiverEstimatedMaximumBitrate {
+            sender_ssrc: 0,
+            bitrate: bitrate as f32,
+            ssrcs,
+        };
+        if let Err(err) = self.peer_connection.write_rtcp(&[Box::new(remb)]).await {
+            log::warn!("channel {}: REMB failed: {}", self.channel_id(), err);
+        }
+    }
+
+    pub async fn close(self: &Arc<Self>) {
+        if let Some(thread) = self.keyframe_thread.lock().await.take() {
+            thread.abort();
+        }
+        if let Some(thread) = self.feedback_thread.lock().await.take() {
+            thread.abort();
+        }
+        if let Err(err) = self.peer_connection.close().await {
+            log::warn!(
+                "channel {}: receiver peer connection close failed: {}",
+                self.channel_id(),
+                err
+            );
+        }
+        self.disconnected.notify_one();
+    }
+
+    // Resolves once the peer connection has failed or has been closed.
+    pub async fn disconnected(&self) {
+        self.disconnected.notified().await
     }
 
-    fn channel_id(&self) -> ChannelId {
+    pub fn channel_id(&self) -> ChannelId {
         self.channel_sender.channel_id()
     }
 
     async fn on_data_channel(self: Arc<Self>, data_channel: Arc<RTCDataChannel>) {
-        let data_receiver =
-            WebRtcDataReceiver::new(self.channel_sender.clone(), data_channel).await;
+        let data_receiver = WebRtcDataReceiver::new(self.channel_sender.clone(), data_channel);
         self.data_receivers.write().await.push(data_receiver);
     }
 
+    // The sender reports of the publisher are not needed, so the RTCP of the `RTCRtpReceiver`
+    // is not read. The feedback for the publisher is written to the peer connection instead.
     async fn on_track(
         self: Arc<Self>,
         track: Option<Arc<TrackRemote>>,
-        receiver: Option<Arc<RTCRtpReceiver>>,
+        _: Option<Arc<RTCRtpReceiver>>,
     ) {
         if let Some(track) = track {
-            let media_receiver =
-                WebRtcMediaReceiver::new(self.channel_sender.clone(), track, receiver).await;
+            let media_receiver = WebRtcMediaReceiver::new(self.channel_sender.clone(), track).await;
             self.media_receivers.write().await.push(media_receiver);
         }
     }
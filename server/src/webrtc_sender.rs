@@ -2,45 +2,65 @@ use core::fmt;
 use std::sync::Arc;
 
 use protocol::{IceCandidate, ServerSenderMessage, SessionDescription};
-use tokio::sync::Mutex;
-use webrtc::data::data_channel::RTCDataChannel;
-use webrtc::media::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
-use webrtc::peer::ice::ice_candidate::RTCIceCandidate;
-use webrtc::peer::peer_connection::RTCPeerConnection;
-use webrtc::peer::peer_connection_state::RTCPeerConnectionState;
+use rtcp::transport_feedbacks::transport_layer_nack::NackPair;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidate;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 
-use crate::{ChannelId, ChannelReceiver, WebRtcApi, WebSocketSender};
+use crate::{
+    ChannelFeedback, ChannelId, ChannelMessage, ChannelReceiver, Error, RtpHistory, WebRtcApi,
+    WebSocketSender,
+};
 
 pub struct WebRtcSender {
     api: Arc<WebRtcApi>,
     channel_receiver: ChannelReceiver,
     peer_connection: RTCPeerConnection,
-    websocket_sender: Mutex<WebSocketSender<ServerSenderMessage>>,
+    // Is `None` for WHEP sessions, the offer comes from the remote side then.
+    websocket_sender: Option<Arc<Mutex<WebSocketSender<ServerSenderMessage>>>>,
     delayed_icecandidates: Mutex<Vec<IceCandidate>>,
     data_channel: Arc<RTCDataChannel>,
     video_track: Arc<TrackLocalStaticRTP>,
     audio_track: Arc<TrackLocalStaticRTP>,
+    // The forwarded packets for retransmission on NACKs.
+    video_history: Mutex<RtpHistory>,
+    audio_history: Mutex<RtpHistory>,
+    // Taken by the first packet actually sent on the track, see `spawn_rtcp_thread`.
+    video_rtp_sender: Mutex<Option<Arc<RTCRtpSender>>>,
+    audio_rtp_sender: Mutex<Option<Arc<RTCRtpSender>>>,
+    rtcp_threads: Mutex<Vec<JoinHandle<()>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    disconnected: Notify,
 }
 
 impl WebRtcSender {
     pub async fn new(
         api: Arc<WebRtcApi>,
         channel_receiver: ChannelReceiver,
-        websocket_sender: WebSocketSender<ServerSenderMessage>,
-    ) -> Arc<Self> {
+        websocket_sender: Option<Arc<Mutex<WebSocketSender<ServerSenderMessage>>>>,
+    ) -> Result<Arc<Self>, Error> {
         use webrtc::api::media_engine::{MIME_TYPE_OPUS, MIME_TYPE_VP8};
-        use webrtc::media::rtp::rtp_codec::RTCRtpCodecCapability;
-        use webrtc::media::track::track_local::TrackLocal;
+        use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+        use webrtc::track::track_local::TrackLocal;
 
         let channel_receiver = channel_receiver;
-        let peer_connection = api.new_peer_connection().await;
-        let websocket_sender = Mutex::new(websocket_sender);
+        let peer_connection = api.new_peer_connection().await?;
         let delayed_icecandidates = Mutex::new(Vec::new());
+        let nack_buffer_size = api.forwarding_settings().nack_buffer_size;
+        let video_history = Mutex::new(RtpHistory::new(nack_buffer_size));
+        let audio_history = Mutex::new(RtpHistory::new(nack_buffer_size));
+        let thread = Mutex::new(None);
+        let disconnected = Notify::new();
 
         let data_channel = peer_connection
             .create_data_channel("data", None)
             .await
-            .unwrap();
+            .map_err(Error::WebRtc)?;
         let video_track = Arc::new(TrackLocalStaticRTP::new(
             RTCRtpCodecCapability {
                 mime_type: MIME_TYPE_VP8.to_owned(),
@@ -60,11 +80,17 @@ impl WebRtcSender {
 
         #[allow(trivial_casts)] // false positive
         let video_track_ref = Arc::clone(&video_track) as Arc<dyn TrackLocal + Sync + Send>;
-        let _ = peer_connection.add_track(video_track_ref).await.unwrap();
+        let video_rtp_sender = peer_connection
+            .add_track(video_track_ref)
+            .await
+            .map_err(Error::WebRtc)?;
 
         #[allow(trivial_casts)] // false positive
         let audio_track_ref = Arc::clone(&audio_track) as Arc<dyn TrackLocal + Sync + Send>;
-        let _ = peer_connection.add_track(audio_track_ref).await.unwrap();
+        let audio_rtp_sender = peer_connection
+            .add_track(audio_track_ref)
+            .await
+            .map_err(Error::WebRtc)?;
 
         let receiver = Arc::new(Self {
             api,
@@ -75,85 +101,136 @@ impl WebRtcSender {
             data_channel,
             video_track,
             audio_track,
+            video_history,
+            audio_history,
+            video_rtp_sender: Mutex::new(Some(video_rtp_sender)),
+            audio_rtp_sender: Mutex::new(Some(audio_rtp_sender)),
+            rtcp_threads: Mutex::new(Vec::new()),
+            thread,
+            disconnected,
         });
 
-        receiver.init().await;
+        if let Err(err) = receiver.init().await {
+            receiver.close().await;
+            return Err(err);
+        }
 
-        receiver
+        Ok(receiver)
     }
 
-    async fn init(self: &Arc<Self>) {
-        self.init_handlers().await;
-        self.send_offer().await;
-        self.spawn_thread();
+    async fn init(self: &Arc<Self>) -> Result<(), Error> {
+        self.init_handlers();
+        if let Some(websocket_sender) = &self.websocket_sender {
+            self.send_offer(websocket_sender).await?;
+        }
+        self.spawn_thread().await;
+        Ok(())
     }
 
-    async fn init_handlers(self: &Arc<Self>) {
+    fn init_handlers(self: &Arc<Self>) {
         use crate::WeakAsyncCallback;
 
         self.peer_connection
             .on_peer_connection_state_change(Box::with_weak_async_callback(
                 self,
                 Self::on_peer_connection_state_change,
-            ))
-            .await;
+            ));
 
         self.peer_connection
             .on_ice_candidate(Box::with_weak_async_callback(
                 self,
                 Self::on_local_icecandidate,
-            ))
-            .await;
+            ));
     }
 
-    async fn send_offer(self: &Arc<Self>) {
-        let offer = self.peer_connection.create_offer(None).await.unwrap();
+    async fn send_offer(
+        self: &Arc<Self>,
+        websocket_sender: &Mutex<WebSocketSender<ServerSenderMessage>>,
+    ) -> Result<(), Error> {
+        let offer = self
+            .peer_connection
+            .create_offer(None)
+            .await
+            .map_err(|err| Error::Signaling(err.into()))?;
 
-        let offer_sdp = offer.serde.sdp.clone();
+        let offer_sdp = offer.sdp.clone();
         self.peer_connection
             .set_local_description(offer)
             .await
-            .unwrap();
+            .map_err(|err| Error::Signaling(err.into()))?;
 
-        self.websocket_sender
+        websocket_sender
             .lock()
             .await
             .send(ServerSenderMessage::Offer(SessionDescription(offer_sdp)))
-            .await;
+            .await
+    }
+
+    // WHEP does not trickle candidates, so the answer is returned once the gathering is complete.
+    pub async fn on_whep_offer(
+        self: &Arc<Self>,
+        sdp: SessionDescription,
+    ) -> Result<SessionDescription, Error> {
+        use anyhow::anyhow;
+        use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+        let offer = RTCSessionDescription::offer(sdp.0)
+            .map_err(|err| Error::SessionDescription(err.into()))?;
+        self.peer_connection
+            .set_remote_description(offer)
+            .await
+            .map_err(|err| Error::SessionDescription(err.into()))?;
+
+        let answer = self
+            .peer_connection
+            .create_answer(None)
+            .await
+            .map_err(|err| Error::Signaling(err.into()))?;
+        let mut gathering_complete = self.peer_connection.gathering_complete_promise().await;
+        self.peer_connection
+            .set_local_description(answer)
+            .await
+            .map_err(|err| Error::Signaling(err.into()))?;
+        let _: Option<()> = gathering_complete.recv().await;
+
+        let answer = self
+            .peer_connection
+            .local_description()
+            .await
+            .ok_or_else(|| Error::Signaling(anyhow!("local description is missing")))?;
+        Ok(SessionDescription(answer.sdp))
     }
 
-    pub async fn on_answer(self: &Arc<Self>, sdp: SessionDescription) {
+    pub async fn on_answer(self: &Arc<Self>, sdp: SessionDescription) -> Result<(), Error> {
         use core::mem::take;
-        use webrtc::peer::sdp::sdp_type::RTCSdpType;
-        use webrtc::peer::sdp::session_description::{
-            RTCSessionDescription, RTCSessionDescriptionSerde,
-        };
+        use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 
-        let mut asnwer = RTCSessionDescription::default();
-        asnwer.serde = RTCSessionDescriptionSerde {
-            sdp_type: RTCSdpType::Answer,
-            sdp: sdp.0,
-        };
+        let answer = RTCSessionDescription::answer(sdp.0)
+            .map_err(|err| Error::SessionDescription(err.into()))?;
 
         self.peer_connection
-            .set_remote_description(asnwer)
+            .set_remote_description(answer)
             .await
-            .unwrap();
+            .map_err(|err| Error::SessionDescription(err.into()))?;
 
         let mut icecandidates = self.delayed_icecandidates.lock().await;
         let icecandidates: Vec<_> = take(&mut icecandidates);
         for candidate in icecandidates {
-            self.on_remote_icecandidate(candidate).await;
+            self.on_remote_icecandidate(candidate).await?;
         }
+        Ok(())
     }
 
-    pub async fn on_remote_icecandidate(self: &Arc<Self>, ice_candidate: IceCandidate) {
+    pub async fn on_remote_icecandidate(
+        self: &Arc<Self>,
+        ice_candidate: IceCandidate,
+    ) -> Result<(), Error> {
         crate::add_remote_icecandidate(
             &self.peer_connection,
             ice_candidate,
             &self.delayed_icecandidates,
         )
-        .await;
+        .await
     }
 
     pub async fn on_all_remote_icecandidates_sent(self: &Arc<Self>) {}
@@ -164,60 +241,245 @@ impl WebRtcSender {
             self.channel_id(),
             state
         );
+        // The remote receiver can only decode the video starting from a keyframe.
+        if state == RTCPeerConnectionState::Connected {
+            self.channel_receiver.request_keyframe();
+        }
+        if state == RTCPeerConnectionState::Failed || state == RTCPeerConnectionState::Closed {
+            self.disconnected.notify_one();
+        }
     }
+
     async fn on_local_icecandidate(self: Arc<Self>, ice_candidate: Option<RTCIceCandidate>) {
-        crate::send_local_icecandidate(
-            &self.websocket_sender,
+        let websocket_sender = match &self.websocket_sender {
+            Some(websocket_sender) => websocket_sender,
+            None => return,
+        };
+        let result = crate::send_local_icecandidate(
+            websocket_sender,
             ice_candidate,
             ServerSenderMessage::IceCandidate,
             ServerSenderMessage::AllIceCandidatesSent,
         )
         .await;
+        if let Err(err) = result {
+            log::error!("channel {}: sender: {}", self.channel_id(), err);
+        }
     }
 
     pub fn channel_id(&self) -> ChannelId {
         self.channel_receiver.channel_id()
     }
 
-    fn spawn_thread(self: &Arc<Self>) {
+    async fn spawn_thread(self: &Arc<Self>) {
+        use tokio::spawn;
+
+        let self_arc = Arc::clone(self);
+        let thread = spawn(async move { self_arc.thread().await });
+        let prev = self.thread.lock().await.replace(thread);
+        assert!(prev.is_none());
+    }
+
+    // Reads the RTCP of the remote receiver, it also has to be read for the interceptors to work.
+    // The NACKs are answered from the history if possible,
+    // the rest of them, keyframe requests and bitrate estimates are passed on to the room sender.
+    // The thread starts with the first packet written and is aborted before closing.
+    async fn spawn_rtcp_thread(
+        self: &Arc<Self>,
+        rtp_sender: Arc<RTCRtpSender>,
+        nack: fn(Vec<NackPair>) -> ChannelFeedback,
+    ) {
         use tokio::spawn;
-        use tokio::task::JoinHandle;
 
-        let self_arc = Arc::clone(&self);
-        let _: JoinHandle<()> = spawn(async move { self_arc.thread().await });
+        let weak = Arc::downgrade(self);
+        let thread = spawn(async move {
+            loop {
+                // The packet is not `Send`, so it is done with before awaiting anything else.
+                let (keyframe_requested, feedback) = match rtp_sender.read_rtcp().await {
+                    Ok((packets, _)) => (
+                        packets
+                            .iter()
+                            .any(|packet| crate::is_keyframe_request(&**packet)),
+                        packets
+                            .iter()
+                            .flat_map(|packet| crate::rtcp_feedback(&**packet, nack))
+                            .collect::<Vec<_>>(),
+                    ),
+                    Err(_) => break,
+                };
+                let self_arc = match weak.upgrade() {
+                    Some(self_arc) => self_arc,
+                    None => break,
+                };
+                if keyframe_requested {
+                    log::debug!("channel {}: keyframe requested", self_arc.channel_id());
+                    self_arc.channel_receiver.request_keyframe();
+                }
+                for feedback in feedback {
+                    if let Some(feedback) = self_arc.retransmit(feedback).await {
+                        self_arc.channel_receiver.send_feedback(feedback);
+                    }
+                }
+            }
+        });
+        self.rtcp_threads.lock().await.push(thread);
+    }
+
+    // Resends the NACKed packets still in the history, returns a NACK of the missing ones.
+    async fn retransmit(&self, feedback: ChannelFeedback) -> Option<ChannelFeedback> {
+        use webrtc::track::track_local::TrackLocalWriter;
+
+        let (track, history, nacks, nack): (_, _, _, fn(_) -> _) = match feedback {
+            ChannelFeedback::VideoNack(nacks) => (
+                &self.video_track,
+                &self.video_history,
+                nacks,
+                ChannelFeedback::VideoNack,
+            ),
+            ChannelFeedback::AudioNack(nacks) => (
+                &self.audio_track,
+                &self.audio_history,
+                nacks,
+                ChannelFeedback::AudioNack,
+            ),
+            ChannelFeedback::Remb(_) => return Some(feedback),
+        };
+
+        let mut missing = Vec::new();
+        for sequence_number in nacks.iter().flat_map(NackPair::packet_list) {
+            let packet = history.lock().await.get(sequence_number);
+            match packet {
+                Some(packet) => {
+                    if let Err(err) = track.write_rtp(&packet).await {
+                        log::warn!("channel {}: retransmit failed: {}", self.channel_id(), err);
+                    }
+                }
+                None => missing.push(NackPair {
+                    packet_id: sequence_number,
+                    lost_packets: 0,
+                }),
+            }
+        }
+        if missing.is_empty() {
+            None
+        } else {
+            Some(nack(missing))
+        }
+    }
+
+    pub async fn close(self: &Arc<Self>) {
+        if let Some(thread) = self.thread.lock().await.take() {
+            thread.abort();
+        }
+        self.channel_receiver.close().await;
+        self.close_peer_connection().await;
+        self.disconnected.notify_one();
+    }
+
+    // Resolves once the peer connection has failed or has been closed.
+    pub async fn disconnected(&self) {
+        self.disconnected.notified().await
+    }
+
+    async fn close_peer_connection(self: &Arc<Self>) {
+        for thread in self.rtcp_threads.lock().await.drain(..) {
+            thread.abort();
+        }
+        let dropped = self.channel_receiver.dropped_packets();
+        if dropped.video > 0 || dropped.audio > 0 {
+            log::info!(
+                "channel {}: dropped {} video and {} audio packets of a slow receiver",
+                self.channel_id(),
+                dropped.video,
+                dropped.audio
+            );
+        }
+        if let Err(err) = self.peer_connection.close().await {
+            log::warn!(
+                "channel {}: sender peer connection close failed: {}",
+                self.channel_id(),
+                err
+            );
+        }
     }
 
     async fn thread(self: &Arc<Self>) {
-        use crate::ChannelMessage;
-        use bytes::Bytes;
-        use rtp::packet::Packet;
-        use webrtc::data::data_channel::data_channel_state::RTCDataChannelState;
-        use webrtc::media::track::track_local::TrackLocalWriter;
-        use webrtc_util::marshal::Unmarshal;
+        // Either the room sender has gone or forwarding has failed,
+        // in both cases the remote receiver is disconnected.
+        let message = match self.forward_all().await {
+            Ok(()) => {
+                log::info!("channel {}: sender channel closed", self.channel_id());
+                ServerSenderMessage::Bye
+            }
+            Err(err) => {
+                log::error!("channel {}: sender: {}", self.channel_id(), err);
+                ServerSenderMessage::Error {
+                    code: err.code(),
+                    reason: err.to_string(),
+                }
+            }
+        };
 
+        self.close_peer_connection().await;
+        self.disconnected.notify_one();
+        if let Some(websocket_sender) = &self.websocket_sender {
+            let mut websocket_sender = websocket_sender.lock().await;
+            let _: Result<(), Error> = websocket_sender.send(message).await;
+            websocket_sender.close().await;
+        }
+    }
+
+    async fn forward_all(self: &Arc<Self>) -> Result<(), Error> {
         while let Some(message) = self.channel_receiver.recv().await {
-            match message {
-                ChannelMessage::Data(data) => {
-                    if self.data_channel.ready_state() == RTCDataChannelState::Open {
-                        let _: usize = self
-                            .data_channel
-                            .send(&Bytes::copy_from_slice(&data))
-                            .await
-                            .unwrap();
-                    }
+            self.forward(message).await?;
+        }
+        Ok(())
+    }
+
+    async fn forward(self: &Arc<Self>, message: ChannelMessage) -> Result<(), Error> {
+        use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+        use webrtc::track::track_local::TrackLocalWriter;
+
+        match message {
+            ChannelMessage::Data(data) => {
+                if self.data_channel.ready_state() == RTCDataChannelState::Open {
+                    let _: usize = self
+                        .data_channel
+                        .send(&data)
+                        .await
+                        .map_err(|err| Error::Forwarding(err.into()))?;
                 }
-                ChannelMessage::Video(data) => {
-                    let mut buf = data.as_slice();
-                    let rtp = Packet::unmarshal(&mut buf).unwrap();
-                    let _: usize = self.video_track.write_rtp(&rtp).await.unwrap();
+            }
+            ChannelMessage::Video(rtp) => {
+                let written = self
+                    .video_track
+                    .write_rtp(&rtp)
+                    .await
+                    .map_err(|err| Error::Forwarding(err.into()))?;
+                self.video_history.lock().await.push(rtp);
+                if written > 0 {
+                    if let Some(rtp_sender) = self.video_rtp_sender.lock().await.take() {
+                        self.spawn_rtcp_thread(rtp_sender, ChannelFeedback::VideoNack)
+                            .await;
+                    }
                 }
-                ChannelMessage::Audio(data) => {
-                    let mut buf = data.as_slice();
-                    let rtp = Packet::unmarshal(&mut buf).unwrap();
-                    let _: usize = self.audio_track.write_rtp(&rtp).await.unwrap();
+            }
+            ChannelMessage::Audio(rtp) => {
+                let written = self
+                    .audio_track
+                    .write_rtp(&rtp)
+                    .await
+                    .map_err(|err| Error::Forwarding(err.into()))?;
+                self.audio_history.lock().await.push(rtp);
+                if written > 0 {
+                    if let Some(rtp_sender) = self.audio_rtp_sender.lock().await.take() {
+                        self.spawn_rtcp_thread(rtp_sender, ChannelFeedback::AudioNack)
+                            .await;
+                    }
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -1,29 +1,35 @@
 use protocol::IceCandidate;
+use rtcp::transport_feedbacks::transport_layer_nack::NackPair;
 use serde::Serialize;
 use tokio::sync::Mutex;
-use webrtc::peer::ice::ice_candidate::RTCIceCandidate;
-use webrtc::peer::peer_connection::RTCPeerConnection;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidate;
+use webrtc::peer_connection::RTCPeerConnection;
 
 use crate::websocket_sender::WebSocketSender;
+use crate::{ChannelFeedback, Error};
 
 pub async fn add_remote_icecandidate(
     peer_connection: &RTCPeerConnection,
     candidate: IceCandidate,
     delayed: &Mutex<Vec<IceCandidate>>,
-) {
-    use webrtc::peer::ice::ice_candidate::RTCIceCandidateInit;
+) -> Result<(), Error> {
+    use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
 
     if peer_connection.remote_description().await.is_some() {
         let candidate = RTCIceCandidateInit {
             candidate: candidate.candidate,
-            sdp_mid: candidate.sdp_mid.unwrap_or_else(|| String::new()),
-            sdp_mline_index: candidate.sdp_mline_index.unwrap_or(0),
-            username_fragment: candidate.username_fragment.unwrap_or_else(|| String::new()),
+            sdp_mid: candidate.sdp_mid,
+            sdp_mline_index: candidate.sdp_mline_index,
+            username_fragment: candidate.username_fragment,
         };
 
-        peer_connection.add_ice_candidate(candidate).await.unwrap();
+        peer_connection
+            .add_ice_candidate(candidate)
+            .await
+            .map_err(|err| Error::IceCandidate(err.into()))
     } else {
         delayed.lock().await.push(candidate);
+        Ok(())
     }
 }
 
@@ -32,21 +38,24 @@ pub async fn send_local_icecandidate<T, F>(
     ice_candidate: Option<RTCIceCandidate>,
     candidate_msg_fn: F,
     all_candidates_sent_msg_fn: T,
-) where
+) -> Result<(), Error>
+where
     T: Serialize,
     F: FnOnce(IceCandidate) -> T,
 {
     if let Some(ice_candidate) = ice_candidate {
-        let json = ice_candidate.to_json().await.unwrap();
+        let json = ice_candidate
+            .to_json()
+            .map_err(|err| Error::Signaling(err.into()))?;
 
         websocket_sender
             .lock()
             .await
             .send(candidate_msg_fn(IceCandidate {
                 candidate: json.candidate,
-                sdp_mid: Some(json.sdp_mid),
-                sdp_mline_index: Some(json.sdp_mline_index),
-                username_fragment: Some(json.username_fragment),
+                sdp_mid: json.sdp_mid,
+                sdp_mline_index: json.sdp_mline_index,
+                username_fragment: json.username_fragment,
             }))
             .await
     } else {
@@ -57,3 +66,46 @@ pub async fn send_local_icecandidate<T, F>(
             .await
     }
 }
+
+// PLI and FIR both ask for a keyframe, they may come within a compound packet.
+pub fn is_keyframe_request(packet: &dyn rtcp::packet::Packet) -> bool {
+    use rtcp::compound_packet::CompoundPacket;
+    use rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+    use rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+
+    let packet = packet.as_any();
+    if let Some(compound) = packet.downcast_ref::<CompoundPacket>() {
+        compound
+            .0
+            .iter()
+            .any(|packet| is_keyframe_request(&**packet))
+    } else {
+        packet.is::<PictureLossIndication>() || packet.is::<FullIntraRequest>()
+    }
+}
+
+// Collects the NACKs and bitrate estimates, they may come within a compound packet.
+// The NACKs are wrapped by `nack`, depending on the track they came for.
+pub fn rtcp_feedback(
+    packet: &dyn rtcp::packet::Packet,
+    nack: fn(Vec<NackPair>) -> ChannelFeedback,
+) -> Vec<ChannelFeedback> {
+    use rtcp::compound_packet::CompoundPacket;
+    use rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+    use rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+
+    let packet = packet.as_any();
+    if let Some(compound) = packet.downcast_ref::<CompoundPacket>() {
+        compound
+            .0
+            .iter()
+            .flat_map(|packet| rtcp_feedback(&**packet, nack))
+            .collect()
+    } else if let Some(packet) = packet.downcast_ref::<TransportLayerNack>() {
+        vec![nack(packet.nacks.clone())]
+    } else if let Some(packet) = packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+        vec![ChannelFeedback::Remb(packet.bitrate as u64)]
+    } else {
+        Vec::new()
+    }
+}
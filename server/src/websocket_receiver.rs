@@ -1,42 +1,68 @@
 use core::marker::PhantomData;
+use core::time::Duration;
 
 use futures::stream::SplitStream;
-use serde::Deserialize;
-use tokio::net::TcpStream;
+use hyper::upgrade::Upgraded;
+use protocol::Codec;
+use serde::de::DeserializeOwned;
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::WebSocketStream;
 
+use crate::Error;
+
+// How long a closed socket waits for the client to acknowledge the close.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 pub struct WebSocketReceiver<T> {
-    receiver: SplitStream<WebSocketStream<TcpStream>>,
+    receiver: SplitStream<WebSocketStream<Upgraded>>,
+    codec: Option<Codec>,
     _message: PhantomData<T>,
 }
 
-#[allow(single_use_lifetimes)] // false positive
-impl<T: for<'a> Deserialize<'a>> WebSocketReceiver<T> {
-    pub fn new(receiver: SplitStream<WebSocketStream<TcpStream>>) -> Self {
+impl<T: DeserializeOwned> WebSocketReceiver<T> {
+    // If the codec is not known yet, it is chosen by the type of the first data frame.
+    pub fn new(receiver: SplitStream<WebSocketStream<Upgraded>>, codec: Option<Codec>) -> Self {
         Self {
             receiver,
+            codec,
             _message: PhantomData,
         }
     }
 
-    pub async fn recv(&mut self) -> Option<T> {
-        use bincode::deserialize;
+    pub fn codec(&self) -> Option<Codec> {
+        self.codec
+    }
+
+    pub async fn recv(&mut self) -> Result<Option<T>, Error> {
         use futures::StreamExt;
+        use protocol::Frame;
 
-        let message = self.receiver.next().await.unwrap().unwrap();
-        match message {
-            Message::Binary(data) => Some(deserialize(&data[..]).unwrap()),
-            Message::Close(_) => None,
-            _ => {
-                log::info!("invalid message: {:?}", message);
-                None
-            }
+        while let Some(message) = self.receiver.next().await {
+            let frame = match message? {
+                Message::Binary(data) => Frame::Binary(data),
+                Message::Text(text) => Frame::Text(text),
+                Message::Close(_) => return Ok(None),
+                Message::Ping(_) | Message::Pong(_) => continue,
+            };
+            let codec = *self.codec.get_or_insert_with(|| Codec::from_frame(&frame));
+            return Ok(Some(codec.decode(&frame)?));
         }
+        Ok(None)
+    }
+
+    // Should be called after the sender is closed. Keeps the connection open until the client
+    // replies with its close frame, so that it can still read the last messages, e.g. an error.
+    pub async fn wait_closed(&mut self) {
+        use futures::StreamExt;
+        use tokio::time::timeout;
+
+        let receiver = &mut self.receiver;
+        let drain = async { while let Some(Ok(_)) = receiver.next().await {} };
+        let _: Result<(), _> = timeout(CLOSE_TIMEOUT, drain).await;
     }
 
-    pub fn into_stream(self) -> SplitStream<WebSocketStream<TcpStream>> {
+    pub fn into_stream(self) -> SplitStream<WebSocketStream<Upgraded>> {
         self.receiver
     }
 }
@@ -1,30 +1,55 @@
 use core::marker::PhantomData;
 
 use futures::stream::SplitSink;
+use hyper::upgrade::Upgraded;
+use protocol::Codec;
 use serde::Serialize;
-use tokio::net::TcpStream;
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::WebSocketStream;
 
+use crate::Error;
+
 #[derive(Debug)]
 pub struct WebSocketSender<T> {
-    sender: SplitSink<WebSocketStream<TcpStream>, Message>,
+    sender: SplitSink<WebSocketStream<Upgraded>, Message>,
+    codec: Codec,
     _message: PhantomData<T>,
 }
 
 impl<T: Serialize> WebSocketSender<T> {
-    pub fn new(sender: SplitSink<WebSocketStream<TcpStream>, Message>) -> Self {
+    pub fn new(sender: SplitSink<WebSocketStream<Upgraded>, Message>, codec: Codec) -> Self {
         Self {
             sender,
+            codec,
             _message: PhantomData,
         }
     }
 
-    pub async fn send(&mut self, message: T) {
-        use bincode::serialize;
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+
+    pub async fn send(&mut self, message: T) -> Result<(), Error> {
+        use futures::SinkExt;
+        use protocol::Frame;
+
+        let message = match self.codec.encode(&message)? {
+            Frame::Binary(data) => Message::Binary(data),
+            Frame::Text(text) => Message::Text(text),
+        };
+        self.sender.send(message).await?;
+        Ok(())
+    }
+
+    pub fn into_sink(self) -> SplitSink<WebSocketStream<Upgraded>, Message> {
+        self.sender
+    }
+
+    pub async fn close(&mut self) {
         use futures::SinkExt;
 
-        let message: Vec<u8> = serialize(&message).unwrap();
-        self.sender.send(Message::Binary(message)).await.unwrap();
+        if let Err(err) = self.sender.close().await {
+            log::debug!("websocket close failed: {}", err);
+        }
     }
 }
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use protocol::{RoomName, SessionDescription};
+use tokio::sync::Mutex;
+
+use crate::{ChannelId, Channels, Error, WebRtcApi, WebRtcSender};
+
+// Serves a WHEP receiver, e.g. an off-the-shelf player, signaled over HTTP instead of WebSocket.
+#[derive(Debug)]
+pub struct WhepSender {
+    room: RoomName,
+    channels: Arc<Mutex<Channels>>,
+    webrtc_sender: Arc<WebRtcSender>,
+}
+
+impl WhepSender {
+    pub async fn new(
+        room: RoomName,
+        offer: SessionDescription,
+        channels: Arc<Mutex<Channels>>,
+        webrtc_api: Arc<WebRtcApi>,
+    ) -> Result<(Self, SessionDescription), Error> {
+        let channel_receiver = channels.lock().await.receiver(&room);
+
+        let webrtc_sender = match WebRtcSender::new(webrtc_api, channel_receiver, None).await {
+            Ok(webrtc_sender) => webrtc_sender,
+            Err(err) => {
+                channels.lock().await.remove_receiver(&room);
+                return Err(err);
+            }
+        };
+
+        let sender = Self {
+            room,
+            channels,
+            webrtc_sender,
+        };
+        match sender.webrtc_sender.on_whep_offer(offer).await {
+            Ok(answer) => Ok((sender, answer)),
+            Err(err) => {
+                sender.close().await;
+                Err(err)
+            }
+        }
+    }
+
+    pub fn room(&self) -> &RoomName {
+        &self.room
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        self.webrtc_sender.channel_id()
+    }
+
+    pub async fn disconnected(&self) {
+        self.webrtc_sender.disconnected().await
+    }
+
+    pub async fn close(&self) {
+        self.webrtc_sender.close().await;
+        self.channels.lock().await.remove_receiver(&self.room);
+    }
+}
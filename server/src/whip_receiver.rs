@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use protocol::{RoomName, SessionDescription};
+use tokio::sync::Mutex;
+
+use crate::{ChannelId, Channels, Error, WebRtcApi, WebRtcReceiver};
+
+// Serves a WHIP sender, e.g. OBS or GStreamer `whipsink`, signaled over HTTP instead of WebSocket.
+#[derive(Debug)]
+pub struct WhipReceiver {
+    room: RoomName,
+    channels: Arc<Mutex<Channels>>,
+    webrtc_receiver: Arc<WebRtcReceiver>,
+}
+
+impl WhipReceiver {
+    pub async fn new(
+        room: RoomName,
+        offer: SessionDescription,
+        channels: Arc<Mutex<Channels>>,
+        webrtc_api: Arc<WebRtcApi>,
+    ) -> Result<(Self, SessionDescription), Error> {
+        let channel_sender = channels.lock().await.sender(&room)?;
+
+        let webrtc_receiver = match WebRtcReceiver::new(webrtc_api, channel_sender, None).await {
+            Ok(webrtc_receiver) => webrtc_receiver,
+            Err(err) => {
+                channels.lock().await.remove_sender(&room);
+                return Err(err);
+            }
+        };
+
+        let receiver = Self {
+            room,
+            channels,
+            webrtc_receiver,
+        };
+        match receiver.webrtc_receiver.on_whip_offer(offer).await {
+            Ok(answer) => Ok((receiver, answer)),
+            Err(err) => {
+                receiver.close().await;
+                Err(err)
+            }
+        }
+    }
+
+    pub fn room(&self) -> &RoomName {
+        &self.room
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        self.webrtc_receiver.channel_id()
+    }
+
+    pub async fn disconnected(&self) {
+        self.webrtc_receiver.disconnected().await
+    }
+
+    pub async fn close(&self) {
+        self.webrtc_receiver.close().await;
+        self.channels.lock().await.remove_sender(&self.room);
+    }
+}
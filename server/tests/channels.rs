@@ -0,0 +1,171 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+// The forwarding from the channel sender to the queues of the subscribers, without WebRTC.
+
+use core::time::Duration;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use protocol::RoomName;
+use server::{ChannelMessage, ChannelReceiver, ChannelSender, Channels, DroppedPackets};
+
+const KEYFRAME: &[u8] = &[0x10, 0x00];
+const DELTA_FRAME: &[u8] = &[0x10, 0x01];
+
+fn video(sequence_number: u16, payload: &'static [u8]) -> ChannelMessage {
+    ChannelMessage::Video(Arc::new(packet(sequence_number, payload)))
+}
+
+fn audio(sequence_number: u16) -> ChannelMessage {
+    ChannelMessage::Audio(Arc::new(packet(sequence_number, &[0xf8])))
+}
+
+fn packet(sequence_number: u16, payload: &'static [u8]) -> rtp::packet::Packet {
+    use rtp::header::Header;
+    use rtp::packet::Packet;
+
+    Packet {
+        header: Header {
+            sequence_number,
+            ..Header::default()
+        },
+        payload: Bytes::from_static(payload),
+    }
+}
+
+fn is_keyframe(payload: &'static [u8]) -> bool {
+    video(0, payload).is_keyframe()
+}
+
+// A room with a sender and a subscriber whose media queue takes `queue_size` packets.
+fn channel(queue_size: usize) -> (ChannelSender, ChannelReceiver) {
+    let mut channels = Channels::new(queue_size, None);
+    let room = RoomName("room".to_owned());
+    let sender = channels.sender(&room).unwrap();
+    let receiver = channels.receiver(&room);
+    (sender, receiver)
+}
+
+// Describes the queued messages, e.g. `V1` for the video packet with sequence number 1.
+async fn received(receiver: &ChannelReceiver) -> Vec<String> {
+    use tokio::time::timeout;
+
+    let mut messages = Vec::new();
+    while let Ok(Some(message)) = timeout(Duration::from_millis(10), receiver.recv()).await {
+        messages.push(match message {
+            ChannelMessage::Data(data) => format!("D{}", String::from_utf8_lossy(&data)),
+            ChannelMessage::Video(packet) => format!("V{}", packet.header.sequence_number),
+            ChannelMessage::Audio(packet) => format!("A{}", packet.header.sequence_number),
+        });
+    }
+    messages
+}
+
+async fn keyframe_requested(sender: &ChannelSender) -> bool {
+    use tokio::time::timeout;
+
+    timeout(Duration::from_millis(10), sender.keyframe_requested())
+        .await
+        .is_ok()
+}
+
+#[test]
+fn vp8_keyframes() {
+    assert!(is_keyframe(KEYFRAME));
+    // With a 7 bit and a 15 bit picture ID, and with all extensions.
+    assert!(is_keyframe(&[0x90, 0x80, 0x05, 0x00]));
+    assert!(is_keyframe(&[0x90, 0x80, 0x85, 0x05, 0x00]));
+    assert!(is_keyframe(&[0x90, 0xf0, 0x85, 0x05, 0x01, 0x02, 0x00]));
+}
+
+#[test]
+fn vp8_non_keyframes() {
+    assert!(!is_keyframe(DELTA_FRAME));
+    assert!(!is_keyframe(&[0x90, 0xf0, 0x85, 0x05, 0x01, 0x02, 0x01]));
+    // Not the start of the first partition.
+    assert!(!is_keyframe(&[0x00, 0x00]));
+    assert!(!is_keyframe(&[0x11, 0x00]));
+    // Truncated.
+    assert!(!is_keyframe(&[]));
+    assert!(!is_keyframe(&[0x10]));
+    assert!(!is_keyframe(&[0x90, 0x80]));
+    assert!(!is_keyframe(&[0x90, 0xf0, 0x85, 0x05, 0x01, 0x02]));
+
+    assert!(!audio(0).is_keyframe());
+    assert!(!ChannelMessage::Data(Bytes::from_static(&[0x10, 0x00])).is_keyframe());
+}
+
+#[tokio::test]
+async fn messages_are_forwarded() {
+    let (sender, receiver) = channel(8);
+
+    sender.send(video(1, KEYFRAME));
+    sender.send(audio(2));
+    sender.send(video(3, DELTA_FRAME));
+
+    assert_eq!(received(&receiver).await, ["V1", "A2", "V3"]);
+    assert_eq!(receiver.dropped_packets(), DroppedPackets::default());
+}
+
+#[tokio::test]
+async fn full_queue_drops_video_until_keyframe() {
+    let (sender, receiver) = channel(2);
+
+    sender.send(video(1, KEYFRAME));
+    sender.send(video(2, DELTA_FRAME));
+    assert!(!keyframe_requested(&sender).await);
+    // The queue is full, so the video is dropped from here on and a keyframe is requested.
+    sender.send(video(3, DELTA_FRAME));
+    sender.send(audio(4));
+    sender.send(ChannelMessage::Data(Bytes::from_static(b"5")));
+    assert!(keyframe_requested(&sender).await);
+    // Data is preferred.
+    assert_eq!(received(&receiver).await, ["D5", "V1", "V2"]);
+
+    // Audio resumes right away, video with the next keyframe.
+    sender.send(video(6, DELTA_FRAME));
+    sender.send(audio(7));
+    assert_eq!(received(&receiver).await, ["A7"]);
+    sender.send(video(8, KEYFRAME));
+    sender.send(video(9, DELTA_FRAME));
+    assert_eq!(received(&receiver).await, ["V8", "V9"]);
+    assert!(!keyframe_requested(&sender).await);
+
+    assert_eq!(
+        receiver.dropped_packets(),
+        DroppedPackets { video: 2, audio: 1 }
+    );
+}
+
+#[tokio::test]
+async fn data_is_never_dropped() {
+    let (sender, receiver) = channel(1);
+
+    sender.send(video(0, KEYFRAME));
+    for index in 0..100 {
+        sender.send(ChannelMessage::Data(Bytes::from(index.to_string())));
+        sender.send(audio(index));
+    }
+
+    let messages = received(&receiver).await;
+    let data: Vec<String> = (0..100).map(|index| format!("D{}", index)).collect();
+    assert_eq!(messages[..100], data[..]);
+    assert_eq!(messages[100..], ["V0"]);
+    assert_eq!(
+        receiver.dropped_packets(),
+        DroppedPackets {
+            video: 0,
+            audio: 100
+        }
+    );
+}
@@ -0,0 +1,726 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+// End-to-end tests over loopback, the server and the clients use host ICE candidates only.
+
+use core::future::Future;
+use core::time::Duration;
+
+use protocol::IceServer;
+use server::{ForwardingSettings, NetworkSettings, Server, ServerSettings, TurnCredentials};
+use test_client::{Error, Receiver, Sender};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+const PACKET_INTERVAL: Duration = Duration::from_millis(20);
+const TEXT: &str = "hello from sender";
+
+// Starts a server on an ephemeral port and returns its WebSocket address.
+async fn start_server(settings: ServerSettings) -> String {
+    use tokio::spawn;
+    use tokio::task::JoinHandle;
+
+    let server = Server::new("127.0.0.1:0", settings).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let _join_handle: JoinHandle<()> = spawn(server.run());
+    format!("ws://{}{}", addr, protocol::WEBSOCKET_PATH)
+}
+
+async fn connect_sender(addr: &str, room: &str) -> Sender {
+    let sender = Sender::connect(addr, room).await.unwrap();
+    sender.wait_connected(TIMEOUT).await.unwrap();
+    sender
+}
+
+async fn connect_receiver(addr: &str, room: &str) -> Receiver {
+    let receiver = Receiver::connect(addr, room).await.unwrap();
+    receiver.wait_connected(TIMEOUT).await.unwrap();
+    receiver
+}
+
+// Keeps sending media and text until `done` resolves,
+// since packets sent before the receiver tracks are negotiated are lost.
+async fn stream_until<T>(
+    sender: &Sender,
+    done: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    use tokio::time::interval;
+
+    tokio::pin!(done);
+    let mut ticker = interval(PACKET_INTERVAL);
+    loop {
+        tokio::select! {
+            result = &mut done => return result,
+            _ = ticker.tick() => {
+                sender.send_video().await?;
+                sender.send_audio().await?;
+                sender.send_text(TEXT).await?;
+            }
+        }
+    }
+}
+
+async fn assert_forwarding(sender: &Sender, receiver: &Receiver) {
+    let text = stream_until(sender, receiver.wait_for_text(TIMEOUT))
+        .await
+        .unwrap();
+    assert_eq!(text, TEXT);
+    stream_until(sender, receiver.wait_for_video(10, TIMEOUT))
+        .await
+        .unwrap();
+    stream_until(sender, receiver.wait_for_audio(10, TIMEOUT))
+        .await
+        .unwrap();
+}
+
+// Nothing is streamed anymore, so only the retransmission can arrive.
+async fn assert_retransmitted_once(sender: &Sender, receiver: &Receiver, sequence_number: u16) {
+    let video_packets = receiver.video_packets();
+    receiver.send_nack(sequence_number).await.unwrap();
+    receiver
+        .wait_for_video(video_packets + 1, TIMEOUT)
+        .await
+        .unwrap();
+    // Gives a second retransmission time to arrive.
+    let result = sender
+        .wait_for_nack(sequence_number, Duration::from_secs(1))
+        .await;
+    assert!(matches!(result, Err(Error::Timeout(_))), "{:?}", result);
+    assert_eq!(receiver.video_packets(), video_packets + 1);
+    assert_eq!(receiver.video_packet_copies(sequence_number), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sender_then_receiver() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receiver_then_sender() {
+    let addr = start_server(ServerSettings::default()).await;
+    let receiver = connect_receiver(&addr, "room").await;
+    let sender = connect_sender(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+
+    receiver.close().await;
+    sender.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn multiple_receivers() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let first = connect_receiver(&addr, "room").await;
+    let second = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &first).await;
+    assert_forwarding(&sender, &second).await;
+
+    sender.close().await;
+    first.close().await;
+    second.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rooms_are_isolated() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "first").await;
+    let receiver = connect_receiver(&addr, "second").await;
+
+    let result = stream_until(&sender, receiver.wait_for_text(Duration::from_secs(2))).await;
+    assert!(matches!(result, Err(Error::Timeout(_))));
+    assert_eq!(receiver.video_packets(), 0);
+    assert_eq!(receiver.audio_packets(), 0);
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receiver_without_rooms_joins_default_room() {
+    use protocol::DEFAULT_ROOM;
+
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, DEFAULT_ROOM).await;
+    let receiver = Receiver::connect_with_capabilities(&addr, "room", &[])
+        .await
+        .unwrap();
+    receiver.wait_connected(TIMEOUT).await.unwrap();
+
+    assert_forwarding(&sender, &receiver).await;
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn incompatible_protocol_version_is_rejected() {
+    use futures::{SinkExt, StreamExt};
+    use protocol::{ClientMessage, Codec, ErrorCode, Frame, ServerMessage, PROTOCOL_VERSION};
+    use tokio_tungstenite::connect_async;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    let addr = start_server(ServerSettings::default()).await;
+    let (mut websocket, _) = connect_async(&addr).await.unwrap();
+    let hello = ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION + 1,
+        capabilities: Vec::new(),
+    };
+    let data = match Codec::Bincode.encode(&hello).unwrap() {
+        Frame::Binary(data) => data,
+        Frame::Text(_) => unreachable!("bincode frames are binary"),
+    };
+    websocket.send(Message::Binary(data)).await.unwrap();
+
+    let data = match websocket.next().await {
+        Some(Ok(Message::Binary(data))) => data,
+        message => panic!("unexpected message: {:?}", message),
+    };
+    let message: ServerMessage = Codec::Bincode.decode(&Frame::Binary(data)).unwrap();
+    assert!(matches!(
+        message,
+        ServerMessage::Error {
+            code: ErrorCode::IncompatibleProtocolVersion,
+            ..
+        }
+    ));
+    // The server closes the socket after the error.
+    while let Some(Ok(message)) = websocket.next().await {
+        assert!(message.is_close(), "{:?}", message);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn repeated_handshake_is_rejected() {
+    use futures::{SinkExt, StreamExt};
+    use protocol::{ClientMessage, Codec, ErrorCode, Frame, ServerMessage, PROTOCOL_VERSION};
+    use tokio_tungstenite::connect_async;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    let addr = start_server(ServerSettings::default()).await;
+    let (mut websocket, _) = connect_async(&addr).await.unwrap();
+    let hello = ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: Vec::new(),
+    };
+    let data = match Codec::Bincode.encode(&hello).unwrap() {
+        Frame::Binary(data) => data,
+        Frame::Text(_) => unreachable!("bincode frames are binary"),
+    };
+    websocket.send(Message::Binary(data.clone())).await.unwrap();
+    websocket.send(Message::Binary(data)).await.unwrap();
+
+    let mut messages = Vec::new();
+    while let Some(Ok(Message::Binary(data))) = websocket.next().await {
+        let message: ServerMessage = Codec::Bincode.decode(&Frame::Binary(data)).unwrap();
+        messages.push(message);
+    }
+    assert!(
+        matches!(
+            messages.as_slice(),
+            [
+                ServerMessage::Hello { .. },
+                ServerMessage::Error {
+                    code: ErrorCode::InvalidMessage,
+                    ..
+                }
+            ]
+        ),
+        "{:?}",
+        messages
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn second_sender_is_rejected() {
+    use protocol::ErrorCode;
+
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+
+    // The error may arrive before the client has finished sending its offer.
+    let result = match Sender::connect(&addr, "room").await {
+        Ok(second) => {
+            let result = second.wait_closed(TIMEOUT).await;
+            second.close().await;
+            result
+        }
+        Err(err) => Err(err),
+    };
+    assert!(
+        matches!(
+            result,
+            Err(Error::Server {
+                code: ErrorCode::RoomFull,
+                ..
+            })
+        ),
+        "{:?}",
+        result
+    );
+
+    sender.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sender_disconnect_closes_receivers() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+    assert_forwarding(&sender, &receiver).await;
+
+    sender.close().await;
+    receiver.wait_closed(TIMEOUT).await.unwrap();
+    receiver.close().await;
+
+    // The room is released, so a new session can be started in it.
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+    assert_forwarding(&sender, &receiver).await;
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receiver_disconnect_keeps_sender() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+    assert_forwarding(&sender, &receiver).await;
+
+    receiver.close().await;
+
+    // The sender is not affected and keeps streaming to the new receivers.
+    let receiver = connect_receiver(&addr, "room").await;
+    assert_forwarding(&sender, &receiver).await;
+
+    sender.close().await;
+    receiver.wait_closed(TIMEOUT).await.unwrap();
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receiver_attach_requests_keyframe() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    stream_until(&sender, sender.wait_for_keyframe_requests(1, TIMEOUT))
+        .await
+        .unwrap();
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downstream_keyframe_request_is_forwarded() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    let keyframe_requests = sender.keyframe_requests();
+    receiver.request_keyframe().await.unwrap();
+    stream_until(
+        &sender,
+        sender.wait_for_keyframe_requests(keyframe_requests + 1, TIMEOUT),
+    )
+    .await
+    .unwrap();
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downstream_nack_is_forwarded() {
+    const SEQUENCE_NUMBER: u16 = 1234;
+
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    // The sender only gets the NACK if the server has replaced its own SSRC by the sender's.
+    receiver.send_nack(SEQUENCE_NUMBER).await.unwrap();
+    stream_until(&sender, sender.wait_for_nack(SEQUENCE_NUMBER, TIMEOUT))
+        .await
+        .unwrap();
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downstream_feedback_ssrcs_are_translated() {
+    use tokio::time::interval;
+
+    const SEQUENCE_NUMBER: u16 = 1234;
+
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    // The receiver addresses its feedback to the SSRC chosen by the server.
+    let downstream_ssrc = receiver.video_ssrc().unwrap();
+    assert_ne!(downstream_ssrc, sender.video_ssrc());
+
+    receiver.send_nack(SEQUENCE_NUMBER).await.unwrap();
+    stream_until(&sender, sender.wait_for_nack(SEQUENCE_NUMBER, TIMEOUT))
+        .await
+        .unwrap();
+    assert_eq!(
+        sender.nack_ssrcs(SEQUENCE_NUMBER),
+        vec![sender.video_ssrc()]
+    );
+
+    let bitrate_reported = async {
+        let done = sender.wait_for_bitrate(300_000, TIMEOUT);
+        tokio::pin!(done);
+        let mut ticker = interval(Duration::from_millis(100));
+        loop {
+            tokio::select! {
+                result = &mut done => return result,
+                _ = ticker.tick() => receiver.send_remb(300_000).await?,
+            }
+        }
+    };
+    stream_until(&sender, bitrate_reported).await.unwrap();
+    let remb_ssrcs = sender.remb_ssrcs();
+    assert!(
+        remb_ssrcs.contains(&sender.video_ssrc()),
+        "{:?}",
+        remb_ssrcs
+    );
+    assert!(!remb_ssrcs.contains(&downstream_ssrc), "{:?}", remb_ssrcs);
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nack_is_answered_by_server() {
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    let sequence_number = receiver.last_video_sequence_number().unwrap();
+    assert_retransmitted_once(&sender, &receiver, sequence_number).await;
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nack_is_answered_from_large_buffer() {
+    // Beyond the 8192 packets kept by the NACK responder of webrtc-rs.
+    const PACKETS: u16 = 10_000;
+    const BATCH: u16 = 50;
+
+    let settings = ServerSettings {
+        forwarding_settings: ForwardingSettings {
+            nack_buffer_size: 16_384,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let addr = start_server(settings).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    let sequence_number = receiver.last_video_sequence_number().unwrap();
+    // Sent in batches, so that none of them is lost on the way.
+    for _ in 0..PACKETS / BATCH {
+        let video_packets = receiver.video_packets();
+        for _ in 0..BATCH {
+            sender.send_video().await.unwrap();
+        }
+        receiver
+            .wait_for_video(video_packets + usize::from(BATCH), TIMEOUT)
+            .await
+            .unwrap();
+    }
+    assert_retransmitted_once(&sender, &receiver, sequence_number).await;
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nack_is_forwarded_without_buffer() {
+    let settings = ServerSettings {
+        forwarding_settings: ForwardingSettings {
+            nack_buffer_size: 0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let addr = start_server(settings).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    let sequence_number = receiver.last_video_sequence_number().unwrap();
+    receiver.send_nack(sequence_number).await.unwrap();
+    sender
+        .wait_for_nack(sequence_number, TIMEOUT)
+        .await
+        .unwrap();
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn media_is_recorded() {
+    use server::Recorder;
+    use std::path::Path;
+    use tokio::time::{sleep, Instant};
+
+    // The sizes of the recorded files with the extension, 0 until they are flushed.
+    fn recorded(dir: &Path, extension: &str) -> Vec<u64> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension() == Some(extension.as_ref()))
+            .map(|path| path.metadata().unwrap().len())
+            .collect()
+    }
+
+    let dir = std::env::temp_dir().join(format!("server-recordings-{}", std::process::id()));
+    let settings = ServerSettings {
+        recorder: Some(Recorder::new(dir.clone()).unwrap()),
+        ..Default::default()
+    };
+    let addr = start_server(settings).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    sender.close().await;
+    receiver.close().await;
+
+    // The recordings are finished once the tracks have ended.
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let video = recorded(&dir, "ivf");
+        let audio = recorded(&dir, "ogg");
+        // More than the 32 byte IVF header.
+        if video.len() == 1 && video[0] > 32 && audio.len() == 1 && audio[0] > 0 {
+            break;
+        }
+        assert!(Instant::now() < deadline, "{:?} {:?}", video, audio);
+        sleep(Duration::from_millis(100)).await;
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stalled_receiver_drops_media_but_not_data() {
+    use protocol::RoomName;
+    use tokio::spawn;
+    use tokio::task::JoinHandle;
+    use tokio::time::timeout;
+
+    const QUEUE_SIZE: usize = 16;
+
+    let settings = ServerSettings {
+        forwarding_settings: ForwardingSettings {
+            subscriber_queue_size: QUEUE_SIZE,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server = Server::new("127.0.0.1:0", settings).await.unwrap();
+    let addr = format!(
+        "ws://{}{}",
+        server.local_addr().unwrap(),
+        protocol::WEBSOCKET_PATH
+    );
+    let channels = server.channels();
+    let _join_handle: JoinHandle<()> = spawn(server.run());
+    // Subscribes like a receiver whose connection has stalled, its queues are not read.
+    let stalled = channels.lock().await.receiver(&RoomName("room".to_owned()));
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    // The other receiver does not notice.
+    assert_forwarding(&sender, &receiver).await;
+    let dropped = stalled.dropped_packets();
+    assert!(dropped.video > 0 && dropped.audio > 0, "{:?}", dropped);
+    // The full media queue is followed by all the texts.
+    let mut messages = 0;
+    while let Ok(Some(_)) = timeout(Duration::from_millis(100), stalled.recv()).await {
+        messages += 1;
+    }
+    assert!(messages > QUEUE_SIZE, "{}", messages);
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lowest_downstream_bitrate_is_forwarded() {
+    use tokio::time::interval;
+
+    let addr = start_server(ServerSettings::default()).await;
+    let sender = connect_sender(&addr, "room").await;
+    let fast_receiver = connect_receiver(&addr, "room").await;
+    let slow_receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &fast_receiver).await;
+    assert_forwarding(&sender, &slow_receiver).await;
+    let bitrate_reported = async {
+        let done = sender.wait_for_bitrate(300_000, TIMEOUT);
+        tokio::pin!(done);
+        let mut ticker = interval(Duration::from_millis(100));
+        loop {
+            tokio::select! {
+                result = &mut done => return result,
+                _ = ticker.tick() => {
+                    fast_receiver.send_remb(500_000).await?;
+                    slow_receiver.send_remb(300_000).await?;
+                }
+            }
+        }
+    };
+    stream_until(&sender, bitrate_reported).await.unwrap();
+
+    sender.close().await;
+    fast_receiver.close().await;
+    slow_receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pushed_ice_servers() {
+    // Nothing listens there, so both sides fall back to host candidates.
+    let settings = ServerSettings {
+        ice_servers: vec![IceServer {
+            urls: vec!["stun:127.0.0.1:9".to_owned()],
+            username: None,
+            credential: None,
+        }],
+        ..Default::default()
+    };
+    let addr = start_server(settings).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn minted_turn_credentials() {
+    // Nothing listens there, so both sides fall back to host candidates.
+    let turn_credentials = TurnCredentials::new(
+        vec!["turn:127.0.0.1:9".to_owned()],
+        "secret".to_owned(),
+        Duration::from_secs(60),
+    );
+    let settings = ServerSettings {
+        turn_credentials: Some(turn_credentials),
+        ..Default::default()
+    };
+    let addr = start_server(settings).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn udp_port_range() {
+    use std::net::UdpSocket;
+
+    const PORT_MIN: u16 = 20000;
+    const PORT_MAX: u16 = 20049;
+
+    let settings = ServerSettings {
+        network_settings: NetworkSettings {
+            udp_port_range: Some((PORT_MIN, PORT_MAX)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let addr = start_server(settings).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    assert_forwarding(&sender, &receiver).await;
+    // The server side ICE sockets occupy ports of the range.
+    let used_ports = (PORT_MIN..=PORT_MAX)
+        .filter(|port| UdpSocket::bind(("0.0.0.0", *port)).is_err())
+        .count();
+    assert!(used_ports >= 2, "{}", used_ports);
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn udp_mux_port() {
+    use std::net::UdpSocket;
+
+    const PORT: u16 = 20060;
+
+    let settings = ServerSettings {
+        network_settings: NetworkSettings {
+            udp_mux_port: Some(PORT),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let addr = start_server(settings).await;
+    let sender = connect_sender(&addr, "room").await;
+    let receiver = connect_receiver(&addr, "room").await;
+
+    // Both peer connections go through the one port.
+    assert_forwarding(&sender, &receiver).await;
+    assert!(UdpSocket::bind(("0.0.0.0", PORT)).is_err());
+
+    sender.close().await;
+    receiver.close().await;
+}
+
+#[tokio::test]
+async fn turn_server_without_credentials_is_rejected() {
+    use server::Error;
+
+    let settings = ServerSettings {
+        ice_servers: vec![IceServer {
+            urls: vec!["turn:127.0.0.1:3478".to_owned()],
+            username: Some("user".to_owned()),
+            credential: None,
+        }],
+        ..Default::default()
+    };
+    let result = Server::new("127.0.0.1:0", settings).await;
+    assert!(matches!(result, Err(Error::TurnCredentialsExpected(_))));
+}
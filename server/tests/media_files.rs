@@ -0,0 +1,350 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+// The IVF and Ogg readers of the virtual sender, and round trips through the recorder writers.
+
+use core::time::Duration;
+
+use bytes::Bytes;
+use rtp::packet::Packet;
+use server::{Error, IvfReader, IvfWriter, OggReader, OggWriter};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+const RTP_MTU: usize = 1188;
+
+// A VP8 frame of the given size, the first bytes are a 640x480 keyframe header.
+fn vp8_frame(keyframe: bool, size: usize) -> Bytes {
+    let mut frame: Vec<u8> = (0..size).map(|index| index as u8).collect();
+    frame[..10].copy_from_slice(&[0x00, 0x00, 0x00, 0x9d, 0x01, 0x2a, 0x80, 0x02, 0xe0, 0x01]);
+    if !keyframe {
+        frame[0] = 0x01;
+    }
+    Bytes::from(frame)
+}
+
+// An IVF file with a 1/30 timebase.
+fn ivf_file(frames: &[(u64, &[u8])]) -> Vec<u8> {
+    let mut file = Vec::new();
+    file.extend_from_slice(b"DKIF");
+    file.extend_from_slice(&0_u16.to_le_bytes());
+    file.extend_from_slice(&32_u16.to_le_bytes());
+    file.extend_from_slice(b"VP80");
+    file.extend_from_slice(&640_u16.to_le_bytes());
+    file.extend_from_slice(&480_u16.to_le_bytes());
+    file.extend_from_slice(&30_u32.to_le_bytes());
+    file.extend_from_slice(&1_u32.to_le_bytes());
+    file.extend_from_slice(&(frames.len() as u32).to_le_bytes());
+    file.extend_from_slice(&0_u32.to_le_bytes());
+    for (timestamp, frame) in frames {
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&timestamp.to_le_bytes());
+        file.extend_from_slice(frame);
+    }
+    file
+}
+
+// An Ogg page without checksum, the reader does not verify it.
+fn ogg_page(header_type: u8, lacing_values: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut page = Vec::new();
+    page.extend_from_slice(b"OggS");
+    page.push(0);
+    page.push(header_type);
+    page.extend_from_slice(&0_u64.to_le_bytes());
+    page.extend_from_slice(&1_u32.to_le_bytes());
+    page.extend_from_slice(&0_u32.to_le_bytes());
+    page.extend_from_slice(&0_u32.to_le_bytes());
+    page.push(lacing_values.len() as u8);
+    page.extend_from_slice(lacing_values);
+    page.extend_from_slice(payload);
+    page
+}
+
+fn opus_head() -> Vec<u8> {
+    let mut opus_head = b"OpusHead".to_vec();
+    opus_head.extend_from_slice(&[1, 2, 0x00, 0x0f, 0x80, 0xbb, 0x00, 0x00, 0, 0, 0]);
+    opus_head
+}
+
+// Packetizes the frames like the virtual sender, 3000 ticks of the 90 kHz clock apart.
+fn vp8_packets(frames: &[Bytes]) -> Vec<Packet> {
+    use rtp::codecs::vp8::Vp8Payloader;
+    use rtp::header::Header;
+    use rtp::packetizer::Payloader;
+
+    let mut payloader = Vp8Payloader::default();
+    let mut packets = Vec::new();
+    for (index, frame) in frames.iter().enumerate() {
+        let payloads = payloader.payload(RTP_MTU, frame).unwrap();
+        let count = payloads.len();
+        for (payload_index, payload) in payloads.into_iter().enumerate() {
+            packets.push(Packet {
+                header: Header {
+                    sequence_number: packets.len() as u16,
+                    timestamp: index as u32 * 3000,
+                    marker: payload_index + 1 == count,
+                    ..Header::default()
+                },
+                payload,
+            });
+        }
+    }
+    packets
+}
+
+async fn write_ivf(frames: &[Bytes]) -> Vec<u8> {
+    let mut writer = IvfWriter::new(Vec::new());
+    for packet in vp8_packets(frames) {
+        writer.write_rtp(&packet).await.unwrap();
+    }
+    writer.finish().await.unwrap();
+    writer.into_inner()
+}
+
+// Writes 20 ms packets, 960 ticks of the 48 kHz clock apart.
+async fn write_ogg(packets: &[Bytes]) -> Vec<u8> {
+    use rtp::header::Header;
+
+    let mut writer = OggWriter::new(Vec::new(), 1).await.unwrap();
+    for (index, payload) in packets.iter().enumerate() {
+        let packet = Packet {
+            header: Header {
+                sequence_number: index as u16,
+                timestamp: index as u32 * 960,
+                ..Header::default()
+            },
+            payload: payload.clone(),
+        };
+        writer.write_rtp(&packet).await.unwrap();
+    }
+    writer.finish().await.unwrap();
+    writer.into_inner()
+}
+
+// A 20 ms fullband Opus packet of the given size.
+fn opus_packet(size: usize) -> Bytes {
+    let mut packet = vec![0x55; size];
+    packet[0] = 0xf8;
+    Bytes::from(packet)
+}
+
+fn read_ivf(file: Vec<u8>) -> Vec<(Duration, Bytes)> {
+    let mut reader = IvfReader::new(Bytes::from(file)).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next_frame() {
+        frames.push((frame.position, frame.data));
+    }
+    frames
+}
+
+fn read_ogg(file: Vec<u8>) -> Vec<Bytes> {
+    let mut reader = OggReader::new(Bytes::from(file)).unwrap();
+    let mut packets = Vec::new();
+    while let Some(packet) = reader.next_opus_packet().unwrap() {
+        packets.push(packet);
+    }
+    packets
+}
+
+#[test]
+fn ivf_frames() {
+    let file = ivf_file(&[(0, &[1, 2, 3]), (1, &[4]), (3, &[5, 6])]);
+
+    assert_eq!(
+        read_ivf(file),
+        vec![
+            (Duration::from_secs(0), Bytes::from_static(&[1, 2, 3])),
+            (Duration::from_nanos(33_333_333), Bytes::from_static(&[4])),
+            (Duration::from_millis(100), Bytes::from_static(&[5, 6])),
+        ]
+    );
+}
+
+#[test]
+fn invalid_ivf_headers() {
+    let file = ivf_file(&[]);
+    let mut wrong_signature = file.clone();
+    wrong_signature[0..4].copy_from_slice(b"RIFF");
+    let mut vp9 = file.clone();
+    vp9[8..12].copy_from_slice(b"VP90");
+    let mut short_header = file.clone();
+    short_header[6..8].copy_from_slice(&16_u16.to_le_bytes());
+    let mut zero_timebase = file.clone();
+    zero_timebase[16..20].copy_from_slice(&0_u32.to_le_bytes());
+
+    for file in [
+        Vec::new(),
+        file[..31].to_vec(),
+        wrong_signature,
+        vp9,
+        short_header,
+        zero_timebase,
+    ] {
+        let result = IvfReader::new(Bytes::from(file));
+        assert!(
+            matches!(result, Err(Error::InvalidMediaFile(_))),
+            "{:?}",
+            result
+        );
+    }
+}
+
+#[test]
+fn truncated_ivf_frames_are_ignored() {
+    let file = ivf_file(&[(0, &[1, 2, 3]), (1, &[4, 5, 6])]);
+
+    // In the data and in the header of the last frame.
+    for size in [file.len() - 1, file.len() - 3 - 6] {
+        let frames = read_ivf(file[..size].to_vec());
+        assert_eq!(
+            frames,
+            vec![(Duration::from_secs(0), Bytes::from_static(&[1, 2, 3]))]
+        );
+    }
+}
+
+#[test]
+fn ogg_packets() {
+    let mut file = ogg_page(0x02, &[19], &opus_head());
+    file.extend(ogg_page(0x00, &[12], b"OpusTags\0\0\0\0"));
+    file.extend(ogg_page(0x00, &[2, 3], &[0xf8, 1, 0xf8, 2, 3]));
+    // A packet continued on the next page.
+    file.extend(ogg_page(0x00, &[255], &[7; 255]));
+    file.extend(ogg_page(0x01, &[45], &[7; 45]));
+
+    assert_eq!(
+        read_ogg(file),
+        vec![
+            Bytes::from_static(&[0xf8, 1]),
+            Bytes::from_static(&[0xf8, 2, 3]),
+            Bytes::from(vec![7; 300]),
+        ]
+    );
+}
+
+#[test]
+fn invalid_ogg_files() {
+    let mut wrong_signature = ogg_page(0x02, &[19], &opus_head());
+    wrong_signature[0..4].copy_from_slice(b"RIFF");
+    let vorbis = ogg_page(0x02, &[7], b"\x01vorbis");
+
+    for file in [Vec::new(), wrong_signature, vorbis] {
+        let result = OggReader::new(Bytes::from(file));
+        assert!(
+            matches!(result, Err(Error::InvalidMediaFile(_))),
+            "{:?}",
+            result
+        );
+    }
+}
+
+#[test]
+fn truncated_ogg_page_is_an_error() {
+    let mut file = ogg_page(0x02, &[19], &opus_head());
+    file.extend(ogg_page(0x00, &[2], &[0xf8, 1]));
+    file.extend(ogg_page(0x00, &[3], &[0xf8, 2, 3]));
+    file.truncate(file.len() - 1);
+
+    let mut reader = OggReader::new(Bytes::from(file)).unwrap();
+    assert_eq!(
+        reader.next_opus_packet().unwrap(),
+        Some(Bytes::from_static(&[0xf8, 1]))
+    );
+    let result = reader.next_opus_packet();
+    assert!(
+        matches!(result, Err(Error::InvalidMediaFile(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn opus_packet_samples() {
+    use server::opus_packet_samples;
+
+    // 20 ms CELT, 10 ms SILK, two 10 ms SILK frames, five 10 ms SILK frames.
+    assert_eq!(opus_packet_samples(&[0xf8]), 960);
+    assert_eq!(opus_packet_samples(&[0x00]), 480);
+    assert_eq!(opus_packet_samples(&[0x01]), 960);
+    assert_eq!(opus_packet_samples(&[0x03, 0x05]), 2400);
+    assert_eq!(opus_packet_samples(&[]), 0);
+}
+
+#[tokio::test]
+async fn ivf_round_trip() {
+    let frames = [
+        vp8_frame(true, 3000),
+        vp8_frame(false, 500),
+        vp8_frame(false, RTP_MTU * 2),
+    ];
+
+    let file = write_ivf(&frames).await;
+
+    assert_eq!(u16::from_le_bytes([file[12], file[13]]), 640);
+    assert_eq!(u16::from_le_bytes([file[14], file[15]]), 480);
+    let positions = [0, 33_333_333, 66_666_666].map(Duration::from_nanos);
+    assert_eq!(
+        read_ivf(file),
+        positions.iter().copied().zip(frames).collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn ogg_round_trip() {
+    let packets = [opus_packet(3), opus_packet(600), opus_packet(255)];
+
+    let file = write_ogg(&packets).await;
+
+    assert_eq!(read_ogg(file), packets.to_vec());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_sender_streams_into_room() {
+    use protocol::RoomName;
+    use server::{FileSender, Server, ServerSettings};
+    use test_client::Receiver;
+    use tokio::spawn;
+    use tokio::task::JoinHandle;
+
+    let dir = std::env::temp_dir().join(format!("server-media-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let video_path = dir.join("video.ivf");
+    let audio_path = dir.join("audio.ogg");
+    // Looped, so a keyframe follows every third of a second.
+    let frames: Vec<Bytes> = (0..10).map(|index| vp8_frame(index == 0, 2000)).collect();
+    std::fs::write(&video_path, write_ivf(&frames).await).unwrap();
+    let packets: Vec<Bytes> = (0..20).map(|_| opus_packet(100)).collect();
+    std::fs::write(&audio_path, write_ogg(&packets).await).unwrap();
+
+    let server = Server::new("127.0.0.1:0", ServerSettings::default())
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    let file_sender = FileSender::new(
+        RoomName("room".to_owned()),
+        Some(&video_path),
+        Some(&audio_path),
+        true,
+    )
+    .unwrap();
+    file_sender.spawn(server.channels());
+    let _join_handle: JoinHandle<()> = spawn(server.run());
+
+    let receiver = Receiver::connect(&format!("ws://{}/ws", addr), "room")
+        .await
+        .unwrap();
+    receiver.wait_connected(TIMEOUT).await.unwrap();
+    receiver.wait_for_video(30, TIMEOUT).await.unwrap();
+    receiver.wait_for_audio(30, TIMEOUT).await.unwrap();
+
+    receiver.close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,98 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use server::{Error, NetworkSettings, Server, ServerSettings};
+
+async fn start_server(network_settings: &NetworkSettings) -> Result<Server, Error> {
+    let settings = ServerSettings {
+        network_settings: network_settings.clone(),
+        ..Default::default()
+    };
+    Server::new("127.0.0.1:0", settings).await
+}
+
+#[tokio::test]
+async fn default_settings() {
+    assert!(start_server(&NetworkSettings::default()).await.is_ok());
+}
+
+#[tokio::test]
+async fn inverted_udp_port_range_is_rejected() {
+    let network_settings = NetworkSettings {
+        udp_port_range: Some((20010, 20000)),
+        ..Default::default()
+    };
+    let result = start_server(&network_settings).await;
+    assert!(matches!(result, Err(Error::NetworkSettings(_))));
+}
+
+#[tokio::test]
+async fn udp_mux_port_with_udp_port_range_is_rejected() {
+    let network_settings = NetworkSettings {
+        udp_port_range: Some((20100, 20109)),
+        udp_mux_port: Some(20110),
+        ..Default::default()
+    };
+    let result = start_server(&network_settings).await;
+    assert!(matches!(result, Err(Error::NetworkSettings(_))));
+}
+
+#[tokio::test]
+async fn udp_mux_port_in_use_is_rejected() {
+    use std::net::UdpSocket;
+
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let network_settings = NetworkSettings {
+        udp_mux_port: Some(socket.local_addr().unwrap().port()),
+        ..Default::default()
+    };
+    let result = start_server(&network_settings).await;
+    assert!(matches!(result, Err(Error::NetworkSettings(_))));
+}
+
+#[tokio::test]
+async fn nat_1to1_ips() {
+    let network_settings = NetworkSettings {
+        nat_1to1_ips: vec![
+            "203.0.113.10".to_owned(),
+            "203.0.113.11/10.0.0.11".to_owned(),
+        ],
+        ..Default::default()
+    };
+    assert!(start_server(&network_settings).await.is_ok());
+}
+
+#[tokio::test]
+async fn invalid_nat_1to1_ip_is_rejected() {
+    for nat_1to1_ip in ["example.net", "203.0.113.10/10.0.0.10/10.0.0.11"] {
+        let network_settings = NetworkSettings {
+            nat_1to1_ips: vec![nat_1to1_ip.to_owned()],
+            ..Default::default()
+        };
+        let result = start_server(&network_settings).await;
+        assert!(
+            matches!(result, Err(Error::NetworkSettings(_))),
+            "{}",
+            nat_1to1_ip
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_ip_is_rejected() {
+    let network_settings = NetworkSettings {
+        ips: vec!["192.0.2.1".parse().unwrap()],
+        ..Default::default()
+    };
+    let result = start_server(&network_settings).await;
+    assert!(matches!(result, Err(Error::NetworkSettings(_))));
+}
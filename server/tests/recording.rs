@@ -0,0 +1,198 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+// The IVF and Ogg writers of the recorder, fed with hand made RTP packets.
+
+use bytes::Bytes;
+use rtp::packet::Packet;
+use server::{IvfWriter, OggWriter};
+
+// A 640x480 VP8 keyframe in a single packet.
+const KEYFRAME: &[u8] = &[
+    0x10, 0x00, 0x00, 0x00, 0x9d, 0x01, 0x2a, 0x80, 0x02, 0xe0, 0x01, 0xaa,
+];
+const DELTA_FRAME: &[u8] = &[0x10, 0x01, 0x00, 0x00, 0xbb];
+// A 20 ms fullband Opus packet.
+const OPUS_PACKET: &[u8] = &[0xf8, 0xff, 0xfe];
+
+fn packet(sequence_number: u16, timestamp: u32, marker: bool, payload: &[u8]) -> Packet {
+    use rtp::header::Header;
+
+    Packet {
+        header: Header {
+            sequence_number,
+            timestamp,
+            marker,
+            ..Header::default()
+        },
+        payload: Bytes::copy_from_slice(payload),
+    }
+}
+
+async fn write_ivf(packets: &[Packet]) -> Vec<u8> {
+    let mut writer = IvfWriter::new(Vec::new());
+    for packet in packets {
+        writer.write_rtp(packet).await.unwrap();
+    }
+    writer.finish().await.unwrap();
+    writer.into_inner()
+}
+
+// Returns the timestamps and the data of the frames after the IVF header.
+fn ivf_frames(file: &[u8]) -> Vec<(u64, Vec<u8>)> {
+    let mut frames = Vec::new();
+    let mut rest = &file[32..];
+    while !rest.is_empty() {
+        let size = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&rest[4..12]);
+        frames.push((u64::from_le_bytes(timestamp), rest[12..12 + size].to_vec()));
+        rest = &rest[12 + size..];
+    }
+    frames
+}
+
+// Returns the header type and the granule position of every Ogg page.
+fn ogg_pages(file: &[u8]) -> Vec<(u8, u64)> {
+    let mut pages = Vec::new();
+    let mut rest = file;
+    while !rest.is_empty() {
+        assert_eq!(&rest[0..4], b"OggS");
+        let mut granule = [0; 8];
+        granule.copy_from_slice(&rest[6..14]);
+        pages.push((rest[5], u64::from_le_bytes(granule)));
+        let segment_count = usize::from(rest[26]);
+        let payload_size: usize = rest[27..27 + segment_count]
+            .iter()
+            .map(|&lacing_value| usize::from(lacing_value))
+            .sum();
+        rest = &rest[27 + segment_count + payload_size..];
+    }
+    pages
+}
+
+#[tokio::test]
+async fn ivf_starts_with_keyframe() {
+    let file = write_ivf(&[
+        packet(1, 0, true, DELTA_FRAME),
+        packet(2, 3000, true, KEYFRAME),
+        packet(3, 6000, true, DELTA_FRAME),
+    ])
+    .await;
+
+    assert_eq!(&file[0..4], b"DKIF");
+    assert_eq!(&file[8..12], b"VP80");
+    assert_eq!(u16::from_le_bytes([file[12], file[13]]), 640);
+    assert_eq!(u16::from_le_bytes([file[14], file[15]]), 480);
+    assert_eq!(
+        ivf_frames(&file),
+        vec![
+            (0, KEYFRAME[1..].to_vec()),
+            (3000, DELTA_FRAME[1..].to_vec())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ivf_frame_of_several_packets() {
+    let file = write_ivf(&[
+        packet(1, 0, false, KEYFRAME),
+        packet(2, 0, false, &[0x00, 0x01, 0x02]),
+        // Padding.
+        packet(3, 0, false, &[]),
+        packet(4, 0, true, &[0x00, 0x03]),
+    ])
+    .await;
+
+    let mut frame = KEYFRAME[1..].to_vec();
+    frame.extend_from_slice(&[0x01, 0x02, 0x03]);
+    assert_eq!(ivf_frames(&file), vec![(0, frame)]);
+}
+
+#[tokio::test]
+async fn ivf_skips_frames_until_keyframe_after_loss() {
+    let file = write_ivf(&[
+        packet(1, 0, true, KEYFRAME),
+        // The last packet of this frame is lost.
+        packet(2, 3000, false, DELTA_FRAME),
+        packet(4, 6000, true, DELTA_FRAME),
+        packet(5, 9000, true, DELTA_FRAME),
+        packet(6, 12000, true, KEYFRAME),
+        packet(7, 15000, true, DELTA_FRAME),
+    ])
+    .await;
+
+    let timestamps: Vec<u64> = ivf_frames(&file)
+        .into_iter()
+        .map(|(timestamp, _)| timestamp)
+        .collect();
+    assert_eq!(timestamps, vec![0, 12000, 15000]);
+}
+
+#[tokio::test]
+async fn ivf_ignores_late_packets() {
+    let file = write_ivf(&[
+        packet(65534, 0, true, KEYFRAME),
+        packet(65535, 3000, true, DELTA_FRAME),
+        packet(65535, 3000, true, DELTA_FRAME),
+        packet(0, 6000, true, DELTA_FRAME),
+        packet(65535, 3000, true, DELTA_FRAME),
+    ])
+    .await;
+
+    assert_eq!(ivf_frames(&file).len(), 3);
+}
+
+#[tokio::test]
+async fn ivf_survives_truncated_descriptors() {
+    let file = write_ivf(&[
+        packet(1, 0, true, KEYFRAME),
+        packet(2, 3000, true, &[0x80]),
+        packet(3, 3000, true, &[0x90, 0x80]),
+        packet(4, 3000, true, &[0x90, 0x80, 0x80, 0x00]),
+        packet(5, 3000, true, &[0x90, 0xf0, 0x80, 0x00, 0x00]),
+        packet(6, 6000, true, KEYFRAME),
+    ])
+    .await;
+
+    let timestamps: Vec<u64> = ivf_frames(&file)
+        .into_iter()
+        .map(|(timestamp, _)| timestamp)
+        .collect();
+    assert_eq!(timestamps, vec![0, 6000]);
+}
+
+#[tokio::test]
+async fn ogg_granule_positions() {
+    let mut writer = OggWriter::new(Vec::new(), 1).await.unwrap();
+    for (sequence_number, timestamp) in [(1, 1000), (2, 1960), (3, 2920)] {
+        writer
+            .write_rtp(&packet(sequence_number, timestamp, false, OPUS_PACKET))
+            .await
+            .unwrap();
+    }
+    writer.finish().await.unwrap();
+    let file = writer.into_inner();
+
+    // The header pages, one page per packet ending at its last sample, and the end of stream.
+    assert_eq!(
+        ogg_pages(&file),
+        vec![
+            (0x02, 0),
+            (0x00, 0),
+            (0x00, 960),
+            (0x00, 1920),
+            (0x00, 2880),
+            (0x04, 2880)
+        ]
+    );
+}
@@ -0,0 +1,165 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use hyper::{Body, Response, StatusCode};
+use server::{Error, Server, StaticFiles};
+
+const INDEX: &str = "<!DOCTYPE html><title>client</title>";
+const WASM: &[u8] = b"\0asm\x01\0\0\0";
+
+fn write_dist(name: &str) -> PathBuf {
+    use std::fs::{create_dir_all, write};
+
+    let dir = std::env::temp_dir().join(format!("server-dist-{}-{}", name, std::process::id()));
+    create_dir_all(dir.join("assets")).unwrap();
+    write(dir.join("index.html"), INDEX).unwrap();
+    write(dir.join("client_bg.wasm"), WASM).unwrap();
+    write(dir.join("assets").join("index.html"), INDEX).unwrap();
+    dir
+}
+
+async fn start_server(dir: PathBuf) -> SocketAddr {
+    use server::ServerSettings;
+    use tokio::spawn;
+    use tokio::task::JoinHandle;
+
+    let settings = ServerSettings {
+        static_files: Some(StaticFiles::new(dir).unwrap()),
+        ..Default::default()
+    };
+    let server = Server::new("127.0.0.1:0", settings).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let _join_handle: JoinHandle<()> = spawn(server.run());
+    addr
+}
+
+async fn get(addr: SocketAddr, path: &str) -> Response<Body> {
+    use hyper::Client;
+
+    let uri = format!("http://{}{}", addr, path).parse().unwrap();
+    Client::new().get(uri).await.unwrap()
+}
+
+async fn body(response: Response<Body>) -> Vec<u8> {
+    hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap()
+        .to_vec()
+}
+
+fn content_type(response: &Response<Body>) -> &str {
+    use hyper::header::CONTENT_TYPE;
+
+    response.headers()[CONTENT_TYPE].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn index_and_wasm() {
+    let addr = start_server(write_dist("files")).await;
+
+    for path in ["/", "/index.html", "/assets/"] {
+        let response = get(addr, path).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        assert!(content_type(&response).starts_with("text/html"), "{}", path);
+        assert_eq!(body(response).await, INDEX.as_bytes(), "{}", path);
+    }
+
+    let response = get(addr, "/client_bg.wasm").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(content_type(&response), "application/wasm");
+    assert_eq!(body(response).await, WASM);
+}
+
+#[tokio::test]
+async fn generated_config() {
+    let addr = start_server(write_dist("config")).await;
+
+    let response = get(addr, "/params.js").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(content_type(&response).starts_with("text/javascript"));
+    let config = String::from_utf8(body(response).await).unwrap();
+    assert_eq!(
+        config,
+        format!("window.server_address = \"ws://{}/ws\";\n", addr)
+    );
+}
+
+#[tokio::test]
+async fn directory_is_redirected() {
+    use hyper::header::LOCATION;
+
+    let addr = start_server(write_dist("directory")).await;
+
+    // The leading slashes must not turn into a redirect to another host.
+    for path in ["/assets", "//assets", "///assets"] {
+        let response = get(addr, path).await;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY, "{}", path);
+        assert_eq!(response.headers()[LOCATION], "/assets/", "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn undecodable_paths_are_bad_requests() {
+    let addr = start_server(write_dist("undecodable")).await;
+
+    for path in ["/%00", "/assets/index%00.html", "/%ff%fe"] {
+        let response = get(addr, path).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn missing_and_escaping_paths() {
+    let addr = start_server(write_dist("missing")).await;
+
+    for path in [
+        "/missing.js",
+        "/%2e%2e/Cargo.toml",
+        "/assets/%2e%2e/%2e%2e/x",
+    ] {
+        let response = get(addr, path).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn websocket_on_ws_path() {
+    use test_client::Sender;
+
+    let addr = start_server(write_dist("websocket")).await;
+
+    let sender = Sender::connect(&format!("ws://{}/ws", addr), "room")
+        .await
+        .unwrap();
+    sender.close().await;
+}
+
+#[tokio::test]
+async fn websocket_on_other_path_is_rejected() {
+    use test_client::{Error, Sender};
+
+    let addr = start_server(write_dist("websocket-path")).await;
+
+    for path in ["", "/index.html", "/whip/room"] {
+        let result = Sender::connect(&format!("ws://{}{}", addr, path), "room").await;
+        assert!(matches!(result, Err(Error::WebSocket(_))), "{}", path);
+    }
+}
+
+#[test]
+fn missing_index_is_rejected() {
+    let result = StaticFiles::new(std::env::temp_dir().join("server-dist-does-not-exist"));
+    assert!(matches!(result, Err(Error::Io(_))));
+}
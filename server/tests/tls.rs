@@ -0,0 +1,100 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use server::{Error, Server, TlsConfig};
+
+// Generates a self-signed certificate for localhost,
+// returns it in DER for the client and the PEM files for the server.
+fn write_certificate(name: &str) -> (Vec<u8>, PathBuf, PathBuf) {
+    use rcgen::generate_simple_self_signed;
+    use std::fs::{create_dir_all, write};
+
+    let cert = generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("server-tls-{}-{}", name, std::process::id()));
+    create_dir_all(&dir).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+    write(&key_path, cert.serialize_private_key_pem()).unwrap();
+    (cert.serialize_der().unwrap(), cert_path, key_path)
+}
+
+async fn start_server(tls_config: TlsConfig) -> SocketAddr {
+    use server::ServerSettings;
+    use tokio::spawn;
+    use tokio::task::JoinHandle;
+
+    let settings = ServerSettings {
+        tls_config: Some(tls_config),
+        ..Default::default()
+    };
+    let server = Server::new("127.0.0.1:0", settings).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let _join_handle: JoinHandle<()> = spawn(server.run());
+    addr
+}
+
+#[tokio::test]
+async fn websocket_over_tls() {
+    use std::sync::Arc;
+    use tokio::net::TcpStream;
+    use tokio_rustls::rustls::{Certificate, ClientConfig};
+    use tokio_rustls::webpki::DNSNameRef;
+    use tokio_rustls::TlsConnector;
+    use tokio_tungstenite::client_async;
+
+    let (cert_der, cert_path, key_path) = write_certificate("websocket");
+    let tls_config = TlsConfig::from_pem_files(&cert_path, &key_path).unwrap();
+    let addr = start_server(tls_config).await;
+
+    let mut client_config = ClientConfig::new();
+    client_config
+        .root_store
+        .add(&Certificate(cert_der))
+        .unwrap();
+    let connector = TlsConnector::from(Arc::new(client_config));
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let domain = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+    let stream = connector.connect(domain, stream).await.unwrap();
+
+    let url = format!("wss://localhost:{}/ws", addr.port());
+    let (mut websocket, _) = client_async(url, stream).await.unwrap();
+    websocket.close(None).await.unwrap();
+}
+
+#[tokio::test]
+async fn plain_websocket_is_rejected() {
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::client_async;
+
+    let tls_config = TlsConfig::self_signed(vec!["localhost".to_owned()]).unwrap();
+    let addr = start_server(tls_config).await;
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let url = format!("ws://localhost:{}/ws", addr.port());
+    assert!(client_async(url, stream).await.is_err());
+}
+
+#[test]
+fn invalid_files_are_rejected() {
+    let (_, cert_path, key_path) = write_certificate("invalid");
+
+    // The certificate and the key are swapped.
+    let result = TlsConfig::from_pem_files(&key_path, &cert_path);
+    assert!(matches!(result, Err(Error::Tls(_))), "{:?}", result);
+
+    let result = TlsConfig::from_pem_files(&cert_path, &cert_path.with_file_name("missing.pem"));
+    assert!(matches!(result, Err(Error::Io(_))), "{:?}", result);
+}
@@ -0,0 +1,62 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use core::time::Duration;
+
+use server::TurnCredentials;
+
+fn turn_credentials(ttl: Duration) -> TurnCredentials {
+    TurnCredentials::new(
+        vec!["turn:turn.example.net:3478".to_owned()],
+        "north".to_owned(),
+        ttl,
+    )
+}
+
+#[test]
+fn credential_is_base64_hmac_sha1_of_username() {
+    let turn_credentials = turn_credentials(Duration::from_secs(60));
+    assert_eq!(
+        turn_credentials.credential("1700000000:alice"),
+        "Cd/49soE35ICqcJF/bCTn8Z4OyE="
+    );
+}
+
+#[test]
+fn minted_credentials_expire_after_ttl() {
+    use std::time::SystemTime;
+
+    let ttl = Duration::from_secs(3600);
+    let ice_server = turn_credentials(ttl).ice_server();
+    assert_eq!(ice_server.urls, ["turn:turn.example.net:3478"]);
+
+    let username = ice_server.username.unwrap();
+    let (expiry, user) = username.split_once(':').unwrap();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let expiry: u64 = expiry.parse().unwrap();
+    assert!(expiry >= (now + ttl).as_secs() - 5 && expiry <= (now + ttl).as_secs());
+    assert!(!user.is_empty());
+
+    let credential = turn_credentials(ttl).credential(&username);
+    assert_eq!(ice_server.credential, Some(credential));
+}
+
+#[test]
+fn every_session_gets_its_own_user() {
+    let turn_credentials = turn_credentials(Duration::from_secs(60));
+    assert_ne!(
+        turn_credentials.ice_server().username,
+        turn_credentials.ice_server().username
+    );
+}
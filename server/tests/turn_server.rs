@@ -0,0 +1,399 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use core::time::Duration;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use async_trait::async_trait;
+use server::{TurnCredentials, TurnServer};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::Mutex;
+use turn::client::Client;
+use webrtc_util::Conn;
+
+const SECRET: &str = "north";
+
+async fn start_turn_server() -> TurnServer {
+    start_turn_server_on(IpAddr::V4(Ipv4Addr::LOCALHOST), false).await
+}
+
+async fn start_turn_server_on(public_ip: IpAddr, allow_private_peers: bool) -> TurnServer {
+    TurnServer::new(0, public_ip, SECRET.to_owned(), allow_private_peers)
+        .await
+        .unwrap()
+}
+
+// A client with valid credentials on the loopback interface of the server.
+async fn new_client(turn_server: &TurnServer) -> Client {
+    let ice_server = TurnCredentials::new(
+        vec![turn_server.turn_url()],
+        SECRET.to_owned(),
+        Duration::from_secs(60),
+    )
+    .ice_server();
+    new_client_with(
+        turn_server,
+        ice_server.username.unwrap(),
+        ice_server.credential.unwrap(),
+    )
+    .await
+}
+
+async fn new_client_with(turn_server: &TurnServer, username: String, password: String) -> Client {
+    use std::sync::Arc;
+    use tokio::net::UdpSocket;
+
+    let local_ip = match turn_server.public_addr() {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+    };
+    let conn = Arc::new(UdpSocket::bind((local_ip, 0)).await.unwrap());
+    new_client_on(turn_server, username, password, conn).await
+}
+
+// A client with valid credentials connected to the TURN server over TCP.
+async fn new_tcp_client(turn_server: &TurnServer) -> (Client, SocketAddr) {
+    use std::sync::Arc;
+
+    let ice_server = TurnCredentials::new(
+        vec![turn_server.turn_tcp_url()],
+        SECRET.to_owned(),
+        Duration::from_secs(60),
+    )
+    .ice_server();
+    let conn = TcpClientConn::connect(turn_server.public_addr()).await;
+    let local_addr = conn.local_addr().unwrap();
+    let client = new_client_on(
+        turn_server,
+        ice_server.username.unwrap(),
+        ice_server.credential.unwrap(),
+        Arc::new(conn),
+    )
+    .await;
+    (client, local_addr)
+}
+
+async fn new_client_on(
+    turn_server: &TurnServer,
+    username: String,
+    password: String,
+    conn: std::sync::Arc<dyn Conn + Send + Sync>,
+) -> Client {
+    use turn::client::ClientConfig;
+
+    let client = Client::new(ClientConfig {
+        stun_serv_addr: turn_server.public_addr().to_string(),
+        turn_serv_addr: turn_server.public_addr().to_string(),
+        username,
+        password,
+        realm: String::new(),
+        software: String::new(),
+        rto_in_ms: 0,
+        conn,
+        vnet: None,
+    })
+    .await
+    .unwrap();
+    client.listen().await.unwrap();
+    client
+}
+
+// Requests a relay from the TURN server with the given credentials.
+async fn allocate(turn_server: &TurnServer, username: String, password: String) -> bool {
+    let client = new_client_with(turn_server, username, password).await;
+    let allocated = client.allocate().await.is_ok();
+    client.close().await.unwrap();
+    allocated
+}
+
+// Sends a packet through a relay to a peer on the loopback interface, returns if it arrived.
+async fn relay_to_loopback(turn_server: &TurnServer) -> bool {
+    let client = new_client(turn_server).await;
+    relay_to_loopback_from(client).await
+}
+
+async fn relay_to_loopback_from(client: Client) -> bool {
+    use tokio::net::UdpSocket;
+    use tokio::time::timeout;
+
+    let relay = client.allocate().await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let peer_addr = peer.local_addr().unwrap();
+
+    // The permission is created by the first packet, which may be sent before it is granted.
+    let mut buf = [0; 16];
+    let mut received = false;
+    for _ in 0..5 {
+        let _: Result<usize, _> = relay.send_to(b"ping", peer_addr).await;
+        if let Ok(Ok((len, _))) =
+            timeout(Duration::from_millis(200), peer.recv_from(&mut buf)).await
+        {
+            received = &buf[..len] == b"ping";
+            break;
+        }
+    }
+    relay.close().await.unwrap();
+    client.close().await.unwrap();
+    received
+}
+
+#[tokio::test]
+async fn stun_binding() {
+    use std::sync::Arc;
+    use tokio::net::UdpSocket;
+    use turn::client::{Client, ClientConfig};
+
+    let turn_server = start_turn_server().await;
+    let conn = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let local_addr = conn.local_addr().unwrap();
+    let client = Client::new(ClientConfig {
+        stun_serv_addr: turn_server.public_addr().to_string(),
+        turn_serv_addr: String::new(),
+        username: String::new(),
+        password: String::new(),
+        realm: String::new(),
+        software: String::new(),
+        rto_in_ms: 0,
+        conn,
+        vnet: None,
+    })
+    .await
+    .unwrap();
+    client.listen().await.unwrap();
+
+    let mapped_addr = client.send_binding_request().await.unwrap();
+    assert_eq!(mapped_addr, local_addr);
+
+    client.close().await.unwrap();
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn allocation_with_minted_credentials() {
+    let turn_server = start_turn_server().await;
+    let turn_credentials = TurnCredentials::new(
+        vec![turn_server.turn_url()],
+        SECRET.to_owned(),
+        Duration::from_secs(60),
+    );
+    let ice_server = turn_credentials.ice_server();
+
+    let allocated = allocate(
+        &turn_server,
+        ice_server.username.unwrap(),
+        ice_server.credential.unwrap(),
+    )
+    .await;
+    assert!(allocated);
+
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn allocation_with_invalid_credentials() {
+    let turn_server = start_turn_server().await;
+    let turn_credentials = TurnCredentials::new(
+        vec![turn_server.turn_url()],
+        "wrong secret".to_owned(),
+        Duration::from_secs(60),
+    );
+    let ice_server = turn_credentials.ice_server();
+
+    let allocated = allocate(
+        &turn_server,
+        ice_server.username.unwrap(),
+        ice_server.credential.unwrap(),
+    )
+    .await;
+    assert!(!allocated);
+
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn allocation_with_expired_credentials() {
+    let turn_server = start_turn_server().await;
+    let username = "1000000000:user".to_owned();
+    let password = server::turn_credential(SECRET, &username);
+
+    let allocated = allocate(&turn_server, username, password).await;
+    assert!(!allocated);
+
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn private_peers_are_denied() {
+    let turn_server = start_turn_server().await;
+
+    assert!(!relay_to_loopback(&turn_server).await);
+
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn private_peers_can_be_allowed() {
+    let turn_server = start_turn_server_on(IpAddr::V4(Ipv4Addr::LOCALHOST), true).await;
+
+    assert!(relay_to_loopback(&turn_server).await);
+
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn ipv6_relay() {
+    let turn_server = start_turn_server_on(IpAddr::V6(Ipv6Addr::LOCALHOST), false).await;
+    let client = new_client(&turn_server).await;
+
+    let relay = client.allocate().await.unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    assert_eq!(relay_addr.ip(), IpAddr::V6(Ipv6Addr::LOCALHOST));
+
+    relay.close().await.unwrap();
+    client.close().await.unwrap();
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn stun_binding_over_tcp() {
+    let turn_server = start_turn_server().await;
+    let (client, local_addr) = new_tcp_client(&turn_server).await;
+
+    let mapped_addr = client.send_binding_request().await.unwrap();
+    assert_eq!(mapped_addr, local_addr);
+
+    client.close().await.unwrap();
+    turn_server.close().await;
+}
+
+// The client reaches the server over TCP, the relay and the peer use UDP.
+#[tokio::test]
+async fn relay_over_tcp() {
+    use tokio::net::UdpSocket;
+    use tokio::time::timeout;
+
+    let turn_server = start_turn_server_on(IpAddr::V4(Ipv4Addr::LOCALHOST), true).await;
+    let (client, _) = new_tcp_client(&turn_server).await;
+    let relay = client.allocate().await.unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let peer_addr = peer.local_addr().unwrap();
+
+    let mut buf = [0; 16];
+    let mut received = None;
+    for _ in 0..5 {
+        let _: Result<usize, _> = relay.send_to(b"ping", peer_addr).await;
+        if let Ok(Ok(received_from)) =
+            timeout(Duration::from_millis(200), peer.recv_from(&mut buf)).await
+        {
+            received = Some(received_from);
+            break;
+        }
+    }
+    assert_eq!(received, Some((4, relay_addr)));
+    assert_eq!(&buf[..4], b"ping");
+
+    let _: usize = peer.send_to(b"pong", relay_addr).await.unwrap();
+    let (len, from) = timeout(Duration::from_secs(1), relay.recv_from(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((&buf[..len], from), (&b"pong"[..], peer_addr));
+
+    relay.close().await.unwrap();
+    client.close().await.unwrap();
+    turn_server.close().await;
+}
+
+#[tokio::test]
+async fn private_peers_are_denied_over_tcp() {
+    let turn_server = start_turn_server().await;
+    let (client, _) = new_tcp_client(&turn_server).await;
+
+    assert!(!relay_to_loopback_from(client).await);
+
+    turn_server.close().await;
+}
+
+// The client side of TURN over TCP, the messages are framed by their length fields.
+struct TcpClientConn {
+    reader: Mutex<OwnedReadHalf>,
+    writer: Mutex<OwnedWriteHalf>,
+    local_addr: SocketAddr,
+    server_addr: SocketAddr,
+}
+
+impl TcpClientConn {
+    async fn connect(server_addr: SocketAddr) -> Self {
+        use tokio::net::TcpStream;
+
+        let stream = TcpStream::connect(server_addr).await.unwrap();
+        let local_addr = stream.local_addr().unwrap();
+        let (reader, writer) = stream.into_split();
+        Self {
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+            local_addr,
+            server_addr,
+        }
+    }
+}
+
+#[async_trait]
+impl Conn for TcpClientConn {
+    async fn connect(&self, _: SocketAddr) -> Result<(), webrtc_util::Error> {
+        Ok(())
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> Result<usize, webrtc_util::Error> {
+        Ok(self.recv_from(buf).await?.0)
+    }
+
+    // A ChannelData message starts with the bits 01 and is padded to 4 bytes over TCP.
+    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), webrtc_util::Error> {
+        use tokio::io::AsyncReadExt;
+
+        let mut reader = self.reader.lock().await;
+        let _: usize = reader.read_exact(&mut buf[..4]).await?;
+        let length = usize::from(u16::from_be_bytes([buf[2], buf[3]]));
+        let size = if buf[0] >> 6 == 0b01 {
+            4 + ((length + 3) & !3)
+        } else {
+            20 + length
+        };
+        let _: usize = reader.read_exact(&mut buf[4..size]).await?;
+        Ok((size, self.server_addr))
+    }
+
+    async fn send(&self, buf: &[u8]) -> Result<usize, webrtc_util::Error> {
+        self.send_to(buf, self.server_addr).await
+    }
+
+    async fn send_to(&self, buf: &[u8], _: SocketAddr) -> Result<usize, webrtc_util::Error> {
+        use tokio::io::AsyncWriteExt;
+
+        self.writer.lock().await.write_all(buf).await?;
+        Ok(buf.len())
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, webrtc_util::Error> {
+        Ok(self.local_addr)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        Some(self.server_addr)
+    }
+
+    async fn close(&self) -> Result<(), webrtc_util::Error> {
+        Ok(())
+    }
+}
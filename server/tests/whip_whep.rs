@@ -0,0 +1,244 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+// WHIP and WHEP sessions over loopback, the offers are made by a webrtc-rs peer connection.
+
+use core::time::Duration;
+use std::net::SocketAddr;
+
+use hyper::{Body, Response, StatusCode};
+use server::{Server, ServerSettings};
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+const SDP_MIME_TYPE: &str = "application/sdp";
+
+async fn start_server() -> SocketAddr {
+    use tokio::spawn;
+    use tokio::task::JoinHandle;
+
+    let server = Server::new("127.0.0.1:0", ServerSettings::default())
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    let _join_handle: JoinHandle<()> = spawn(server.run());
+    addr
+}
+
+// A peer connection with a video and an audio transceiver in the given direction.
+async fn new_peer_connection(direction: RTCRtpTransceiverDirection) -> RTCPeerConnection {
+    use std::sync::Arc;
+    use webrtc::api::media_engine::MediaEngine;
+    use webrtc::api::APIBuilder;
+    use webrtc::peer_connection::configuration::RTCConfiguration;
+    use webrtc::rtp_transceiver::RTCRtpTransceiver;
+    use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs().unwrap();
+    let api = APIBuilder::new().with_media_engine(media_engine).build();
+    let peer_connection = api
+        .new_peer_connection(RTCConfiguration::default())
+        .await
+        .unwrap();
+    for kind in [RTPCodecType::Video, RTPCodecType::Audio] {
+        let init = RTCRtpTransceiverInit {
+            direction,
+            send_encodings: Vec::new(),
+        };
+        let _: Arc<RTCRtpTransceiver> = peer_connection
+            .add_transceiver_from_kind(kind, &[init])
+            .await
+            .unwrap();
+    }
+    peer_connection
+}
+
+// WHIP and WHEP do not trickle candidates, so the offer is made once the gathering is complete.
+async fn create_offer(peer_connection: &RTCPeerConnection) -> String {
+    let offer = peer_connection.create_offer(None).await.unwrap();
+    let mut gathering_complete = peer_connection.gathering_complete_promise().await;
+    peer_connection.set_local_description(offer).await.unwrap();
+    let _: Option<()> = gathering_complete.recv().await;
+    peer_connection.local_description().await.unwrap().sdp
+}
+
+async fn post(addr: SocketAddr, path: &str, content_type: &str, body: Body) -> Response<Body> {
+    use hyper::header::CONTENT_TYPE;
+    use hyper::{Client, Method, Request};
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{}{}", addr, path))
+        .header(CONTENT_TYPE, content_type)
+        .body(body)
+        .unwrap();
+    Client::new().request(request).await.unwrap()
+}
+
+async fn delete(addr: SocketAddr, path: &str) -> StatusCode {
+    use hyper::{Client, Method, Request};
+
+    let request = Request::builder()
+        .method(Method::DELETE)
+        .uri(format!("http://{}{}", addr, path))
+        .body(Body::empty())
+        .unwrap();
+    Client::new().request(request).await.unwrap().status()
+}
+
+// Checks the response to an offer and returns the resource location and the answer.
+async fn answer(response: Response<Body>, path: &str) -> (String, String) {
+    use hyper::header::{CONTENT_TYPE, LOCATION};
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[CONTENT_TYPE], SDP_MIME_TYPE);
+    let location = response.headers()[LOCATION].to_str().unwrap().to_owned();
+    assert!(
+        location.starts_with(&format!("{}/", path)) && location.len() > path.len() + 1,
+        "{}",
+        location
+    );
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let answer = String::from_utf8(body.to_vec()).unwrap();
+    assert!(answer.starts_with("v=0"), "{}", answer);
+    (location, answer)
+}
+
+async fn set_answer(peer_connection: &RTCPeerConnection, answer: String) {
+    use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+    let description = RTCSessionDescription::answer(answer).unwrap();
+    peer_connection
+        .set_remote_description(description)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn whip_session() {
+    let addr = start_server().await;
+    let peer_connection = new_peer_connection(RTCRtpTransceiverDirection::Sendonly).await;
+    let offer = create_offer(&peer_connection).await;
+
+    let response = post(addr, "/whip/room", SDP_MIME_TYPE, Body::from(offer.clone())).await;
+    let (location, answer) = answer(response, "/whip/room").await;
+    set_answer(&peer_connection, answer).await;
+
+    // The room has a sender now.
+    let response = post(addr, "/whip/room", SDP_MIME_TYPE, Body::from(offer.clone())).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    assert_eq!(delete(addr, &location).await, StatusCode::OK);
+    assert_eq!(delete(addr, &location).await, StatusCode::NOT_FOUND);
+    let response = post(addr, "/whip/room", SDP_MIME_TYPE, Body::from(offer)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    peer_connection.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn whep_session() {
+    use std::sync::Arc;
+    use test_client::Sender;
+    use tokio::sync::Notify;
+    use tokio::time::{interval, timeout, Instant};
+
+    let addr = start_server().await;
+    let sender = Sender::connect(&format!("ws://{}/ws", addr), "room")
+        .await
+        .unwrap();
+    sender.wait_connected(TIMEOUT).await.unwrap();
+
+    let peer_connection = new_peer_connection(RTCRtpTransceiverDirection::Recvonly).await;
+    let video_received = Arc::new(Notify::new());
+    let on_video = Arc::clone(&video_received);
+    peer_connection.on_track(Box::new(move |track, _| {
+        if matches!(&track, Some(track) if track.kind() == RTPCodecType::Video) {
+            on_video.notify_one();
+        }
+        Box::pin(async {})
+    }));
+    let offer = create_offer(&peer_connection).await;
+
+    let response = post(addr, "/whep/room", SDP_MIME_TYPE, Body::from(offer)).await;
+    let (location, answer) = answer(response, "/whep/room").await;
+    set_answer(&peer_connection, answer).await;
+
+    // The track is only reported once its first packet arrives.
+    let streaming = async {
+        let mut ticker = interval(Duration::from_millis(20));
+        loop {
+            let _: Instant = ticker.tick().await;
+            sender.send_video().await.unwrap();
+        }
+    };
+    let result = timeout(TIMEOUT, async {
+        tokio::select! {
+            _ = video_received.notified() => {}
+            _ = streaming => {}
+        }
+    })
+    .await;
+    assert!(result.is_ok(), "no video received over WHEP");
+
+    assert_eq!(delete(addr, &location).await, StatusCode::OK);
+    assert_eq!(delete(addr, &location).await, StatusCode::NOT_FOUND);
+
+    peer_connection.close().await.unwrap();
+    sender.close().await;
+}
+
+#[tokio::test]
+async fn offer_must_be_sdp() {
+    let addr = start_server().await;
+
+    let response = post(addr, "/whep/room", "text/plain", Body::from("v=0\r\n")).await;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn oversized_offer_is_rejected() {
+    let addr = start_server().await;
+    let offer = "a".repeat(128 * 1024);
+
+    let response = post(addr, "/whip/room", SDP_MIME_TYPE, Body::from(offer.clone())).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Without a `Content-Length` the limit applies while reading.
+    let (mut body_sender, body) = Body::channel();
+    let sending = async move {
+        for chunk in offer.into_bytes().chunks(16 * 1024) {
+            if body_sender.send_data(chunk.to_vec().into()).await.is_err() {
+                break;
+            }
+        }
+    };
+    let (response, ()) = tokio::join!(post(addr, "/whep/room", SDP_MIME_TYPE, body), sending);
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn unknown_resource_is_not_found() {
+    let addr = start_server().await;
+
+    assert_eq!(
+        delete(addr, "/whip/room/0123456789abcdef").await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        delete(addr, "/whep/room/0123456789abcdef").await,
+        StatusCode::NOT_FOUND
+    );
+}
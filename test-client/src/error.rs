@@ -0,0 +1,31 @@
+use protocol::{CodecError, ErrorCode};
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("websocket error: {0}")]
+    WebSocket(#[source] Box<tungstenite::Error>),
+    #[error("message codec error: {0}")]
+    Codec(#[from] CodecError),
+    #[error("webrtc error: {0}")]
+    WebRtc(#[source] webrtc::Error),
+    #[error("handshake expected")]
+    HandshakeExpected,
+    #[error("unexpected message: {0}")]
+    UnexpectedMessage(String),
+    #[error("server error {code:?}: {reason}")]
+    Server { code: ErrorCode, reason: String },
+    #[error("connection closed")]
+    Closed,
+    #[error("timed out waiting for {0}")]
+    Timeout(&'static str),
+    #[error("no video track received yet")]
+    NoVideoTrack,
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(err: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(err))
+    }
+}
@@ -0,0 +1,66 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+mod error;
+mod peer;
+mod receiver;
+mod sender;
+mod websocket;
+
+pub use error::Error;
+pub use receiver::Receiver;
+pub use sender::Sender;
+
+use peer::{add_remote_icecandidate, new_peer_connection, to_icecandidate, watch_state};
+use websocket::{connect, WebSocketReceiver, WebSocketSender};
+
+use core::time::Duration;
+
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+
+// Waits until the watched value satisfies the predicate.
+async fn wait_for<T>(
+    mut receiver: watch::Receiver<T>,
+    predicate: impl Fn(&T) -> bool,
+    timeout: Duration,
+    what: &'static str,
+) -> Result<(), Error> {
+    let wait = async {
+        while !predicate(&*receiver.borrow()) {
+            if receiver.changed().await.is_err() {
+                return Err(Error::Closed);
+            }
+        }
+        Ok(())
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .map_err(|_| Error::Timeout(what))?
+}
+
+// Waits for the signaling task to finish and returns its result.
+async fn wait_signaling(
+    signaling: &Mutex<Option<JoinHandle<Result<(), Error>>>>,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let mut signaling = signaling.lock().await;
+    let join_handle = match signaling.as_mut() {
+        Some(join_handle) => join_handle,
+        None => return Ok(()),
+    };
+    let result = tokio::time::timeout(timeout, join_handle)
+        .await
+        .map_err(|_| Error::Timeout("close"))?;
+    *signaling = None;
+    result.unwrap_or(Err(Error::Closed))
+}
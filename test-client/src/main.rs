@@ -0,0 +1,103 @@
+#![warn(
+    clippy::all,
+    rust_2018_idioms,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    single_use_lifetimes,
+    trivial_casts,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use core::time::Duration;
+
+use clap::{AppSettings, Clap};
+use test_client::{Error, Receiver, Sender};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const PACKET_INTERVAL: Duration = Duration::from_millis(20);
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clap)]
+#[clap(
+    version = env!("CARGO_PKG_VERSION"),
+    author = env!("CARGO_PKG_AUTHORS"),
+    about = "Headless client for testing the server",
+)]
+#[clap(setting = AppSettings::ColoredHelp)]
+struct Options {
+    /// Server WebSocket address
+    #[clap(short, long, default_value = "ws://localhost:9010/ws")]
+    address: String,
+    /// Room name
+    #[clap(short, long, default_value = "default")]
+    room: String,
+    /// Send synthetic video, audio and data channel text or receive and count them
+    #[clap(possible_values = &["sender", "receiver"])]
+    mode: String,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let opts: Options = Options::parse();
+    let result = match opts.mode.as_str() {
+        "sender" => run_sender(&opts.address, &opts.room).await,
+        _ => run_receiver(&opts.address, &opts.room).await,
+    };
+    if let Err(err) = result {
+        log::error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run_sender(addr: &str, room: &str) -> Result<(), Error> {
+    use tokio::time::{interval, Instant};
+
+    let sender = Sender::connect(addr, room).await?;
+    sender.wait_connected(CONNECT_TIMEOUT).await?;
+    log::info!("sender connected");
+
+    let mut ticker = interval(PACKET_INTERVAL);
+    let mut next_stats = Instant::now();
+    let mut packets: u64 = 0;
+    let mut texts: u64 = 0;
+    loop {
+        let _ = ticker.tick().await;
+        sender.send_video().await?;
+        sender.send_audio().await?;
+        packets += 1;
+        if Instant::now() >= next_stats {
+            next_stats += STATS_INTERVAL;
+            sender.send_text(&format!("message {}", texts)).await?;
+            texts += 1;
+            log::info!("sent {} packets of each kind", packets);
+        }
+    }
+}
+
+async fn run_receiver(addr: &str, room: &str) -> Result<(), Error> {
+    use tokio::time::Instant;
+
+    let receiver = Receiver::connect(addr, room).await?;
+    receiver.wait_connected(CONNECT_TIMEOUT).await?;
+    log::info!("receiver connected");
+
+    let mut next_stats = Instant::now() + STATS_INTERVAL;
+    loop {
+        match receiver.wait_for_text(STATS_INTERVAL).await {
+            Ok(text) => log::info!("received text: {}", text),
+            Err(Error::Timeout(_)) => {}
+            Err(err) => return Err(err),
+        }
+        if Instant::now() >= next_stats {
+            next_stats += STATS_INTERVAL;
+            log::info!(
+                "received {} video and {} audio packets",
+                receiver.video_packets(),
+                receiver.audio_packets()
+            );
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use protocol::{IceCandidate, IceServer};
+use tokio::sync::{watch, Mutex};
+use webrtc::ice_transport::ice_candidate::RTCIceCandidate;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+
+use crate::Error;
+
+// Uses the ICE servers pushed by the server, with none only host candidates are gathered.
+// SRTP replay protection is off, so the retransmissions of packets received already are counted.
+pub async fn new_peer_connection(ice_servers: &[IceServer]) -> Result<RTCPeerConnection, Error> {
+    use interceptor::registry::Registry;
+    use webrtc::api::interceptor_registry::register_default_interceptors;
+    use webrtc::api::media_engine::MediaEngine;
+    use webrtc::api::setting_engine::SettingEngine;
+    use webrtc::api::APIBuilder;
+    use webrtc::ice_transport::ice_server::RTCIceServer;
+    use webrtc::peer_connection::configuration::RTCConfiguration;
+
+    let mut media_engine = MediaEngine::default();
+    media_engine
+        .register_default_codecs()
+        .map_err(Error::WebRtc)?;
+    let registry =
+        register_default_interceptors(Registry::new(), &mut media_engine).map_err(Error::WebRtc)?;
+
+    let mut setting_engine = SettingEngine::default();
+    setting_engine.disable_srtp_replay_protection(true);
+
+    let api = APIBuilder::new()
+        .with_media_engine(media_engine)
+        .with_interceptor_registry(registry)
+        .with_setting_engine(setting_engine)
+        .build();
+    let ice_servers = ice_servers
+        .iter()
+        .map(|ice_server| RTCIceServer {
+            urls: ice_server.urls.clone(),
+            username: ice_server.username.clone().unwrap_or_default(),
+            credential: ice_server.credential.clone().unwrap_or_default(),
+            ..Default::default()
+        })
+        .collect();
+    let config = RTCConfiguration {
+        ice_servers,
+        ..Default::default()
+    };
+    api.new_peer_connection(config).await.map_err(Error::WebRtc)
+}
+
+pub fn to_icecandidate(candidate: &RTCIceCandidate) -> Result<IceCandidate, Error> {
+    let json = candidate.to_json().map_err(Error::WebRtc)?;
+    Ok(IceCandidate {
+        candidate: json.candidate,
+        sdp_mid: json.sdp_mid,
+        sdp_mline_index: json.sdp_mline_index,
+        username_fragment: json.username_fragment,
+    })
+}
+
+// Candidates received before the remote description are delayed until it is set.
+pub async fn add_remote_icecandidate(
+    peer_connection: &RTCPeerConnection,
+    candidate: IceCandidate,
+    delayed: &Mutex<Vec<IceCandidate>>,
+) -> Result<(), Error> {
+    use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+
+    if peer_connection.remote_description().await.is_some() {
+        let candidate = RTCIceCandidateInit {
+            candidate: candidate.candidate,
+            sdp_mid: candidate.sdp_mid,
+            sdp_mline_index: candidate.sdp_mline_index,
+            username_fragment: candidate.username_fragment,
+        };
+        peer_connection
+            .add_ice_candidate(candidate)
+            .await
+            .map_err(Error::WebRtc)
+    } else {
+        delayed.lock().await.push(candidate);
+        Ok(())
+    }
+}
+
+pub fn watch_state(peer_connection: &RTCPeerConnection) -> watch::Receiver<RTCPeerConnectionState> {
+    let (sender, receiver) = watch::channel(RTCPeerConnectionState::New);
+    peer_connection.on_peer_connection_state_change(Box::new(move |state| {
+        let _: Result<(), _> = sender.send(state);
+        Box::pin(async {})
+    }));
+    receiver
+}
@@ -0,0 +1,389 @@
+use core::fmt;
+use core::time::Duration;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use protocol::{ClientReceiverMessage, ServerSenderMessage};
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+
+use crate::{Error, WebSocketReceiver, WebSocketSender};
+
+// Subscribes to a room and counts the received media and text, like a browser receiver.
+pub struct Receiver {
+    peer_connection: Arc<RTCPeerConnection>,
+    websocket_sender: Arc<Mutex<WebSocketSender<ClientReceiverMessage>>>,
+    state: watch::Receiver<RTCPeerConnectionState>,
+    video_packets: watch::Receiver<usize>,
+    audio_packets: watch::Receiver<usize>,
+    received_video: Arc<std::sync::Mutex<ReceivedVideo>>,
+    texts: Mutex<mpsc::UnboundedReceiver<String>>,
+    signaling: Mutex<Option<JoinHandle<Result<(), Error>>>>,
+}
+
+impl Receiver {
+    // The address is e.g. `ws://localhost:9010/ws`.
+    pub async fn connect(addr: &str, room: &str) -> Result<Self, Error> {
+        use protocol::CAPABILITY_ROOMS;
+
+        Self::connect_with_capabilities(addr, room, &[CAPABILITY_ROOMS]).await
+    }
+
+    // Without `CAPABILITY_ROOMS` the room is ignored, like by a client of an older version.
+    pub async fn connect_with_capabilities(
+        addr: &str,
+        room: &str,
+        capabilities: &[&str],
+    ) -> Result<Self, Error> {
+        use protocol::{ClientMessage, RoomName};
+
+        let (websocket_sender, websocket_receiver, ice_servers) = crate::connect(
+            addr,
+            capabilities,
+            ClientMessage::StartSender(RoomName(room.to_owned())),
+        )
+        .await?;
+        let websocket_sender = Arc::new(Mutex::new(websocket_sender));
+
+        let peer_connection = Arc::new(crate::new_peer_connection(&ice_servers).await?);
+        let state = crate::watch_state(&peer_connection);
+        let (video_packets, audio_packets, received_video) = count_rtp_packets(&peer_connection);
+        let texts = collect_texts(&peer_connection);
+        send_local_icecandidates(&peer_connection, &websocket_sender);
+
+        let signaling = tokio::spawn(signaling(
+            Arc::clone(&peer_connection),
+            Arc::clone(&websocket_sender),
+            websocket_receiver,
+        ));
+
+        Ok(Self {
+            peer_connection,
+            websocket_sender,
+            state,
+            video_packets,
+            audio_packets,
+            received_video,
+            texts: Mutex::new(texts),
+            signaling: Mutex::new(Some(signaling)),
+        })
+    }
+
+    // Waits until the peer connection is established.
+    pub async fn wait_connected(&self, timeout: Duration) -> Result<(), Error> {
+        crate::wait_for(
+            self.state.clone(),
+            |state| *state == RTCPeerConnectionState::Connected,
+            timeout,
+            "connection",
+        )
+        .await
+    }
+
+    // Binary messages are decoded as UTF-8.
+    pub async fn wait_for_text(&self, timeout: Duration) -> Result<String, Error> {
+        let mut texts = self.texts.lock().await;
+        tokio::time::timeout(timeout, texts.recv())
+            .await
+            .map_err(|_| Error::Timeout("text"))?
+            .ok_or(Error::Closed)
+    }
+
+    // Waits until `count` video packets have been received in total.
+    pub async fn wait_for_video(&self, count: usize, timeout: Duration) -> Result<(), Error> {
+        crate::wait_for(
+            self.video_packets.clone(),
+            |&packets| packets >= count,
+            timeout,
+            "video packets",
+        )
+        .await
+    }
+
+    // Waits until `count` audio packets have been received in total.
+    pub async fn wait_for_audio(&self, count: usize, timeout: Duration) -> Result<(), Error> {
+        crate::wait_for(
+            self.audio_packets.clone(),
+            |&packets| packets >= count,
+            timeout,
+            "audio packets",
+        )
+        .await
+    }
+
+    pub fn video_packets(&self) -> usize {
+        *self.video_packets.borrow()
+    }
+
+    pub fn audio_packets(&self) -> usize {
+        *self.audio_packets.borrow()
+    }
+
+    // Sends a PLI, like a browser after packet loss.
+    pub async fn request_keyframe(&self) -> Result<(), Error> {
+        use rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+
+        let pli = PictureLossIndication {
+            sender_ssrc: 0,
+            media_ssrc: self.received_video_ssrc()?,
+        };
+        let _: usize = self
+            .peer_connection
+            .write_rtcp(&[Box::new(pli)])
+            .await
+            .map_err(Error::WebRtc)?;
+        Ok(())
+    }
+
+    // Sends a NACK for the video packet, as if it was lost.
+    pub async fn send_nack(&self, sequence_number: u16) -> Result<(), Error> {
+        use rtcp::transport_feedbacks::transport_layer_nack::{NackPair, TransportLayerNack};
+
+        let nack = TransportLayerNack {
+            sender_ssrc: 0,
+            media_ssrc: self.received_video_ssrc()?,
+            nacks: vec![NackPair {
+                packet_id: sequence_number,
+                lost_packets: 0,
+            }],
+        };
+        let _: usize = self
+            .peer_connection
+            .write_rtcp(&[Box::new(nack)])
+            .await
+            .map_err(Error::WebRtc)?;
+        Ok(())
+    }
+
+    // Sends a REMB, like a browser estimating its bandwidth.
+    pub async fn send_remb(&self, bitrate: u64) -> Result<(), Error> {
+        use rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+
+        let remb = ReceiverEstimatedMaximumBitrate {
+            sender_ssrc: 0,
+            bitrate: bitrate as f32,
+            ssrcs: vec![self.received_video_ssrc()?],
+        };
+        let _: usize = self
+            .peer_connection
+            .write_rtcp(&[Box::new(remb)])
+            .await
+            .map_err(Error::WebRtc)?;
+        Ok(())
+    }
+
+    // The sequence number of the latest video packet, e.g. to NACK it.
+    pub fn last_video_sequence_number(&self) -> Option<u16> {
+        self.received_video
+            .lock()
+            .unwrap()
+            .last
+            .map(|(_, sequence_number)| sequence_number)
+    }
+
+    // How often the video packet arrived, retransmissions included.
+    pub fn video_packet_copies(&self, sequence_number: u16) -> usize {
+        let received_video = self.received_video.lock().unwrap();
+        received_video
+            .copies
+            .get(&sequence_number)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // The SSRC of the video packets received, the server chooses it for each receiver.
+    pub fn video_ssrc(&self) -> Option<u32> {
+        self.received_video
+            .lock()
+            .unwrap()
+            .last
+            .map(|(ssrc, _)| ssrc)
+    }
+
+    fn received_video_ssrc(&self) -> Result<u32, Error> {
+        self.video_ssrc().ok_or(Error::NoVideoTrack)
+    }
+
+    // Fails on a server error.
+    pub async fn wait_closed(&self, timeout: Duration) -> Result<(), Error> {
+        crate::wait_signaling(&self.signaling, timeout).await
+    }
+
+    // Sends `Bye` and closes the session.
+    pub async fn close(&self) {
+        // The candidate handler takes the lock too, and the ICE agent waits for the handler
+        // when closing, so the lock is released before the peer connection is closed.
+        {
+            let mut websocket_sender = self.websocket_sender.lock().await;
+            let _: Result<(), Error> = websocket_sender.send(ClientReceiverMessage::Bye).await;
+            websocket_sender.close().await;
+        }
+        let _: Result<(), _> = self.peer_connection.close().await;
+    }
+}
+
+impl fmt::Debug for Receiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("state", &*self.state.borrow())
+            .field("video_packets", &self.video_packets())
+            .field("audio_packets", &self.audio_packets())
+            .finish_non_exhaustive()
+    }
+}
+
+async fn signaling(
+    peer_connection: Arc<RTCPeerConnection>,
+    websocket_sender: Arc<Mutex<WebSocketSender<ClientReceiverMessage>>>,
+    mut websocket_receiver: WebSocketReceiver<ServerSenderMessage>,
+) -> Result<(), Error> {
+    use core::mem::take;
+    use protocol::SessionDescription;
+    use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+    let delayed_icecandidates = Mutex::new(Vec::new());
+    while let Some(message) = websocket_receiver.recv().await? {
+        match message {
+            ServerSenderMessage::Offer(sdp) => {
+                let offer = RTCSessionDescription::offer(sdp.0).map_err(Error::WebRtc)?;
+                peer_connection
+                    .set_remote_description(offer)
+                    .await
+                    .map_err(Error::WebRtc)?;
+
+                let answer = peer_connection
+                    .create_answer(None)
+                    .await
+                    .map_err(Error::WebRtc)?;
+                let answer_sdp = answer.sdp.clone();
+                peer_connection
+                    .set_local_description(answer)
+                    .await
+                    .map_err(Error::WebRtc)?;
+                websocket_sender
+                    .lock()
+                    .await
+                    .send(ClientReceiverMessage::Answer(SessionDescription(
+                        answer_sdp,
+                    )))
+                    .await?;
+
+                let icecandidates: Vec<_> = take(&mut *delayed_icecandidates.lock().await);
+                for candidate in icecandidates {
+                    crate::add_remote_icecandidate(
+                        &peer_connection,
+                        candidate,
+                        &delayed_icecandidates,
+                    )
+                    .await?;
+                }
+            }
+            ServerSenderMessage::IceCandidate(candidate) => {
+                crate::add_remote_icecandidate(&peer_connection, candidate, &delayed_icecandidates)
+                    .await?;
+            }
+            ServerSenderMessage::AllIceCandidatesSent => {}
+            ServerSenderMessage::Error { code, reason } => {
+                return Err(Error::Server { code, reason })
+            }
+            ServerSenderMessage::Bye => break,
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct ReceivedVideo {
+    // The SSRC and sequence number of the latest video packet.
+    last: Option<(u32, u16)>,
+    // The packets received per sequence number.
+    copies: HashMap<u16, usize>,
+}
+
+type SharedReceivedVideo = Arc<std::sync::Mutex<ReceivedVideo>>;
+
+fn count_rtp_packets(
+    peer_connection: &RTCPeerConnection,
+) -> (
+    watch::Receiver<usize>,
+    watch::Receiver<usize>,
+    SharedReceivedVideo,
+) {
+    use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+
+    let (video_sender, video_receiver) = watch::channel(0);
+    let (audio_sender, audio_receiver) = watch::channel(0);
+    let received_video = SharedReceivedVideo::default();
+    let on_track_received_video = Arc::clone(&received_video);
+    let video_sender = Arc::new(video_sender);
+    let audio_sender = Arc::new(audio_sender);
+    peer_connection.on_track(Box::new(move |track, _| {
+        if let Some(track) = track {
+            let (counter, received_video) = match track.kind() {
+                RTPCodecType::Video => (
+                    Arc::clone(&video_sender),
+                    Some(Arc::clone(&on_track_received_video)),
+                ),
+                RTPCodecType::Audio => (Arc::clone(&audio_sender), None),
+                RTPCodecType::Unspecified => return Box::pin(async {}),
+            };
+            let _join_handle: JoinHandle<()> = tokio::spawn(async move {
+                while let Ok((packet, _)) = track.read_rtp().await {
+                    if let Some(received_video) = &received_video {
+                        let sequence_number = packet.header.sequence_number;
+                        let mut received_video = received_video.lock().unwrap();
+                        received_video.last = Some((track.ssrc(), sequence_number));
+                        *received_video.copies.entry(sequence_number).or_default() += 1;
+                    }
+                    let packets = *counter.borrow() + 1;
+                    let _: Result<(), _> = counter.send(packets);
+                }
+            });
+        }
+        Box::pin(async {})
+    }));
+    (video_receiver, audio_receiver, received_video)
+}
+
+fn collect_texts(peer_connection: &RTCPeerConnection) -> mpsc::UnboundedReceiver<String> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    peer_connection.on_data_channel(Box::new(move |data_channel| {
+        let sender = sender.clone();
+        Box::pin(async move {
+            data_channel.on_message(Box::new(move |message| {
+                // The server forwards data channel messages as binary.
+                let text = String::from_utf8_lossy(&message.data).into_owned();
+                let _: Result<(), _> = sender.send(text);
+                Box::pin(async {})
+            }));
+        })
+    }));
+    receiver
+}
+
+fn send_local_icecandidates(
+    peer_connection: &RTCPeerConnection,
+    websocket_sender: &Arc<Mutex<WebSocketSender<ClientReceiverMessage>>>,
+) {
+    let websocket_sender = Arc::clone(websocket_sender);
+    peer_connection.on_ice_candidate(Box::new(move |candidate| {
+        let websocket_sender = Arc::clone(&websocket_sender);
+        Box::pin(async move {
+            let message = match candidate {
+                Some(candidate) => match crate::to_icecandidate(&candidate) {
+                    Ok(candidate) => ClientReceiverMessage::IceCandidate(candidate),
+                    Err(err) => {
+                        log::error!("receiver: {}", err);
+                        return;
+                    }
+                },
+                None => ClientReceiverMessage::AllIceCandidatesSent,
+            };
+            if let Err(err) = websocket_sender.lock().await.send(message).await {
+                log::error!("receiver: {}", err);
+            }
+        })
+    }));
+}
@@ -0,0 +1,479 @@
+use core::fmt;
+use core::time::Duration;
+use std::sync::Arc;
+
+use protocol::{ClientSenderMessage, ServerReceiverMessage};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+
+use crate::{Error, WebSocketReceiver, WebSocketSender};
+
+// Synthetic payloads are small enough to fit into a single RTP packet.
+const VIDEO_PAYLOAD_SIZE: usize = 500;
+const AUDIO_PAYLOAD_SIZE: usize = 100;
+const VIDEO_CLOCK_RATE: u32 = 90000;
+// One keyframe per second at one frame every 20 ms.
+const KEYFRAME_INTERVAL: u16 = 50;
+const AUDIO_CLOCK_RATE: u32 = 48000;
+
+// Publishes synthetic media and text into a room, like a browser sender.
+pub struct Sender {
+    peer_connection: Arc<RTCPeerConnection>,
+    websocket_sender: Arc<Mutex<WebSocketSender<ClientSenderMessage>>>,
+    data_channel: Arc<RTCDataChannel>,
+    video_track: Arc<TrackLocalStaticRTP>,
+    audio_track: Arc<TrackLocalStaticRTP>,
+    video_ssrc: u32,
+    video: Mutex<RtpGenerator>,
+    // The keyframe requests answered with a keyframe so far.
+    keyframe_requests_answered: Mutex<usize>,
+    audio: Mutex<RtpGenerator>,
+    state: watch::Receiver<RTCPeerConnectionState>,
+    data_channel_open: watch::Receiver<bool>,
+    feedback: watch::Receiver<Feedback>,
+    // Taken by the first video packet actually sent, see `read_feedback`.
+    video_rtcp: Mutex<Option<(Arc<RTCRtpSender>, watch::Sender<Feedback>)>>,
+    // Started by the first video packet sent, aborted when closing.
+    rtcp_thread: Mutex<Option<JoinHandle<()>>>,
+    signaling: Mutex<Option<JoinHandle<Result<(), Error>>>>,
+}
+
+impl Sender {
+    // The address is e.g. `ws://localhost:9010/ws`.
+    pub async fn connect(addr: &str, room: &str) -> Result<Self, Error> {
+        use protocol::{ClientMessage, RoomName, SessionDescription, CAPABILITY_ROOMS};
+        use webrtc::api::media_engine::{MIME_TYPE_OPUS, MIME_TYPE_VP8};
+
+        let (websocket_sender, websocket_receiver, ice_servers) = crate::connect(
+            addr,
+            &[CAPABILITY_ROOMS],
+            ClientMessage::StartReceiver(RoomName(room.to_owned())),
+        )
+        .await?;
+        let websocket_sender = Arc::new(Mutex::new(websocket_sender));
+
+        let peer_connection = Arc::new(crate::new_peer_connection(&ice_servers).await?);
+        let data_channel = peer_connection
+            .create_data_channel("data", None)
+            .await
+            .map_err(Error::WebRtc)?;
+        let (video_track, video_rtp_sender) =
+            add_track(&peer_connection, MIME_TYPE_VP8, "video").await?;
+        let (audio_track, _) = add_track(&peer_connection, MIME_TYPE_OPUS, "audio").await?;
+        let video_ssrc = video_rtp_sender.get_parameters().await.encodings[0].ssrc;
+        let (feedback_sender, feedback) = watch::channel(Feedback::default());
+
+        let state = crate::watch_state(&peer_connection);
+        let data_channel_open = watch_data_channel_open(&data_channel);
+        send_local_icecandidates(&peer_connection, &websocket_sender);
+
+        let offer = peer_connection
+            .create_offer(None)
+            .await
+            .map_err(Error::WebRtc)?;
+        let offer_sdp = offer.sdp.clone();
+        peer_connection
+            .set_local_description(offer)
+            .await
+            .map_err(Error::WebRtc)?;
+        websocket_sender
+            .lock()
+            .await
+            .send(ClientSenderMessage::Offer(SessionDescription(offer_sdp)))
+            .await?;
+
+        let signaling = tokio::spawn(signaling(Arc::clone(&peer_connection), websocket_receiver));
+
+        Ok(Self {
+            peer_connection,
+            websocket_sender,
+            data_channel,
+            video_track,
+            audio_track,
+            video_ssrc,
+            video: Mutex::new(RtpGenerator::new(VIDEO_CLOCK_RATE, VIDEO_PAYLOAD_SIZE)),
+            keyframe_requests_answered: Mutex::new(0),
+            audio: Mutex::new(RtpGenerator::new(AUDIO_CLOCK_RATE, AUDIO_PAYLOAD_SIZE)),
+            state,
+            data_channel_open,
+            feedback,
+            video_rtcp: Mutex::new(Some((video_rtp_sender, feedback_sender))),
+            rtcp_thread: Mutex::new(None),
+            signaling: Mutex::new(Some(signaling)),
+        })
+    }
+
+    // Waits until the peer connection and the data channel are open.
+    pub async fn wait_connected(&self, timeout: Duration) -> Result<(), Error> {
+        crate::wait_for(
+            self.state.clone(),
+            |state| *state == RTCPeerConnectionState::Connected,
+            timeout,
+            "connection",
+        )
+        .await?;
+        crate::wait_for(
+            self.data_channel_open.clone(),
+            |open| *open,
+            timeout,
+            "data channel",
+        )
+        .await
+    }
+
+    pub async fn send_text(&self, text: &str) -> Result<(), Error> {
+        let _: usize = self
+            .data_channel
+            .send_text(text.to_owned())
+            .await
+            .map_err(Error::WebRtc)?;
+        Ok(())
+    }
+
+    // Sends a synthetic VP8 frame in a single RTP packet. Like a browser, it sends a keyframe
+    // periodically and after new keyframe requests, the other frames are delta frames.
+    pub async fn send_video(&self) -> Result<(), Error> {
+        use webrtc::track::track_local::TrackLocalWriter;
+
+        let packet = {
+            let mut video = self.video.lock().await;
+            let mut keyframe_requests_answered = self.keyframe_requests_answered.lock().await;
+            let keyframe_requests = self.keyframe_requests();
+            let keyframe = video.sequence_number % KEYFRAME_INTERVAL == 0
+                || keyframe_requests > *keyframe_requests_answered;
+            *keyframe_requests_answered = keyframe_requests;
+            // VP8 payload descriptor with the start of partition bit, then the frame tag
+            // whose lowest bit is zero for keyframes.
+            video.next(&[0x10, if keyframe { 0x00 } else { 0x01 }])?
+        };
+        let written = self
+            .video_track
+            .write_rtp(&packet)
+            .await
+            .map_err(Error::WebRtc)?;
+        if written > 0 {
+            if let Some((rtp_sender, feedback)) = self.video_rtcp.lock().await.take() {
+                let thread = read_feedback(rtp_sender, feedback);
+                *self.rtcp_thread.lock().await = Some(thread);
+            }
+        }
+        Ok(())
+    }
+
+    // Sends a synthetic 20 ms Opus packet.
+    pub async fn send_audio(&self) -> Result<(), Error> {
+        use webrtc::track::track_local::TrackLocalWriter;
+
+        // Opus TOC byte of a 20 ms CELT frame.
+        let packet = self.audio.lock().await.next(&[0xf8])?;
+        let _: usize = self
+            .audio_track
+            .write_rtp(&packet)
+            .await
+            .map_err(Error::WebRtc)?;
+        Ok(())
+    }
+
+    // Waits until the server has requested `count` keyframes in total.
+    pub async fn wait_for_keyframe_requests(
+        &self,
+        count: usize,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        crate::wait_for(
+            self.feedback.clone(),
+            |feedback| feedback.keyframe_requests >= count,
+            timeout,
+            "keyframe requests",
+        )
+        .await
+    }
+
+    // The number of PLI and FIR packets received so far.
+    pub fn keyframe_requests(&self) -> usize {
+        self.feedback.borrow().keyframe_requests
+    }
+
+    // The SSRC of the video packets sent, the server has to address its feedback to it.
+    pub fn video_ssrc(&self) -> u32 {
+        self.video_ssrc
+    }
+
+    // Waits until the server has NACKed the video packet.
+    pub async fn wait_for_nack(
+        &self,
+        sequence_number: u16,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        crate::wait_for(
+            self.feedback.clone(),
+            |feedback| {
+                feedback
+                    .nacks
+                    .iter()
+                    .any(|&(_, nacked)| nacked == sequence_number)
+            },
+            timeout,
+            "NACK",
+        )
+        .await
+    }
+
+    // The media SSRCs of the NACKs received so far for the video packet.
+    pub fn nack_ssrcs(&self, sequence_number: u16) -> Vec<u32> {
+        self.feedback
+            .borrow()
+            .nacks
+            .iter()
+            .filter(|&&(_, nacked)| nacked == sequence_number)
+            .map(|&(ssrc, _)| ssrc)
+            .collect()
+    }
+
+    // Waits until the latest REMB of the server announces the bitrate.
+    pub async fn wait_for_bitrate(&self, bitrate: u64, timeout: Duration) -> Result<(), Error> {
+        crate::wait_for(
+            self.feedback.clone(),
+            |feedback| feedback.bitrate == Some(bitrate),
+            timeout,
+            "REMB",
+        )
+        .await
+    }
+
+    // The SSRCs of the latest REMB.
+    pub fn remb_ssrcs(&self) -> Vec<u32> {
+        self.feedback.borrow().remb_ssrcs.clone()
+    }
+
+    // Fails on a server error.
+    pub async fn wait_closed(&self, timeout: Duration) -> Result<(), Error> {
+        crate::wait_signaling(&self.signaling, timeout).await
+    }
+
+    // Sends `Bye` and closes the session.
+    pub async fn close(&self) {
+        // The candidate handler takes the lock too, and the ICE agent waits for the handler
+        // when closing, so the lock is released before the peer connection is closed.
+        {
+            let mut websocket_sender = self.websocket_sender.lock().await;
+            let _: Result<(), Error> = websocket_sender.send(ClientSenderMessage::Bye).await;
+            websocket_sender.close().await;
+        }
+        if let Some(thread) = self.rtcp_thread.lock().await.take() {
+            thread.abort();
+        }
+        let _: Result<(), _> = self.peer_connection.close().await;
+    }
+}
+
+impl fmt::Debug for Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("state", &*self.state.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+async fn signaling(
+    peer_connection: Arc<RTCPeerConnection>,
+    mut websocket_receiver: WebSocketReceiver<ServerReceiverMessage>,
+) -> Result<(), Error> {
+    use core::mem::take;
+    use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+    let delayed_icecandidates = Mutex::new(Vec::new());
+    while let Some(message) = websocket_receiver.recv().await? {
+        match message {
+            ServerReceiverMessage::Answer(sdp) => {
+                let answer = RTCSessionDescription::answer(sdp.0).map_err(Error::WebRtc)?;
+                peer_connection
+                    .set_remote_description(answer)
+                    .await
+                    .map_err(Error::WebRtc)?;
+
+                let icecandidates: Vec<_> = take(&mut *delayed_icecandidates.lock().await);
+                for candidate in icecandidates {
+                    crate::add_remote_icecandidate(
+                        &peer_connection,
+                        candidate,
+                        &delayed_icecandidates,
+                    )
+                    .await?;
+                }
+            }
+            ServerReceiverMessage::IceCandidate(candidate) => {
+                crate::add_remote_icecandidate(&peer_connection, candidate, &delayed_icecandidates)
+                    .await?;
+            }
+            ServerReceiverMessage::AllIceCandidatesSent => {}
+            ServerReceiverMessage::Error { code, reason } => {
+                return Err(Error::Server { code, reason })
+            }
+            ServerReceiverMessage::Bye => break,
+        }
+    }
+    Ok(())
+}
+
+async fn add_track(
+    peer_connection: &RTCPeerConnection,
+    mime_type: &str,
+    id: &str,
+) -> Result<(Arc<TrackLocalStaticRTP>, Arc<RTCRtpSender>), Error> {
+    use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+    use webrtc::track::track_local::TrackLocal;
+
+    let track = Arc::new(TrackLocalStaticRTP::new(
+        RTCRtpCodecCapability {
+            mime_type: mime_type.to_owned(),
+            ..Default::default()
+        },
+        id.to_owned(),
+        id.to_owned(),
+    ));
+    #[allow(trivial_casts)] // false positive
+    let track_ref = Arc::clone(&track) as Arc<dyn TrackLocal + Sync + Send>;
+    let rtp_sender = peer_connection
+        .add_track(track_ref)
+        .await
+        .map_err(Error::WebRtc)?;
+    Ok((track, rtp_sender))
+}
+
+// The RTCP feedback received from the server for the video track.
+#[derive(Clone, Debug, Default)]
+struct Feedback {
+    keyframe_requests: usize,
+    // The media SSRC and the sequence number of every NACKed packet.
+    nacks: Vec<(u32, u16)>,
+    bitrate: Option<u64>,
+    remb_ssrcs: Vec<u32>,
+}
+
+// Reads the RTCP of the track until the peer connection is closed.
+fn read_feedback(
+    rtp_sender: Arc<RTCRtpSender>,
+    feedback: watch::Sender<Feedback>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Ok((packets, _)) = rtp_sender.read_rtcp().await {
+            let mut next = feedback.borrow().clone();
+            for packet in &packets {
+                add_feedback(&mut next, &**packet);
+            }
+            let _: Result<(), _> = feedback.send(next);
+        }
+    })
+}
+
+fn add_feedback(feedback: &mut Feedback, packet: &dyn rtcp::packet::Packet) {
+    use rtcp::compound_packet::CompoundPacket;
+    use rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+    use rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+    use rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+    use rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+
+    let packet = packet.as_any();
+    if let Some(compound) = packet.downcast_ref::<CompoundPacket>() {
+        for packet in &compound.0 {
+            add_feedback(feedback, &**packet);
+        }
+    } else if packet.is::<PictureLossIndication>() || packet.is::<FullIntraRequest>() {
+        feedback.keyframe_requests += 1;
+    } else if let Some(nack) = packet.downcast_ref::<TransportLayerNack>() {
+        for pair in &nack.nacks {
+            let packets = pair.packet_list().into_iter();
+            feedback
+                .nacks
+                .extend(packets.map(|sequence_number| (nack.media_ssrc, sequence_number)));
+        }
+    } else if let Some(remb) = packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+        feedback.bitrate = Some(remb.bitrate as u64);
+        feedback.remb_ssrcs = remb.ssrcs.clone();
+    }
+}
+
+fn watch_data_channel_open(data_channel: &RTCDataChannel) -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    data_channel.on_open(Box::new(move || {
+        let _: Result<(), _> = sender.send(true);
+        Box::pin(async {})
+    }));
+    receiver
+}
+
+fn send_local_icecandidates(
+    peer_connection: &RTCPeerConnection,
+    websocket_sender: &Arc<Mutex<WebSocketSender<ClientSenderMessage>>>,
+) {
+    let websocket_sender = Arc::clone(websocket_sender);
+    peer_connection.on_ice_candidate(Box::new(move |candidate| {
+        let websocket_sender = Arc::clone(&websocket_sender);
+        Box::pin(async move {
+            let message = match candidate {
+                Some(candidate) => match crate::to_icecandidate(&candidate) {
+                    Ok(candidate) => ClientSenderMessage::IceCandidate(candidate),
+                    Err(err) => {
+                        log::error!("sender: {}", err);
+                        return;
+                    }
+                },
+                None => ClientSenderMessage::AllIceCandidatesSent,
+            };
+            if let Err(err) = websocket_sender.lock().await.send(message).await {
+                log::error!("sender: {}", err);
+            }
+        })
+    }));
+}
+
+// Produces RTP packets with a fixed payload header followed by zero padding.
+#[derive(Debug)]
+struct RtpGenerator {
+    clock_rate: u32,
+    payload_size: usize,
+    sequence_number: u16,
+    timestamp: u32,
+}
+
+impl RtpGenerator {
+    fn new(clock_rate: u32, payload_size: usize) -> Self {
+        Self {
+            clock_rate,
+            payload_size,
+            sequence_number: 0,
+            timestamp: 0,
+        }
+    }
+
+    fn next(&mut self, header: &[u8]) -> Result<rtp::packet::Packet, Error> {
+        use bytes::{BufMut, BytesMut};
+        use rtp::header::Header;
+        use rtp::packet::Packet;
+
+        let mut payload = BytesMut::with_capacity(self.payload_size);
+        payload.put_slice(header);
+        payload.resize(self.payload_size.max(header.len()), 0);
+
+        let packet = Packet {
+            header: Header {
+                version: 2,
+                marker: true,
+                sequence_number: self.sequence_number,
+                timestamp: self.timestamp,
+                ..Default::default()
+            },
+            payload: payload.freeze(),
+        };
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        // Every packet carries 20 ms of media.
+        self.timestamp = self.timestamp.wrapping_add(self.clock_rate / 50);
+        Ok(packet)
+    }
+}
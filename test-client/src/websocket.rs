@@ -0,0 +1,139 @@
+use core::marker::PhantomData;
+
+use futures::stream::{SplitSink, SplitStream};
+use protocol::{ClientMessage, Codec, IceServer, ServerMessage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::Error;
+
+type Stream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug)]
+pub struct WebSocketSender<T> {
+    sender: SplitSink<Stream, Message>,
+    codec: Codec,
+    _phantom: PhantomData<T>,
+}
+
+#[derive(Debug)]
+pub struct WebSocketReceiver<T> {
+    receiver: SplitStream<Stream>,
+    codec: Codec,
+    _phantom: PhantomData<T>,
+}
+
+// Connects to the server, performs the handshake and sends the start message,
+// returns the ICE servers received in the handshake.
+pub async fn connect<T, U>(
+    addr: &str,
+    capabilities: &[&str],
+    start: ClientMessage,
+) -> Result<(WebSocketSender<T>, WebSocketReceiver<U>, Vec<IceServer>), Error>
+where
+    T: Serialize,
+    U: DeserializeOwned,
+{
+    use futures::StreamExt;
+    use protocol::PROTOCOL_VERSION;
+    use tokio_tungstenite::connect_async;
+
+    let (websocket, _) = connect_async(addr).await?;
+    let (sender, receiver) = websocket.split();
+    let codec = Codec::default();
+
+    let mut sender = WebSocketSender::<ClientMessage>::new(sender, codec);
+    let mut receiver = WebSocketReceiver::<ServerMessage>::new(receiver, codec);
+
+    sender
+        .send(ClientMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: capabilities
+                .iter()
+                .map(|&capability| capability.to_owned())
+                .collect(),
+        })
+        .await?;
+    let ice_servers = match receiver.recv().await? {
+        Some(ServerMessage::Hello { ice_servers, .. }) => ice_servers,
+        Some(ServerMessage::Error { code, reason }) => return Err(Error::Server { code, reason }),
+        None => return Err(Error::Closed),
+    };
+    sender.send(start).await?;
+
+    Ok((sender.cast(), receiver.cast(), ice_servers))
+}
+
+impl<T: Serialize> WebSocketSender<T> {
+    fn new(sender: SplitSink<Stream, Message>, codec: Codec) -> Self {
+        Self {
+            sender,
+            codec,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub async fn send(&mut self, message: T) -> Result<(), Error> {
+        use futures::SinkExt;
+        use protocol::Frame;
+
+        let message = match self.codec.encode(&message)? {
+            Frame::Binary(data) => Message::Binary(data),
+            Frame::Text(text) => Message::Text(text),
+        };
+        Ok(self.sender.send(message).await?)
+    }
+
+    pub async fn close(&mut self) {
+        use futures::SinkExt;
+
+        // The server may have closed the connection already.
+        let _: Result<(), _> = self.sender.close().await;
+    }
+
+    fn cast<U>(self) -> WebSocketSender<U> {
+        WebSocketSender {
+            sender: self.sender,
+            codec: self.codec,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> WebSocketReceiver<T> {
+    fn new(receiver: SplitStream<Stream>, codec: Codec) -> Self {
+        Self {
+            receiver,
+            codec,
+            _phantom: PhantomData,
+        }
+    }
+
+    // Returns `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Result<Option<T>, Error> {
+        use futures::StreamExt;
+        use protocol::Frame;
+
+        while let Some(message) = self.receiver.next().await {
+            let frame = match message? {
+                Message::Binary(data) => Frame::Binary(data),
+                Message::Text(text) => Frame::Text(text),
+                Message::Close(_) => return Ok(None),
+                Message::Ping(_) | Message::Pong(_) => continue,
+            };
+            return Ok(Some(self.codec.decode(&frame)?));
+        }
+        Ok(None)
+    }
+
+    fn cast<U>(self) -> WebSocketReceiver<U> {
+        WebSocketReceiver {
+            receiver: self.receiver,
+            codec: self.codec,
+            _phantom: PhantomData,
+        }
+    }
+}
@@ -8,7 +8,7 @@ SERVER_PORT="9010"
 (
     cd client
     mkdir -p target
-    echo $"window.server_address = \"ws://$SERVER_ADDRESS:$SERVER_PORT\";" > target/params.js
+    echo $"window.server_address = \"ws://$SERVER_ADDRESS:$SERVER_PORT/ws\";" > target/params.js
     trunk serve --release -d dist -w . ../protocol
 ) &
 CLIENT_PID=$!